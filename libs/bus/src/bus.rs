@@ -1,86 +1,108 @@
-use crate::{BusConnectable, MutRef};
-use std::{cell::RefCell, rc::Rc, vec::Vec};
+use crate::MemoryMap;
 
-impl Into<Bus> for Vec<u8> {
-    fn into(self) -> Bus {
-        Bus::new(vec![Rc::new(RefCell::new(self))])
-    }
-}
+/// The number of address bits inside a page, which makes pages of 256 bytes
+pub const PAGE_BITS: usize = 8;
+
+/// The number of addresses in a page
+pub const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+const PAGES: usize = 0x1_0000 >> PAGE_BITS;
 
-/// Represent a data bus
+/// The page table of a memory bus
 ///
-/// One one piece of data may be on the bus at one time
-pub struct Bus {
-    connections: Vec<MutRef<dyn BusConnectable>>,
+/// Every page of the address space is mapped to the device that answers for it, along with
+/// the offset of the page's first address inside the device, so finding where an address
+/// goes is an index and an add.  Like the `IoBus`, the bus does not hold the devices, it
+/// gives back the `D` a page is mapped to and the owner of the devices reads or writes the
+/// right one.
+///
+/// The table is filled from the ranges given to the `BusBuilder`, or page by page with
+/// `map`, which the owner has to do again whenever a device changes what it answers for,
+/// e.g. when a mapper switches banks.  A device has to answer for whole pages.
+#[derive(Clone, Debug)]
+pub struct Bus<D> {
+    pages: Vec<Option<(D, usize)>>,
 }
 
-#[allow(dead_code)]
-impl Bus {
-    pub fn builder() -> BusBuilder {
+impl<D: Copy> Bus<D> {
+    pub fn builder() -> BusBuilder<D> {
         BusBuilder::new()
     }
 
-    pub fn new(connections: Vec<MutRef<dyn BusConnectable>>) -> Bus {
-        Bus { connections }
+    /// A bus with nothing mapped
+    pub fn new() -> Bus<D> {
+        Bus {
+            pages: vec![None; PAGES],
+        }
+    }
+
+    /// The first address of every page
+    pub fn pages() -> impl Iterator<Item = u16> {
+        (0..PAGES).map(|page| (page << PAGE_BITS) as u16)
     }
 
-    pub fn cpu_write(&mut self, addr: u16, data: u8) -> bool {
-        self.connections
-            .iter_mut()
-            .find(|conn| conn.borrow().accept(addr))
-            .map(|conn| conn.borrow_mut().cpu_write(addr, data))
-            .is_some()
+    /// Map the page that holds `addr` to a device, with the first address of the page at
+    /// `offset` inside it, or leave the page unmapped with `None`
+    pub fn map(&mut self, addr: u16, page: Option<(D, usize)>) {
+        self.pages[addr as usize >> PAGE_BITS] = page;
     }
-    pub fn cpu_read(&self, addr: u16) -> Option<u8> {
-        self.connections
-            .iter()
-            .find(|&conn| conn.borrow().accept(addr))
-            .map(|conn| conn.borrow_mut().cpu_read(addr))
+
+    /// The device that answers for the address, and the offset of the address inside it
+    #[inline]
+    pub fn device(&self, addr: u16) -> Option<(D, usize)> {
+        self.pages[addr as usize >> PAGE_BITS]
+            .map(|(device, offset)| (device, offset + (addr as usize & (PAGE_SIZE - 1))))
     }
 }
 
-impl Default for Bus {
-    fn default() -> Bus {
-        Bus::new(vec![Rc::new(RefCell::new(vec![]))])
+/// Nothing is mapped
+impl<D: Copy> Default for Bus<D> {
+    fn default() -> Bus<D> {
+        Bus::new()
     }
 }
 
-pub struct BusBuilder {
-    connections: Vec<MutRef<dyn BusConnectable>>,
+pub struct BusBuilder<D> {
+    bus: Bus<D>,
 }
 
-impl BusBuilder {
-    pub fn new() -> BusBuilder {
-        BusBuilder {
-            connections: vec![],
-        }
+impl<D: Copy> BusBuilder<D> {
+    pub fn new() -> BusBuilder<D> {
+        BusBuilder { bus: Bus::new() }
     }
 
-    pub fn add<T>(mut self, connection: T) -> Self
-    where
-        T: 'static + BusConnectable,
-    {
-        self.connections.push(Rc::new(RefCell::new(connection)));
-        self
+    /// Map the addresses to a device, from its first address on, replacing the devices
+    /// that were mapped to them before
+    ///
+    /// Mapping the same device again to other addresses mirrors it.
+    pub fn add(self, addrs: impl Into<MemoryMap>, device: D) -> Self {
+        self.add_offset(addrs, device, 0)
     }
 
-    pub fn add_box<T>(mut self, connection: Box<T>) -> Self
-    where
-        T: 'static + BusConnectable,
-    {
-        self.connections.push(Rc::new(RefCell::new(*connection)));
+    /// Map the addresses to a device, with the first of them at `offset` inside it
+    pub fn add_offset(mut self, addrs: impl Into<MemoryMap>, device: D, offset: usize) -> Self {
+        let addrs = addrs.into();
+        debug_assert!(
+            (addrs.min as usize).is_multiple_of(PAGE_SIZE)
+                && (addrs.max as usize + 1).is_multiple_of(PAGE_SIZE),
+            "a device has to answer for whole pages"
+        );
+
+        for addr in (addrs.min..=addrs.max).step_by(PAGE_SIZE) {
+            let page = offset + (addr - addrs.min) as usize;
+            self.bus.map(addr, Some((device, page)));
+        }
         self
     }
 
-    pub fn add_ref(mut self, connection: &MutRef<dyn BusConnectable>) -> Self {
-        self.connections.push(Rc::clone(connection));
-        self
+    pub fn build(self) -> Bus<D> {
+        self.bus
     }
+}
 
-    pub fn build(self) -> Bus {
-        Bus {
-            connections: self.connections,
-        }
+impl<D: Copy> Default for BusBuilder<D> {
+    fn default() -> BusBuilder<D> {
+        BusBuilder::new()
     }
 }
 
@@ -88,13 +110,51 @@ impl BusBuilder {
 mod tests {
     use super::*;
 
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    enum Device {
+        Rom,
+        Ram,
+    }
+
     #[test]
     fn test_bus_builder() {
         let bus = Bus::builder()
-            .add(vec![0x01, 0x02])
-            .add(vec![0x03, 0x04])
+            .add_offset(0x0000..0x4000, Device::Rom, 0x4000)
+            .add(0xC000..0xC400, Device::Ram)
+            .add(0xFC00..=0xFFFF, Device::Ram)
             .build();
 
-        assert_eq!(bus.connections.len(), 2);
+        assert_eq!(Some((Device::Rom, 0x4000)), bus.device(0x0000));
+        assert_eq!(Some((Device::Rom, 0x7FFF)), bus.device(0x3FFF));
+        assert_eq!(None, bus.device(0x4000));
+        assert_eq!(Some((Device::Ram, 0x3FF)), bus.device(0xC3FF));
+        assert_eq!(None, bus.device(0xC400));
+        assert_eq!(Some((Device::Ram, 0x010)), bus.device(0xFC10));
+    }
+
+    #[test]
+    fn test_page_table() {
+        let mut bus = Bus::new();
+        for addr in Bus::<Device>::pages() {
+            let page = match addr {
+                0x0000..=0x3FFF => Some((Device::Rom, addr as usize + 0x4000)),
+                // 1 KB of ram, mirrored
+                0xC000..=0xFFFF => Some((Device::Ram, addr as usize % 0x400)),
+                _ => None,
+            };
+            bus.map(addr, page);
+        }
+
+        assert_eq!(256, Bus::<Device>::pages().count());
+        assert_eq!(Some((Device::Rom, 0x4000)), bus.device(0x0000));
+        assert_eq!(Some((Device::Rom, 0x7FFF)), bus.device(0x3FFF));
+        assert_eq!(None, bus.device(0x4000));
+        assert_eq!(Some((Device::Ram, 0x3FF)), bus.device(0xC3FF));
+        assert_eq!(Some((Device::Ram, 0x010)), bus.device(0xFC10));
+
+        // the table only changes when it is mapped again
+        bus.map(0x0000, Some((Device::Rom, 0)));
+        assert_eq!(Some((Device::Rom, 0xFF)), bus.device(0x00FF));
+        assert_eq!(Some((Device::Rom, 0x4100)), bus.device(0x0100));
     }
 }
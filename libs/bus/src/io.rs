@@ -0,0 +1,95 @@
+/// The number of ports, only the low byte of the address selects one
+const PORTS: usize = 0x100;
+
+/// Which device answers for every I/O port of the cpu
+///
+/// Only the low byte of the port address is decoded, and every port is looked up in a
+/// table, so a device answers for all the ports it is mapped to.  Devices that have more
+/// than one register tell them apart by the bits of the port they decode, e.g. the SG-1000
+/// VDP answers on 0x80-0xBF and uses A0 to pick its data or control register.
+///
+/// The bus does not hold the devices, it gives back the `D` a port is mapped to (usually
+/// an enum of the devices of a machine) and the owner of the devices calls the right one.
+/// A port nothing is mapped to gives `None`, which is an open bus on the hardware.
+#[derive(Clone, Debug)]
+pub struct IoBus<D> {
+    ports: Vec<Option<D>>,
+}
+
+impl<D: Copy> IoBus<D> {
+    pub fn builder() -> IoBusBuilder<D> {
+        IoBusBuilder::new()
+    }
+
+    /// The device mapped to the port
+    #[inline]
+    pub fn device(&self, port: u16) -> Option<D> {
+        self.ports[port as usize % PORTS]
+    }
+}
+
+/// No devices, every port is open
+impl<D: Copy> Default for IoBus<D> {
+    fn default() -> IoBus<D> {
+        IoBus::builder().build()
+    }
+}
+
+pub struct IoBusBuilder<D> {
+    ports: Vec<Option<D>>,
+}
+
+impl<D: Copy> IoBusBuilder<D> {
+    pub fn new() -> IoBusBuilder<D> {
+        IoBusBuilder {
+            ports: vec![None; PORTS],
+        }
+    }
+
+    /// Map a device to the ports, replacing the devices that were mapped to them before
+    pub fn add(mut self, ports: impl IntoIterator<Item = u8>, device: D) -> Self {
+        for port in ports {
+            self.ports[port as usize] = Some(device);
+        }
+        self
+    }
+
+    pub fn build(self) -> IoBus<D> {
+        IoBus { ports: self.ports }
+    }
+}
+
+impl<D: Copy> Default for IoBusBuilder<D> {
+    fn default() -> IoBusBuilder<D> {
+        IoBusBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    enum Device {
+        All,
+        Even,
+        Vdp,
+    }
+
+    #[test]
+    fn test_decode() {
+        let io = IoBus::builder()
+            .add(0x00..=0xFF, Device::All)
+            .add((0x00..=0x3F).step_by(2), Device::Even)
+            .add(0x80..=0xBF, Device::Vdp)
+            .build();
+
+        // the high byte of the address is ignored
+        assert_eq!(Some(Device::Vdp), io.device(0x12BF));
+        assert_eq!(Some(Device::Vdp), io.device(0x0080));
+        assert_eq!(Some(Device::All), io.device(0x00C0));
+        assert_eq!(Some(Device::Even), io.device(0x3E));
+        assert_eq!(Some(Device::All), io.device(0x3F));
+        assert_eq!(None, IoBus::<Device>::default().device(0xBE));
+    }
+}
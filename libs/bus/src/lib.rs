@@ -1,9 +1,10 @@
 use std::ops::{Range, RangeInclusive};
 
 pub mod bus;
+pub mod io;
+pub mod log;
 pub mod ram;
-
-pub type MutRef<T> = std::rc::Rc<std::cell::RefCell<T>>;
+pub mod state;
 
 /// Represents an object connected to a bus
 ///
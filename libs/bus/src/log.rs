@@ -0,0 +1,260 @@
+//! Logging for the parts of the emulator, which can be switched on per part at runtime
+//!
+//! Messages are written with the `log!` macro, e.g.
+//! ```
+//! bus::log!(Vdp, Debug, "register {} = 0x{:02x}", 1, 0xE0);
+//! ```
+//! and only formatted when the level of their target lets them through.  The levels are
+//! set with a filter like `vdp=debug,io=trace`, see `set_filter`.
+//!
+//! By default the bus and the ports only log errors, as games read the open bus all the
+//! time and every other target logs warnings.
+use std::fmt;
+use std::io::{stderr, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+/// The part of the emulator a message is about
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    /// The instructions the cpu runs and the interrupts it takes
+    Cpu,
+    /// The registers and VRAM accesses of the video chip
+    Vdp,
+    /// The memory accesses of the cpu
+    Bus,
+    /// The port accesses of the cpu
+    Io,
+    /// The buttons of the joypads and the console
+    Input,
+}
+
+impl Target {
+    pub const ALL: [Target; 5] = [
+        Target::Cpu,
+        Target::Vdp,
+        Target::Bus,
+        Target::Io,
+        Target::Input,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Cpu => "cpu",
+            Target::Vdp => "vdp",
+            Target::Bus => "bus",
+            Target::Io => "io",
+            Target::Input => "input",
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Target, String> {
+        Target::ALL
+            .iter()
+            .copied()
+            .find(|target| target.name() == name.to_ascii_lowercase())
+            .ok_or_else(|| {
+                format!(
+                    "unknown log target '{}', expected cpu, vdp, bus, io or input",
+                    name
+                )
+            })
+    }
+}
+
+/// How much is logged, each level includes the ones before it
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+    Off,
+    /// Something the emulator can not do
+    Error,
+    /// Something a game should not do, e.g. writing to a register the VDP does not have
+    Warn,
+    Info,
+    /// Changes of state and accesses to the open bus, e.g. a register of the VDP being written
+    Debug,
+    /// Every single access, which is a lot
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 6] = [
+        Level::Off,
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Level, String> {
+        Level::ALL
+            .iter()
+            .copied()
+            .find(|level| level.name() == name.to_ascii_lowercase())
+            .ok_or_else(|| {
+                format!(
+                    "unknown log level '{}', expected off, error, warn, info, debug or trace",
+                    name
+                )
+            })
+    }
+}
+
+/// The level of every target, indexed like `Target::ALL`
+static LEVELS: [AtomicU8; 5] = [
+    AtomicU8::new(Level::Warn as u8),
+    AtomicU8::new(Level::Warn as u8),
+    AtomicU8::new(Level::Error as u8),
+    AtomicU8::new(Level::Error as u8),
+    AtomicU8::new(Level::Warn as u8),
+];
+
+/// Where the messages go, stderr if not set
+static OUTPUT: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+pub fn level(target: Target) -> Level {
+    Level::ALL[LEVELS[target as usize].load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_level(target: Target, level: Level) {
+    LEVELS[target as usize].store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages of `level` about `target` are written
+#[inline]
+pub fn enabled(target: Target, level: Level) -> bool {
+    level != Level::Off && level as u8 <= LEVELS[target as usize].load(Ordering::Relaxed)
+}
+
+/// Set the levels from a comma separated list of `target=level`, or just `level` for every
+/// target, e.g. `warn,vdp=debug,io=trace`
+///
+/// Nothing is changed if the filter is invalid.
+pub fn set_filter(filter: &str) -> Result<(), String> {
+    let mut levels = Target::ALL.map(level);
+    for item in filter
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        match item.find('=') {
+            Some(split) => {
+                let target: Target = item[..split].trim().parse()?;
+                levels[target as usize] = item[split + 1..].trim().parse()?;
+            }
+            None => levels = [item.parse()?; 5],
+        }
+    }
+
+    for (&target, &level) in Target::ALL.iter().zip(levels.iter()) {
+        set_level(target, level);
+    }
+    Ok(())
+}
+
+/// Send the messages somewhere else than stderr, or back to stderr with `None`
+pub fn set_output(output: Option<Box<dyn Write + Send>>) {
+    *OUTPUT.lock().unwrap() = output;
+}
+
+/// Write a message, whatever the level of its target.  Use `log!` instead.
+pub fn write(target: Target, level: Level, message: fmt::Arguments) {
+    let mut output = OUTPUT.lock().unwrap();
+    let line = format!("[{:<5} {:<5}] {}\n", target.name(), level.name(), message);
+    // there is nowhere to report a failed log message
+    let _ = match output.as_mut() {
+        Some(output) => output.write_all(line.as_bytes()),
+        None => stderr().write_all(line.as_bytes()),
+    };
+}
+
+/// Log a message about a `Target` at a `Level`, given by their names
+#[macro_export]
+macro_rules! log {
+    ($target:ident, $level:ident, $($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Target::$target, $crate::log::Level::$level) {
+            $crate::log::write(
+                $crate::log::Target::$target,
+                $crate::log::Level::$level,
+                format_args!($($arg)+),
+            );
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Collects the output to check it
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // the levels are global, so everything is checked in one test
+    #[test]
+    fn test_filter() {
+        // the open bus is too common to warn about
+        assert_eq!(Level::Error, level(Target::Bus));
+        assert_eq!(Level::Error, level(Target::Io));
+        assert_eq!(Level::Warn, level(Target::Vdp));
+
+        set_filter("off,vdp=debug, io = trace").unwrap();
+        assert_eq!(Level::Debug, level(Target::Vdp));
+        assert_eq!(Level::Trace, level(Target::Io));
+        assert_eq!(Level::Off, level(Target::Cpu));
+        assert!(enabled(Target::Vdp, Level::Warn));
+        assert!(!enabled(Target::Vdp, Level::Trace));
+        assert!(!enabled(Target::Cpu, Level::Error));
+        assert!(!enabled(Target::Io, Level::Off));
+
+        assert!(set_filter("vdp=loud").is_err());
+        assert!(set_filter("cpu=trace,gpu=debug").is_err());
+        assert_eq!(
+            Level::Off,
+            level(Target::Cpu),
+            "an invalid filter changes nothing"
+        );
+
+        let capture = Capture::default();
+        set_output(Some(Box::new(capture.clone())));
+        crate::log!(Vdp, Debug, "register {} = 0x{:02x}", 1, 0xE0);
+        crate::log!(Vdp, Trace, "not written");
+        set_output(None);
+        set_filter("warn").unwrap();
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert_eq!("[vdp   debug] register 1 = 0xe0\n", output);
+    }
+}
@@ -1,11 +1,47 @@
-use crate::{BusConnectable, MemoryMap, MutRef};
-use std::{cell::RefCell, rc::Rc};
+use crate::state::{take, take_u32, SaveState};
+use crate::{BusConnectable, MemoryMap};
 
 const MAX_SIZE: usize = 0x1_00_00;
 
+/// How the contents of a ram module are filled when it is built
+///
+/// Real hardware powers on with garbage in RAM.  `Random` can be used to flush out
+/// software that depends on uninitialized memory.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RamInit {
+    /// Every byte is 0
+    #[default]
+    Zero,
+    /// Every byte is set to the given value
+    Fill(u8),
+    /// Every byte is pseudo-random, generated from the given seed
+    Random(u64),
+}
+
+impl RamInit {
+    /// Fill the slice according to the init mode
+    pub fn apply(&self, data: &mut [u8]) {
+        match *self {
+            RamInit::Zero => data.iter_mut().for_each(|byte| *byte = 0),
+            RamInit::Fill(val) => data.iter_mut().for_each(|byte| *byte = val),
+            RamInit::Random(seed) => {
+                // xorshift64, the state can never be 0
+                let mut state = seed | 1;
+                for byte in data.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = (state >> 24) as u8;
+                }
+            }
+        }
+    }
+}
+
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct Ram {
-    data: MutRef<Vec<u8>>,
+    data: Vec<u8>,
     size: usize,
     memory_map: MemoryMap,
     mirrors: Vec<MemoryMap>,
@@ -16,7 +52,7 @@ impl Ram {
     pub fn new(size: usize, memory_map: MemoryMap) -> Ram {
         Ram {
             size,
-            data: Rc::new(RefCell::new(Vec::with_capacity(size))),
+            data: Vec::with_capacity(size),
             memory_map,
             mirrors: vec![],
             read_only: false,
@@ -31,35 +67,47 @@ impl Ram {
     ///     mirrors:    empty vec
     ///     data:       Vec of size with all values 0
     ///     read_only:  false
+    ///     init:       RamInit::Zero
     pub fn builder() -> RamBuilder {
         RamBuilder::new()
     }
 
-    /// Get the an Rc::clone of the data in the VRAM
-    pub fn vram(&self) -> MutRef<Vec<u8>> {
-        Rc::clone(&self.data)
-    }
-}
-
-impl BusConnectable for Ram {
-    fn accept(&self, addr: u16) -> bool {
-        if self.memory_map.contains(addr) {
-            return true;
-        }
-        self.mirrors.iter().find(|map| map.contains(addr)).is_some()
+    /// Refill the entire contents of the ram
+    pub fn init(&mut self, init: RamInit) {
+        init.apply(&mut self.data);
     }
 
-    fn cpu_read(&mut self, addr: u16) -> u8 {
+    /// Where the address is in the ram, through its memory map or one of its mirrors
+    pub fn offset(&self, addr: u16) -> Option<usize> {
         if self.memory_map.contains(addr) {
-            let index = addr - self.memory_map.min;
-            return self.data.borrow()[index as usize];
+            return Some((addr - self.memory_map.min) as usize);
         }
 
         self.mirrors
             .iter()
             .find(|map| map.contains(addr))
-            .map(|map| self.data.borrow()[(addr - map.min) as usize])
-            .unwrap()
+            .map(|map| (addr - map.min) as usize)
+    }
+
+    /// The contents, at the offsets given by `offset`
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The contents, at the offsets given by `offset`.  Writing them does not check
+    /// whether the ram is read only.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl BusConnectable for Ram {
+    fn accept(&self, addr: u16) -> bool {
+        self.offset(addr).is_some()
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.data[self.offset(addr).unwrap()]
     }
 
     fn cpu_write(&mut self, addr: u16, data: u8) -> bool {
@@ -70,26 +118,36 @@ impl BusConnectable for Ram {
             );
         }
 
-        if self.memory_map.contains(addr) {
-            let index = addr - self.memory_map.min;
-            self.data.borrow_mut()[index as usize] = data;
+        match self.offset(addr) {
+            Some(index) => {
+                self.data[index] = data;
+                true
+            }
+            None => false,
         }
+    }
+}
 
-        let vec = &mut self.data.borrow_mut();
-        self.mirrors
-            .iter_mut()
-            .find(|map| map.contains(addr))
-            .map(move |map| vec[(addr - map.min) as usize] = data)
-            .is_some()
+impl SaveState for Ram {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.data);
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        let len = take_u32(state) as usize;
+        assert_eq!(self.data.len(), len, "The saved ram has a different size");
+        self.data.copy_from_slice(take(state, len));
     }
 }
 
 pub struct RamBuilder {
-    data: Option<MutRef<Vec<u8>>>,
+    data: Option<Vec<u8>>,
     size: Option<usize>,
     memory_map: Option<MemoryMap>,
     mirrors: Option<Vec<MemoryMap>>,
     read_only: Option<bool>,
+    init: Option<RamInit>,
 }
 
 impl RamBuilder {
@@ -100,6 +158,7 @@ impl RamBuilder {
             memory_map: None,
             mirrors: None,
             read_only: None,
+            init: None,
         }
     }
 
@@ -109,7 +168,7 @@ impl RamBuilder {
     }
 
     pub fn data(mut self, data: Vec<u8>) -> Self {
-        self.data = Some(Rc::new(RefCell::new(data)));
+        self.data = Some(data);
         self
     }
 
@@ -138,13 +197,19 @@ impl RamBuilder {
         self
     }
 
+    /// How to fill the ram that is not covered by `data`
+    pub fn init(mut self, init: RamInit) -> Self {
+        self.init = Some(init);
+        self
+    }
+
     pub fn build(self) -> Ram {
         let size = self.size.unwrap_or(MAX_SIZE);
-        let data = self
-            .data
-            .unwrap_or(Rc::new(RefCell::new(Vec::with_capacity(size))));
+        let mut data = self.data.unwrap_or(Vec::with_capacity(size));
+        let supplied = data.len().min(size);
+        data.resize(size, 0);
+        self.init.unwrap_or_default().apply(&mut data[supplied..]);
 
-        data.borrow_mut().resize(size, 0);
         Ram {
             data,
             size,
@@ -170,7 +235,6 @@ mod tests {
 
         assert_eq!(100, ram.size);
         ram.data
-            .borrow()
             .iter()
             .zip(0..100)
             .for_each(|(&fst, snd)| assert_eq!(fst, snd));
@@ -200,4 +264,47 @@ mod tests {
         assert!(!ram.accept(0x01 - 1));
         assert!(!ram.accept(0xA1));
     }
+
+    #[test]
+    fn test_offset() {
+        let mut ram = Ram::builder()
+            .size(0x2000)
+            .map(MemoryMap::from(0xC000..0xE000))
+            .mirror(MemoryMap::from(0xE000..=0xFFFF))
+            .build();
+        assert_eq!(Some(0), ram.offset(0xC000));
+        assert_eq!(Some(0x1FFC), ram.offset(0xFFFC));
+        assert_eq!(None, ram.offset(0xBFFF));
+
+        assert!(ram.cpu_write(0xFFFC, 0x55));
+        assert_eq!(0x55, ram.cpu_read(0xDFFC));
+        assert_eq!(0x55, ram.data()[0x1FFC]);
+    }
+
+    #[test]
+    fn test_ram_init() {
+        let ram = Ram::builder()
+            .size(0x10)
+            .data(vec![1, 2])
+            .init(RamInit::Fill(0xAA))
+            .build();
+        assert_eq!(&[1, 2, 0xAA, 0xAA], &ram.data[..4]);
+
+        let first = Ram::builder().size(0x400).init(RamInit::Random(7)).build();
+        let second = Ram::builder().size(0x400).init(RamInit::Random(7)).build();
+        assert_eq!(first.data, second.data);
+        assert!(first.data.iter().any(|&byte| byte != 0));
+    }
+
+    #[test]
+    fn test_save_state() {
+        let mut ram = Ram::builder().size(0x10).init(RamInit::Random(3)).build();
+        let mut out = Vec::new();
+        ram.save_state(&mut out);
+        let saved = ram.data.clone();
+
+        ram.init(RamInit::Zero);
+        ram.load_state(&mut out.as_slice());
+        assert_eq!(saved, ram.data);
+    }
 }
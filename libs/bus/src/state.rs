@@ -0,0 +1,71 @@
+/// A device whose state can be saved and restored, e.g. for snapshots of the machine
+///
+/// The state is a flat run of bytes with no framing, so a device has to load exactly
+/// the bytes it saved and the devices of a machine have to be loaded in the order they
+/// were saved in.
+pub trait SaveState {
+    /// Append the state of the device to `out`
+    fn save_state(&self, out: &mut Vec<u8>);
+
+    /// Restore the state from the front of `state` and advance `state` past it
+    fn load_state(&mut self, state: &mut &[u8]);
+}
+
+/// Take `len` bytes off the front of a state
+///
+/// # Panics
+/// If the state is shorter than `len`
+pub fn take<'a>(state: &mut &'a [u8], len: usize) -> &'a [u8] {
+    assert!(state.len() >= len, "The saved state is truncated");
+    let (front, rest) = state.split_at(len);
+    *state = rest;
+
+    front
+}
+
+pub fn take_u8(state: &mut &[u8]) -> u8 {
+    take(state, 1)[0]
+}
+
+pub fn take_bool(state: &mut &[u8]) -> bool {
+    take_u8(state) != 0
+}
+
+pub fn take_u16(state: &mut &[u8]) -> u16 {
+    let mut bytes = [0; 2];
+    bytes.copy_from_slice(take(state, 2));
+    u16::from_le_bytes(bytes)
+}
+
+pub fn take_u32(state: &mut &[u8]) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(take(state, 4));
+    u32::from_le_bytes(bytes)
+}
+
+pub fn take_u64(state: &mut &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(take(state, 8));
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take() {
+        let mut out = vec![7, 1];
+        out.extend_from_slice(&0x1234u16.to_le_bytes());
+        out.extend_from_slice(&0xDEAD_BEEFu32.to_le_bytes());
+        out.extend_from_slice(&u64::MAX.to_le_bytes());
+
+        let mut state = out.as_slice();
+        assert_eq!(7, take_u8(&mut state));
+        assert!(take_bool(&mut state));
+        assert_eq!(0x1234, take_u16(&mut state));
+        assert_eq!(0xDEAD_BEEF, take_u32(&mut state));
+        assert_eq!(u64::MAX, take_u64(&mut state));
+        assert!(state.is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use bus::{
+    state::{take_bool, take_u16, take_u64, take_u8, SaveState},
+    BusConnectable,
+};
+
+/// The clock of the PSG, shared with the cpu (Hz)
+pub const CLOCK_RATE: u32 = 3_579_545;
+
+/// The chip divides its clock by 16, giving one output sample per 16 cpu cycles
+const CLOCK_DIVIDER: u64 = 16;
+
+/// The rate of the samples the PSG produces (Hz)
+pub const SAMPLE_RATE: f64 = CLOCK_RATE as f64 / CLOCK_DIVIDER as f64;
+
+/// The noise shift register of the SN76489AN is 15 bits wide and starts with the top
+/// bit set
+const NOISE_RESET: u16 = 1 << 14;
+
+/// Attenuation 15 is silence
+const SILENT: u8 = 0xF;
+
+/// The output level for each attenuation value, 2dB apart
+fn volume(attenuation: u8) -> f32 {
+    if attenuation >= SILENT {
+        0.0
+    } else {
+        10f32.powf(-0.1 * attenuation as f32)
+    }
+}
+
+/// The SN76489 programmable sound generator of the SG-1000
+///
+/// It has three square wave tone channels and one noise channel, each with its own
+/// attenuation.  The cpu writes to it through any port from 0x40 to 0x7F; it can not
+/// be read.
+#[rustfmt::skip]
+#[derive(Clone)]
+pub struct Psg {
+    tone_periods:  [u16; 3],
+    attenuations:  [u8; 4],
+    noise_control: u8,
+    /// The register the next data byte goes to: channel * 2 + 1 if it is an attenuation
+    latch:         u8,
+    counters:      [u16; 4],
+    tone_outputs:  [bool; 3],
+    /// The noise channel shifts its register every second time its counter runs out
+    noise_toggle:  bool,
+    noise_shift:   u16,
+    clock_cycles:  u64,
+    samples:       Vec<f32>,
+}
+
+impl Psg {
+    /// A PSG as it is on power on: every channel is silent
+    #[rustfmt::skip]
+    pub fn new() -> Psg {
+        Psg {
+            tone_periods:  [0; 3],
+            attenuations:  [SILENT; 4],
+            noise_control: 0,
+            latch:         0,
+            counters:      [0; 4],
+            tone_outputs:  [true; 3],
+            noise_toggle:  false,
+            noise_shift:   NOISE_RESET,
+            clock_cycles:  0,
+            samples:       Vec::new(),
+        }
+    }
+
+    /// Run the PSG for the given number of cpu cycles, producing one sample per 16 cycles
+    pub fn update(&mut self, cycles: u64) {
+        self.clock_cycles += cycles;
+        while self.clock_cycles >= CLOCK_DIVIDER {
+            self.clock_cycles -= CLOCK_DIVIDER;
+            self.tick();
+            let sample = self.sample();
+            self.samples.push(sample);
+        }
+    }
+
+    /// Move the samples produced since the last call to the end of `out`
+    ///
+    /// The samples are at `SAMPLE_RATE` and between -1 and 1.
+    pub fn take_samples(&mut self, out: &mut Vec<f32>) {
+        out.append(&mut self.samples);
+    }
+
+    fn write(&mut self, val: u8) {
+        if val & 0x80 != 0 {
+            self.latch = (val >> 4) & 0b111;
+        }
+        let channel = (self.latch >> 1) as usize;
+        let is_attenuation = self.latch & 1 == 1;
+
+        if is_attenuation {
+            self.attenuations[channel] = val & 0xF;
+        } else if channel == 3 {
+            self.noise_control = val & 0b111;
+            self.noise_shift = NOISE_RESET;
+        } else if val & 0x80 != 0 {
+            let period = &mut self.tone_periods[channel];
+            *period = (*period & 0x3F0) | (val as u16 & 0xF);
+        } else {
+            let period = &mut self.tone_periods[channel];
+            *period = (*period & 0xF) | ((val as u16 & 0x3F) << 4);
+        }
+    }
+
+    fn noise_period(&self) -> u16 {
+        match self.noise_control & 0b11 {
+            3 => self.tone_periods[2],
+            rate => 0x10 << rate,
+        }
+    }
+
+    fn tick(&mut self) {
+        for channel in 0..3 {
+            self.counters[channel] = self.counters[channel].saturating_sub(1);
+            if self.counters[channel] == 0 {
+                self.counters[channel] = self.tone_periods[channel];
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        self.counters[3] = self.counters[3].saturating_sub(1);
+        if self.counters[3] == 0 {
+            self.counters[3] = self.noise_period();
+            self.noise_toggle = !self.noise_toggle;
+            if self.noise_toggle {
+                self.shift_noise();
+            }
+        }
+    }
+
+    fn shift_noise(&mut self) {
+        let white_noise = self.noise_control & 0b100 != 0;
+        let feedback = if white_noise {
+            (self.noise_shift ^ (self.noise_shift >> 1)) & 1
+        } else {
+            self.noise_shift & 1
+        };
+
+        self.noise_shift = (self.noise_shift >> 1) | (feedback << 14);
+    }
+
+    /// The mix of all channels
+    fn sample(&self) -> f32 {
+        let level = |high: bool, attenuation: u8| {
+            let volume = volume(attenuation);
+            if high {
+                volume
+            } else {
+                -volume
+            }
+        };
+
+        // a period of 0 or 1 holds the output high, which games use to play samples
+        let tones: f32 = (0..3)
+            .map(|channel| {
+                let high = self.tone_periods[channel] <= 1 || self.tone_outputs[channel];
+                level(high, self.attenuations[channel])
+            })
+            .sum();
+        let noise = level(self.noise_shift & 1 == 1, self.attenuations[3]);
+
+        (tones + noise) / 4.0
+    }
+}
+
+impl Default for Psg {
+    fn default() -> Psg {
+        Psg::new()
+    }
+}
+
+impl BusConnectable for Psg {
+    fn accept(&self, addr: u16) -> bool {
+        addr & 0xC0 == 0x40
+    }
+
+    fn cpu_read(&mut self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    fn cpu_write(&mut self, _addr: u16, val: u8) -> bool {
+        self.write(val);
+        true
+    }
+}
+
+/// The registers and counters of the chip.  Samples that were not taken are not saved.
+impl SaveState for Psg {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        for period in self.tone_periods.iter() {
+            out.extend_from_slice(&period.to_le_bytes());
+        }
+        out.extend_from_slice(&self.attenuations);
+        out.push(self.noise_control);
+        out.push(self.latch);
+        for counter in self.counters.iter() {
+            out.extend_from_slice(&counter.to_le_bytes());
+        }
+        out.extend(self.tone_outputs.iter().map(|&output| output as u8));
+        out.push(self.noise_toggle as u8);
+        out.extend_from_slice(&self.noise_shift.to_le_bytes());
+        out.extend_from_slice(&self.clock_cycles.to_le_bytes());
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        for period in self.tone_periods.iter_mut() {
+            *period = take_u16(state);
+        }
+        for attenuation in self.attenuations.iter_mut() {
+            *attenuation = take_u8(state);
+        }
+        self.noise_control = take_u8(state);
+        self.latch = take_u8(state);
+        for counter in self.counters.iter_mut() {
+            *counter = take_u16(state);
+        }
+        for output in self.tone_outputs.iter_mut() {
+            *output = take_bool(state);
+        }
+        self.noise_toggle = take_bool(state);
+        self.noise_shift = take_u16(state);
+        self.clock_cycles = take_u64(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(psg: &mut Psg, count: u64) -> Vec<f32> {
+        let mut out = Vec::new();
+        psg.update(count * CLOCK_DIVIDER);
+        psg.take_samples(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_silent_on_power_on() {
+        let mut psg = Psg::new();
+        assert!(samples(&mut psg, 100).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_tone_registers() {
+        let mut psg = Psg::new();
+        // latch tone 1 with the low nibble, then the high 6 bits as data
+        psg.cpu_write(0x7F, 0b1010_0101);
+        psg.cpu_write(0x7F, 0b0001_0010);
+        assert_eq!(0x125, psg.tone_periods[1]);
+
+        // a latched attenuation write
+        psg.cpu_write(0x7F, 0b1011_0011);
+        assert_eq!(3, psg.attenuations[1]);
+        assert_eq!(0x125, psg.tone_periods[1]);
+    }
+
+    #[test]
+    fn test_square_wave() {
+        let mut psg = Psg::new();
+        psg.cpu_write(0x7F, 0b1000_0100); // tone 0 period 4
+        psg.cpu_write(0x7F, 0b1001_0000); // tone 0 full volume
+
+        let out = samples(&mut psg, 16);
+        let first = out[0];
+        assert!(first != 0.0);
+        // the output flips every 4 samples
+        for (index, &sample) in out.iter().enumerate() {
+            let expected = if (index / 4) % 2 == 0 { first } else { -first };
+            assert_eq!(expected, sample, "sample {}", index);
+        }
+    }
+
+    #[test]
+    fn test_noise_reset() {
+        let mut psg = Psg::new();
+        psg.cpu_write(0x7F, 0b1110_0100); // white noise, fastest rate
+        samples(&mut psg, 1000);
+        assert_ne!(NOISE_RESET, psg.noise_shift);
+
+        psg.cpu_write(0x7F, 0b1110_0100);
+        assert_eq!(NOISE_RESET, psg.noise_shift);
+    }
+}
@@ -1,11 +1,14 @@
 use crate::Canvas;
-use bus::{bus::*, ram::Ram, BusConnectable, MutRef};
+use bus::{
+    ram::{Ram, RamInit},
+    state::{take, take_u16, take_u64, take_u8, SaveState},
+    BusConnectable,
+};
 use graphics1::Graphics1Renderer;
 use graphics2::Graphics2Renderer;
 use im::*;
 use std::io::{Result, Write};
 use std::mem;
-use std::{cell::RefCell, rc::Rc};
 use textmode::TextModeRenderer;
 
 mod graphics1;
@@ -22,6 +25,10 @@ pub const VRAM_SIZE: usize = 0x4001; // 16 kbytes
 const CYCLES_PER_LINE: u64 = 228;
 
 const MAX_LINES: u16 = 262;
+const PAL_LINES: u16 = 313;
+
+/// The cpu address of the vram is 14 bits wide and wraps around after 0x3FFF
+const ADDR_MASK: u16 = 0x3FFF;
 
 pub static COLORS: [Rgba<u8>; 16] = [
     Rgba([0, 0, 0, 0]),             // transparent
@@ -48,6 +55,7 @@ fn is_bit_set(val: u32, bit: u8) -> bool {
     (val >> bit) & 1 > 0
 }
 
+#[derive(Clone)]
 enum RWState {
     None,
     First(u8),
@@ -63,10 +71,12 @@ enum GrahpicsMode {
 
 #[allow(dead_code)]
 #[rustfmt::skip]
+#[derive(Clone)]
 pub struct Ppu {
-    canvas:   Option<Canvas>,
+    frame:        Canvas,
+    frame_ready:  bool,
     next_canvas:  Canvas,
-    ram:          MutRef<Ram>,
+    ram:          Ram,
     status_reg:   u8,
     registers:    [u8; 8],
     line:         u16,
@@ -78,12 +88,20 @@ pub struct Ppu {
 }
 
 impl Ppu {
-    #[rustfmt::skip]
     pub fn new() -> Ppu {
+        Ppu::with_vram_init(RamInit::Zero)
+    }
+
+    /// Create a ppu whose VRAM is filled according to `init`
+    ///
+    /// The VRAM contents are undefined on power on, so games should never rely on them
+    #[rustfmt::skip]
+    pub fn with_vram_init(init: RamInit) -> Ppu {
         let mut ppu = Ppu {
-            canvas:       Some(ImageBuffer::new(4*WIDTH, 4*HEIGHT)),
+            frame:        ImageBuffer::new(4*WIDTH, 4*HEIGHT),
+            frame_ready:  true,
             next_canvas:  ImageBuffer::new(4*WIDTH, 4*HEIGHT),
-            ram:          Rc::new(RefCell::new(Ram::builder().size(VRAM_SIZE).build())),
+            ram:          Ram::builder().size(VRAM_SIZE).init(init).build(),
             status_reg:   0,
             registers:    [0; 8],
             line:         0,
@@ -103,22 +121,59 @@ impl Ppu {
     /// # Returns
     /// None if there was no change in the canvas
     /// Some with the new canvas
-    ///     Note that calling this will consume the canvas
-    pub fn get_canvas(&mut self) -> Option<Canvas> {
-        self.canvas.take()
+    ///     Note that calling this will consume the canvas, until the next frame is done
+    ///     it gives None
+    pub fn get_canvas(&mut self) -> Option<&Canvas> {
+        if !self.frame_ready {
+            return None;
+        }
+
+        self.frame_ready = false;
+        Some(&self.frame)
+    }
+
+    /// The last complete frame, whether or not it was taken with `get_canvas`
+    pub fn frame(&self) -> &Canvas {
+        &self.frame
+    }
+
+    /// The frame that is being drawn, complete up to the current line
+    pub fn next_frame(&self) -> &Canvas {
+        &self.next_canvas
+    }
+
+    /// The line the VDP is drawing, counting from the top of the picture
+    pub fn line(&self) -> u16 {
+        self.line
+    }
+
+    /// Draw the pictures with every pixel `zoom` times as wide and high
+    ///
+    /// The picture that is being drawn is thrown away.
+    pub fn set_zoom(&mut self, zoom: u8) {
+        assert!(zoom > 0, "The zoom can not be 0");
+        self.image_zoom = zoom;
+        let (width, height) = (WIDTH * zoom as u32, HEIGHT * zoom as u32);
+        self.frame = ImageBuffer::new(width, height);
+        self.next_canvas = ImageBuffer::new(width, height);
+    }
+
+    /// Switch between the 262 lines of an NTSC frame and the 313 lines of a PAL frame
+    pub fn set_pal(&mut self, pal: bool) {
+        self.max_lines = if pal { PAL_LINES } else { MAX_LINES };
     }
 
     fn ram_read(&mut self) -> u8 {
-        let val = self.ram.borrow_mut().cpu_read(self.cpu_addr);
-        self.cpu_addr += 1;
+        let val = self.ram.cpu_read(self.cpu_addr);
+        self.cpu_addr = (self.cpu_addr + 1) & ADDR_MASK;
         val
     }
 
     // get the status register
-    // this resets the top two bits of the register and resets the read_write state
+    // this resets the frame, 5th sprite and coincidence flags and resets the read_write state
     fn get_status_reg(&mut self) -> u8 {
         let output = self.status_reg;
-        self.status_reg &= !(0b11 << 7);
+        self.status_reg &= 0b1_1111;
         self.rw_state = RWState::None;
 
         output
@@ -157,6 +212,12 @@ impl Ppu {
         is_bit_set(self.registers[1].into(), 5)
     }
 
+    /// The INT line, held from the vblank until the status register is read as long as
+    /// the interrupt is enabled
+    pub fn interrupt_pending(&self) -> bool {
+        self.intrpt_enabled() && self.status_reg & 0x80 != 0
+    }
+
     fn graphics_mode(&self) -> GrahpicsMode {
         use GrahpicsMode::*;
         let m3 = is_bit_set(self.registers[0].into(), 1);
@@ -172,13 +233,22 @@ impl Ppu {
         } else if m1 && !m2 && !m3 {
             Text
         } else {
-            //eprintln!("Illegal Graphics mode! M1={}, M2={}, M3={}", m1, m2, m3);
+            bus::log!(
+                Vdp,
+                Trace,
+                "illegal mode M1={} M2={} M3={}, drawing text",
+                m1,
+                m2,
+                m3
+            );
             Text
         }
     }
 
+    // the bits of the table registers above the 14 bit vram address are ignored
+
     fn name_table(&self) -> u16 {
-        self.registers[2] as u16 * 0x400
+        (self.registers[2] & 0x0F) as u16 * 0x400
     }
 
     fn color_table(&self) -> u16 {
@@ -186,15 +256,15 @@ impl Ppu {
     }
 
     fn pattern_gen_table(&self) -> u16 {
-        self.registers[4] as u16 * 0x800
+        (self.registers[4] & 0x07) as u16 * 0x800
     }
 
     fn sprite_attr_table(&self) -> u16 {
-        self.registers[5] as u16 * 0x80
+        (self.registers[5] & 0x7F) as u16 * 0x80
     }
 
     fn sprite_patt_gen_table(&self) -> u16 {
-        self.registers[6] as u16 * 0x800
+        (self.registers[6] & 0x07) as u16 * 0x800
     }
 
     pub fn log(&self, mut log: impl Write) -> std::io::Result<()> {
@@ -228,10 +298,12 @@ impl Ppu {
                 HEIGHT * self.image_zoom as u32,
             );
             mem::swap(&mut swapped_canvas, &mut self.next_canvas);
-            self.canvas = Some(swapped_canvas);
+            self.frame = swapped_canvas;
+            self.frame_ready = true;
 
             // set the interrupt flag
             self.status_reg |= 1 << 7;
+            bus::log!(Vdp, Trace, "vblank");
         }
 
         return vblank;
@@ -253,48 +325,54 @@ impl Ppu {
     }
 
     fn set_coincidence_flag(&mut self) {
-        self.status_reg |= 1 << 6;
+        self.status_reg |= 1 << 5;
     }
 
+    /// Only the first line with too many sprites in a frame is reported
     fn set_5th_sprite(&mut self, num: u8) {
-        self.status_reg |= num & 0b1_1111;
+        if self.status_reg & (1 << 6) == 0 {
+            self.status_reg = (self.status_reg & !0b1_1111) | 1 << 6 | (num & 0b1_1111);
+        }
     }
 }
 
 impl BusConnectable for Ppu {
+    /// The VDP is selected by A7 and A6 (0x80-0xBF) and A0 picks the control register (odd
+    /// ports) or the data register (even ports)
     fn accept(&self, addr: u16) -> bool {
-        (addr & 0xBE) == 0xBE || (addr & 0xBF) == 0xBF
+        addr & 0xC0 == 0x80
     }
 
     fn cpu_read(&mut self, addr: u16) -> u8 {
-        //println!("Reading address {:x} from PPU", addr);
-        match addr & 0xFF {
-            0xBF => self.get_status_reg(),
-            0xBE => self.ram_read(),
-            _ => panic!("Attempting to read data from PPU at IO address {}", addr),
+        match addr & 1 {
+            1 => {
+                let status = self.get_status_reg();
+                bus::log!(Vdp, Trace, "status read 0x{:02x}", status);
+                status
+            }
+            _ => {
+                let addr = self.cpu_addr;
+                let val = self.ram_read();
+                bus::log!(Vdp, Trace, "vram read 0x{:04x} -> 0x{:02x}", addr, val);
+                val
+            }
         }
     }
 
     fn cpu_write(&mut self, addr: u16, val: u8) -> bool {
-        // println!(
-        //     "Writing val 0x{:x} (0b{:04b} {:04b}) to address 0x{:x} in PPU",
-        //     val,
-        //     (val >> 4) & 0b1111,
-        //     val & 0b1111,
-        //     addr & 0xFF,
-        // );
-        match addr & 0xFF {
-            0xBE => {
-                self.ram.borrow_mut().cpu_write(self.cpu_addr, val);
-                // println!(
-                //     "
-                // ===== WRITING 0x{:02x} TO 0x{:04x} IN PPU ====
-                // ",
-                //     val, self.cpu_addr
-                // );
-                self.cpu_addr += 1;
+        match addr & 1 {
+            0 => {
+                bus::log!(
+                    Vdp,
+                    Trace,
+                    "vram write 0x{:04x} <- 0x{:02x}",
+                    self.cpu_addr,
+                    val
+                );
+                self.ram.cpu_write(self.cpu_addr, val);
+                self.cpu_addr = (self.cpu_addr + 1) & ADDR_MASK;
             }
-            0xBF => match self.rw_state {
+            _ => match self.rw_state {
                 RWState::None => {
                     self.rw_state = RWState::First(val);
                 }
@@ -303,26 +381,62 @@ impl BusConnectable for Ppu {
                         // set cpu address
                         let high = val as u16 & 0b0011_1111;
                         self.cpu_addr = (high << 8) | fst as u16;
-                    // println!("Setting PPU Addr to 0x{:04x}", self.cpu_addr);
+                        bus::log!(Vdp, Trace, "vram address 0x{:04x}", self.cpu_addr);
                     } else if val >> 4 == 0b1000 {
                         // write to register
                         let reg = val & 0b1111;
                         if reg <= 0b111 {
+                            bus::log!(Vdp, Debug, "register {} <- 0x{:02x}", reg, fst);
                             self.registers[reg as usize] = fst;
+                        } else {
+                            bus::log!(Vdp, Warn, "write to missing register {}", reg);
                         }
-                        // println!("Writing 0x{:02x} to register {} in PPU", fst, reg);
                     }
 
                     self.rw_state = RWState::None;
                 }
             },
-            _ => panic!("Attempting to write to PPU at IO address {}", addr),
         };
 
         false
     }
 }
 
+/// The VRAM, registers and beam position of the VDP
+///
+/// The picture that is being drawn is not part of the state, so states should be saved
+/// at the end of a frame.
+impl SaveState for Ppu {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        self.ram.save_state(out);
+        out.push(self.status_reg);
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&self.line.to_le_bytes());
+        out.extend_from_slice(&self.max_lines.to_le_bytes());
+        out.extend_from_slice(&self.clock_cycles.to_le_bytes());
+        match self.rw_state {
+            RWState::None => out.extend_from_slice(&[0, 0]),
+            RWState::First(val) => out.extend_from_slice(&[1, val]),
+        }
+        out.extend_from_slice(&self.cpu_addr.to_le_bytes());
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        self.ram.load_state(state);
+        self.status_reg = take_u8(state);
+        self.registers.copy_from_slice(take(state, 8));
+        self.line = take_u16(state);
+        self.max_lines = take_u16(state);
+        self.clock_cycles = take_u64(state);
+        self.rw_state = match take(state, 2) {
+            [0, _] => RWState::None,
+            [_, val] => RWState::First(*val),
+            _ => unreachable!(),
+        };
+        self.cpu_addr = take_u16(state);
+    }
+}
+
 trait Renderer {
     fn draw(&mut self);
 }
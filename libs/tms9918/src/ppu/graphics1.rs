@@ -44,15 +44,15 @@ impl<'a> Renderer for Graphics1Renderer<'a> {
 
         for cell in 0..LINE_WIDTH {
             let name_entry_ptr = name_tbl + self.cell_row() * LINE_WIDTH + cell;
-            let name_entry = self.ppu.ram.borrow_mut().cpu_read(name_entry_ptr) as u16;
+            let name_entry = self.ppu.ram.cpu_read(name_entry_ptr) as u16;
 
             let colr_entry_ptr = colr_tbl + name_entry / 8;
-            let colr_entry = self.ppu.ram.borrow_mut().cpu_read(colr_entry_ptr);
+            let colr_entry = self.ppu.ram.cpu_read(colr_entry_ptr);
 
             let (color1, color0) = Graphics1Renderer::extract_color(colr_entry);
 
             let patt_entry_ptr = patt_tbl + name_entry * 8 + self.cell_sub_row();
-            let mut patt_entry = self.ppu.ram.borrow_mut().cpu_read(patt_entry_ptr);
+            let mut patt_entry = self.ppu.ram.cpu_read(patt_entry_ptr);
 
             for bit_num in 0..8 {
                 let color = if patt_entry & 0b1000_0000 > 0 {
@@ -1,5 +1,5 @@
 use crate::{
-    ppu::{ImageWriter, Ppu, Renderer, COLORS},
+    ppu::{sprites::SpriteRenderer, ImageWriter, Ppu, Renderer, COLORS},
     Canvas,
 };
 use bus::BusConnectable;
@@ -32,21 +32,29 @@ impl<'a> Graphics2Renderer<'a> {
 
 impl<'a> Renderer for Graphics2Renderer<'a> {
     fn draw(&mut self) {
-        let cells_start = self.cell_row();
+        let cells_start = self.cell_row() * LINE_WIDTH;
         let block = self.block();
-        let patt_tbl = self.ppu.pattern_gen_table() + 2048 * block;
         let name_tbl = self.ppu.name_table();
-        let color_tbl = self.ppu.color_table() + 2048 * block;
+
+        // in graphics II only the top bit of the table registers selects the table; the
+        // low bits mask the cell number, which mirrors parts of the tables
+        let patt_reg = self.ppu.registers[4] as u16;
+        let patt_tbl = (patt_reg & 0b100) << 11;
+        let patt_mask = ((patt_reg & 0b11) << 8) | 0xFF;
+        let color_reg = self.ppu.registers[3] as u16;
+        let color_tbl = (color_reg & 0x80) << 6;
+        let color_mask = ((color_reg & 0x7F) << 3) | 0b111;
 
         for i in 0..LINE_WIDTH {
             let cell_ptr = name_tbl + cells_start + i;
-            let name_entry = self.ppu.ram.borrow_mut().cpu_read(cell_ptr) as u16;
+            let name_entry = self.ppu.ram.cpu_read(cell_ptr) as u16;
+            let cell = (block << 8) | name_entry;
 
-            let gen_ptr = patt_tbl + name_entry * 8 + self.cell_sub_row();
-            let mut patt = self.ppu.ram.borrow_mut().cpu_read(gen_ptr);
+            let gen_ptr = patt_tbl + (cell & patt_mask) * 8 + self.cell_sub_row();
+            let mut patt = self.ppu.ram.cpu_read(gen_ptr);
 
-            let color_ptr = color_tbl + name_entry * 8 + self.cell_sub_row();
-            let color = self.ppu.ram.borrow_mut().cpu_read(color_ptr);
+            let color_ptr = color_tbl + (cell & color_mask) * 8 + self.cell_sub_row();
+            let color = self.ppu.ram.cpu_read(color_ptr);
             let color0 = COLORS[(color & 0x0F) as usize];
             let color1 = COLORS[(color >> 4) as usize];
 
@@ -60,6 +68,8 @@ impl<'a> Renderer for Graphics2Renderer<'a> {
                 patt <<= 1;
             }
         }
+
+        SpriteRenderer::new(self.ppu, self.zoom, self.line).draw();
     }
 }
 
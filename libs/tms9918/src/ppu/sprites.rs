@@ -4,28 +4,44 @@ use crate::{
 };
 use bus::BusConnectable;
 
-const LINE_WIDTH: u16 = 242;
+const SPRITES: u16 = 32;
+
+/// Sprites per line, the ones after them on the line are not drawn
+const MAX_PER_LINE: u8 = 4;
+
+/// A Y position of 0xD0 ends the sprite attribute table
+const END_OF_TABLE: u8 = 0xD0;
 
 struct Sprite {
-    x: u16,
-    y: u16,
+    /// The first line of the sprite, from -31 (partly above the top of the picture) on
+    top: i16,
+    /// The first pixel of the sprite, from -32 on with the early clock bit
+    left: i16,
     name_entry: u16,
-    early_clock: bool,
     color: Color,
 }
 
 impl Sprite {
-    pub fn new(x: u8, y: u8, name_ptr: u8, clock_color: u8) -> Sprite {
+    pub fn new(y: u8, x: u8, name_ptr: u8, clock_color: u8) -> Sprite {
+        // a sprite starts on the line after its Y position, and positions from 0xE1 on
+        // wrap around to above the top of the picture
+        let top = if y > 0xE0 { y as i16 - 0xFF } else { y as i16 + 1 };
+        let early_clock = clock_color & 0x80 != 0;
+
         Sprite {
-            x: x as u16,
-            y: y as u16,
+            top,
+            left: x as i16 - if early_clock { 32 } else { 0 },
             name_entry: name_ptr as u16,
-            early_clock: clock_color >= 0x80,
             color: COLORS[clock_color as usize & 0x0F],
         }
     }
 }
 
+/// Draws the sprites of a line over the picture of the graphics mode
+///
+/// The sprite with the lower number is in front, a line shows at most four sprites, and
+/// a pixel where two sprites are set sets the coincidence flag even if one of them is
+/// transparent.
 pub struct SpriteRenderer<'a> {
     ppu: &'a mut Ppu,
     zoom: u16,
@@ -37,100 +53,78 @@ impl<'a> SpriteRenderer<'a> {
         SpriteRenderer { ppu, zoom, line }
     }
 
-    /// TODO: use the bleeding in of the Early Clock Bit
-    fn draw_8x8(&mut self) {
-        let mut count = 0;
-        let mut positions = Vec::new();
-
-        let attr_tbl = self.ppu.sprite_attr_table();
-        let gen_tbl = self.ppu.sprite_patt_gen_table();
-
-        for spr in 0..32 {
-            let attr_ptr = attr_tbl + 4 * spr;
-            let sprite;
-            {
-                let mut ram = self.ppu.ram.borrow_mut();
-                sprite = Sprite::new(
-                    ram.cpu_read(attr_ptr),
-                    ram.cpu_read(attr_ptr + 1),
-                    ram.cpu_read(attr_ptr + 2),
-                    ram.cpu_read(attr_ptr + 3),
-                );
-            }
-
-            // make sure the sprite is actaully on the current line
-            if sprite.y <= self.line && sprite.y + 8 > self.line {
-                count += 1;
-                if count >= 5 {
-                    self.ppu.set_5th_sprite(spr as u8);
-                    return;
-                }
-
-                let pattern_line = self.line - sprite.y;
-                let pattern_ptr = gen_tbl + sprite.name_entry * 8 + pattern_line;
-                let mut pattern = self.ppu.ram.borrow_mut().cpu_read(pattern_ptr);
-
-                for i in 0..8 {
-                    // check sprite coincidence
-                    let coincident: bool = match positions.binary_search(&(sprite.x + i)) {
-                        Ok(_) => {
-                            self.ppu.set_coincidence_flag();
-                            true
-                        }
-                        Err(pos) => {
-                            positions.insert(pos, sprite.x + i);
-                            false
-                        }
-                    };
-
-                    // if the bit is set for this pixel and it is not already taken up
-                    if pattern & 0x80 > 0 && !coincident {
-                        self.color_pixel(sprite.color, sprite.x + i, self.line);
-                    }
-
-                    pattern <<= 1;
-                }
-            }
+    /// The row of the sprite's pattern on the line, given the size of the sprite in
+    /// pixels and its magnification
+    fn pattern_row(&self, sprite: &Sprite, size: u16, magnify: u16) -> Option<u16> {
+        let row = self.line as i16 - sprite.top;
+        if row >= 0 && row < (size * magnify) as i16 {
+            Some(row as u16 / magnify)
+        } else {
+            None
         }
     }
+}
 
-    fn draw_16x16(&mut self) {
-        let mut positions = Vec::new();
-
+impl<'a> Renderer for SpriteRenderer<'a> {
+    fn draw(&mut self) {
+        let (size, magnify) = self.ppu.get_sprite_size();
+        let (size, magnify) = (size as u16, magnify as u16);
         let attr_tbl = self.ppu.sprite_attr_table();
         let gen_tbl = self.ppu.sprite_patt_gen_table();
 
-        for spr in (0..32).rev() {
+        let mut taken = [false; 256];
+        let mut count = 0;
+
+        for spr in 0..SPRITES {
             let attr_ptr = attr_tbl + 4 * spr;
-            let sprite;
-            {
-                let mut ram = self.ppu.ram.borrow_mut();
-                sprite = Sprite::new(
-                    ram.cpu_read(attr_ptr),
-                    ram.cpu_read(attr_ptr + 1),
-                    ram.cpu_read(attr_ptr + 2),
-                    ram.cpu_read(attr_ptr + 3),
-                );
+            let ram = &mut self.ppu.ram;
+            let y = ram.cpu_read(attr_ptr);
+            if y == END_OF_TABLE {
+                break;
             }
 
-            // check sprite coincidence
-            for i in 0..8 {
-                match positions.binary_search(&(sprite.x + i)) {
-                    Ok(_) => self.ppu.set_coincidence_flag(),
-                    Err(pos) => positions.insert(pos, sprite.x + i),
-                }
+            let sprite = Sprite::new(
+                y,
+                ram.cpu_read(attr_ptr + 1),
+                ram.cpu_read(attr_ptr + 2),
+                ram.cpu_read(attr_ptr + 3),
+            );
+            let row = match self.pattern_row(&sprite, size, magnify) {
+                Some(row) => row,
+                None => continue,
+            };
+
+            if count == MAX_PER_LINE {
+                self.ppu.set_5th_sprite(spr as u8);
+                break;
+            }
+            count += 1;
+
+            // a 16x16 sprite is four patterns, the left two above each other and then the
+            // right two, so the right half of a row is 16 bytes further
+            let name_entry = if size == 16 {
+                sprite.name_entry & 0xFC
+            } else {
+                sprite.name_entry
+            };
+            let pattern_ptr = gen_tbl + name_entry * 8 + row;
+            let mut pattern = (self.ppu.ram.cpu_read(pattern_ptr) as u16) << 8;
+            if size == 16 {
+                pattern |= self.ppu.ram.cpu_read(pattern_ptr + 16) as u16;
             }
 
-            // make sure the sprite is actaully on the current line
-            if sprite.y <= self.line && sprite.y + 8 > self.line {
-                let pattern_line = self.line - sprite.y;
-                let pattern_ptr = gen_tbl + sprite.name_entry * 8 + pattern_line;
-                let pattern = self.ppu.ram.borrow_mut().cpu_read(pattern_ptr);
+            for i in 0..size * magnify {
+                let x = sprite.left + i as i16;
+                if pattern & (0x8000 >> (i / magnify)) == 0 || !(0..=255).contains(&x) {
+                    continue;
+                }
 
-                for i in 0..8 {
-                    // if the bit is set for this pixel
-                    if pattern & 0x80 > 0 {
-                        self.color_pixel(sprite.color, sprite.x + i, self.line);
+                if taken[x as usize] {
+                    self.ppu.set_coincidence_flag();
+                } else {
+                    taken[x as usize] = true;
+                    if sprite.color != COLORS[0] {
+                        self.color_pixel(sprite.color, x as u16, self.line);
                     }
                 }
             }
@@ -138,19 +132,6 @@ impl<'a> SpriteRenderer<'a> {
     }
 }
 
-impl<'a> Renderer for SpriteRenderer<'a> {
-    fn draw(&mut self) {
-        let (size, zoom) = self.ppu.get_sprite_size();
-        self.zoom *= zoom as u16;
-
-        if size == 8 {
-            self.draw_8x8();
-        } else {
-            self.draw_16x16();
-        }
-    }
-}
-
 impl<'a> ImageWriter for SpriteRenderer<'a> {
     fn zoom(&self) -> u16 {
         self.zoom
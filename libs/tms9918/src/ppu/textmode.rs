@@ -38,11 +38,11 @@ impl<'a> Renderer for TextModeRenderer<'a> {
         let cell_row = self.cell_row();
         for i in 0..LINE_WIDTH {
             let name_tbl_ptr = name_tbl + cell_row * LINE_WIDTH + i; // get the name table entry
-            let mut patt_tbl_ptr = self.ppu.ram.borrow_mut().cpu_read(name_tbl_ptr) as u16;
+            let mut patt_tbl_ptr = self.ppu.ram.cpu_read(name_tbl_ptr) as u16;
             patt_tbl_ptr = patt_tbl + 8 * patt_tbl_ptr; // get the pointer to the pattern generator entry
             patt_tbl_ptr += self.inner_cell_row() as u16; // get the row in the pattern table
 
-            let mut pattern = self.ppu.ram.borrow_mut().cpu_read(patt_tbl_ptr); // the actual row of the pattern cell we care about
+            let mut pattern = self.ppu.ram.cpu_read(patt_tbl_ptr); // the actual row of the pattern cell we care about
             for bit_num in 0..7 {
                 if (pattern & 0b1000_0000) == 0 {
                     self.color_pixel(back_color, i * 6 + bit_num, self.line);
@@ -0,0 +1,108 @@
+//! How fast the cpu runs a few endless loops, each leaning on a different prefix
+//!
+//! Save a baseline before touching the decoder and compare against it afterwards:
+//!     cargo bench --bench cpu -- --save-baseline before
+//!     cargo bench --bench cpu -- --baseline before
+//!
+//! The match-based decoder the function tables replaced, against the tables (in millions
+//! of instructions per second, `--measurement-time 5` on one core):
+//!
+//! | benchmark | match | tables |
+//! |-----------|-------|--------|
+//! | main      | 12.6  | 84.1   |
+//! | indexed   | 6.9   | 80.2   |
+//! | block     | 9.3   | 52.2   |
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use z80::cpu::{Cpu, Z80Bus};
+
+/// The instructions every iteration of a benchmark runs
+const INSTRUCTIONS: u64 = 10_000;
+
+/// 64K of ram and no devices
+struct Flat {
+    memory: Vec<u8>,
+}
+
+impl Z80Bus for Flat {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize] = val;
+    }
+
+    fn input(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn output(&mut self, _port: u16, _val: u8) {}
+}
+
+fn cpu(program: &[u8]) -> Cpu<Flat> {
+    let mut memory = vec![0; 0x10000];
+    memory[..program.len()].copy_from_slice(program);
+
+    let mut cpu = Cpu::with_bus(Flat { memory });
+    cpu.power_on();
+    cpu
+}
+
+/// Loads, 8-bit arithmetic and logic, a CB rotate and a relative jump
+const MAIN: &[u8] = &[
+    0x31, 0x00, 0xF0, // ld sp, 0xF000
+    0x06, 0x00, // ld b, 0
+    0x3C, // inc a
+    0x80, // add a, b
+    0xA9, // xor c
+    0x4F, // ld c, a
+    0xCB, 0x11, // rl c
+    0x15, // dec d
+    0xB2, // or d
+    0xE6, 0x7F, // and 0x7F
+    0x10, 0xF4, // djnz -12
+    0xC3, 0x03, 0x00, // jp 0x0003
+];
+
+/// Loads and arithmetic through IX and IY
+const INDEXED: &[u8] = &[
+    0xDD, 0x21, 0x00, 0x80, // ld ix, 0x8000
+    0xFD, 0x21, 0x00, 0x90, // ld iy, 0x9000
+    0x06, 0x00, // ld b, 0
+    0xDD, 0x7E, 0x01, // ld a, (ix+1)
+    0xFD, 0x86, 0x02, // add a, (iy+2)
+    0xDD, 0x77, 0x03, // ld (ix+3), a
+    0xDD, 0x23, // inc ix
+    0xFD, 0x23, // inc iy
+    0x10, 0xF1, // djnz -15
+    0xC3, 0x00, 0x00, // jp 0x0000
+];
+
+/// A block copy with ldir
+const BLOCK: &[u8] = &[
+    0x21, 0x00, 0x80, // ld hl, 0x8000
+    0x11, 0x00, 0x90, // ld de, 0x9000
+    0x01, 0x00, 0x01, // ld bc, 0x0100
+    0xED, 0xB0, // ldir
+    0xC3, 0x00, 0x00, // jp 0x0000
+];
+
+fn bench_programs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    for (name, program) in &[("main", MAIN), ("indexed", INDEXED), ("block", BLOCK)] {
+        let mut cpu = cpu(program);
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for _ in 0..INSTRUCTIONS {
+                    cpu.do_operation();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_programs);
+criterion_main!(benches);
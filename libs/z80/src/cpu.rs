@@ -1,10 +1,13 @@
 #![allow(dead_code)]
 extern crate bus;
 
-use bus::{bus::Bus, MutRef};
+use bus::{
+    state::{take_bool, take_u16, take_u64, take_u8, SaveState},
+    BusConnectable,
+};
 use opcode::Opcode;
 use std::io::Write;
-use std::{mem, rc::Rc};
+use std::mem;
 
 // DONE:
 // 1). LD for main group
@@ -13,7 +16,14 @@ use std::{mem, rc::Rc};
 
 mod bits;
 mod extended;
+mod flags;
 mod opcode;
+#[cfg(test)]
+mod single_step;
+mod state;
+mod timing;
+
+pub use state::CpuState;
 
 const RESET: bool = false;
 const SET: bool = true;
@@ -66,53 +76,123 @@ pub enum Flags {
     Sign,
 }
 
+/// Everything the cpu is connected to: the memory and the I/O ports
+///
+/// The cpu owns its bus, so a machine that owns all of its devices and implements this
+/// trait is dispatched statically, can be cloned whole and can run on another thread.
+pub trait Z80Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+    fn input(&mut self, port: u16) -> u8;
+    fn output(&mut self, port: u16, val: u8);
+
+    /// Called before every machine cycle that uses the bus, with the T-state it starts at
+    /// (see `Cpu::clock`), so a device can catch up to the exact cycle of an access.
+    ///
+    /// Returns the wait states to insert, e.g. for memory that is contended, which make
+    /// the instruction take longer.  By default the cycles are ignored.
+    fn m_cycle(&mut self, _clock: u64, _cycle: MCycle) -> u64 {
+        0
+    }
+}
+
+/// A machine cycle in which the cpu uses the bus
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MCycle {
+    /// An opcode fetch (M1), which takes 4 T-states.  The opcode after a CB, DD, ED or FD
+    /// prefix is fetched the same way, except for DD CB and FD CB where it is read like
+    /// the displacement before it.
+    Fetch(u16),
+    /// A memory read of 3 T-states
+    Read(u16),
+    /// A memory write of 3 T-states
+    Write(u16, u8),
+    /// A port read of 4 T-states
+    Input(u16),
+    /// A port write of 4 T-states
+    Output(u16, u8),
+    /// Acknowledging an interrupt, in place of the opcode fetch
+    InterruptAck,
+}
+
+/// A flat memory and no devices, e.g. to run a program in tests
+///
+/// Reads past the end of the vector see 0 and writes past it grow the vector, like the
+/// vector on a `bus::BusConnectable`.  The ports are an open bus.
+impl Z80Bus for Vec<u8> {
+    fn read(&mut self, addr: u16) -> u8 {
+        BusConnectable::cpu_read(self, addr)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        BusConnectable::cpu_write(self, addr, val);
+    }
+
+    fn input(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn output(&mut self, _port: u16, _val: u8) {}
+}
+
 #[rustfmt::skip]
 // NOTE: all addresses are byte based, so the program counter points to a byte
-pub struct Cpu {
+#[derive(Clone)]
+pub struct Cpu<B> {
     clock:                u64,
-    clock_queue:          i64,
+    /// The T-state the instruction that is running ends at
+    end:                  u64,
     iff1:                 bool,
     iff2:                 bool,
+    interrupt_mode:       u8,
     interrupt_count:      u8,
     reg:                  [u16; 8], // contains A, F, B, C, D, E, H, L
     alt_reg:              [u16; 8], // contains alternate A, F, B, C, D, E, H, L
     spec_reg:             [u32; 6], // contains I, R, IX, IY, PC, SP 
     halted:               bool,
+    /// Stopped from outside, e.g. by a debugger, without running any instruction
+    paused:               bool,
     pub reset_req:        bool,
-    data_bus:             MutRef<Bus>,
-    io_bus:               MutRef<Bus>,
+    bus:                  B,
+    /// A request on the NMI line, which is taken once
     pub nomask_interrupt: bool,
+    /// The level of the INT line.  A device holds it for as long as it requests an
+    /// interrupt, which is taken whenever interrupts are enabled, and releases it once the
+    /// handler has answered.
     pub mask_interrupt:   bool,
 }
 
-impl Cpu {
+impl<B: Z80Bus> Cpu<B> {
+    /// A cpu connected to `bus`, with every register 0 until `power_on`
     #[rustfmt::skip]
-    /// TODO: set buffer to point to a vector of binary file data 
-    pub fn new(data: &MutRef<Bus>, io: &MutRef<Bus>) -> Cpu {
+    pub fn with_bus(bus: B) -> Cpu<B> {
         Cpu {
             clock:                0,
-            clock_queue:          0,
+            end:                  0,
             iff1:                 false,
             iff2:                 false,
+            interrupt_mode:       0,
             reg:                  [0; 8],
             alt_reg:              [0; 8],
             spec_reg:             [0; 6],
             halted:               false,
+            paused:               false,
             reset_req:            false,
             interrupt_count:      0,
-            data_bus:             Rc::clone(data),
-            io_bus:               Rc::clone(io),
+            bus,
             nomask_interrupt:     false,
             mask_interrupt:       false,
 
         }
     }
 
-    pub fn with_pc(data: &MutRef<Bus>, io: &MutRef<Bus>, pc: u16) -> Cpu {
-        let mut cpu = Cpu::new(data, io);
-        cpu.set_reg_value_16(RegisterCode16::PC, pc);
+    /// The memory and the devices the cpu is connected to
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
 
-        cpu
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
     }
 
     //
@@ -129,7 +209,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn set_pc(&mut self, val: u16) {
+    pub fn set_pc(&mut self, val: u16) {
         self.set_reg_value_16(RegisterCode16::PC, val);
     }
 
@@ -139,14 +219,14 @@ impl Cpu {
             I => self.reg_value_16(RegisterCode16::I) as u8,
             R => self.reg_value_16(RegisterCode16::R) as u8,
             IXh => (self.reg_value_16(RegisterCode16::IX) >> 8) as u8,
-            IYh => (self.reg_value_16(RegisterCode16::IX) >> 8) as u8,
-            IXl => (self.reg_value_16(RegisterCode16::IX) & 0x0F) as u8,
-            IYl => (self.reg_value_16(RegisterCode16::IY) & 0x0F) as u8,
+            IYh => (self.reg_value_16(RegisterCode16::IY) >> 8) as u8,
+            IXl => (self.reg_value_16(RegisterCode16::IX) & 0xFF) as u8,
+            IYl => (self.reg_value_16(RegisterCode16::IY) & 0xFF) as u8,
             _ => self.reg[code as usize] as u8,
         }
     }
 
-    fn set_reg_value_16(&mut self, code: RegisterCode16, val: u16) {
+    pub fn set_reg_value_16(&mut self, code: RegisterCode16, val: u16) {
         let reg_high: RegisterCode;
         let reg_low: RegisterCode;
         match code {
@@ -207,7 +287,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn set_flag(&mut self, f: Flags, set: bool) {
+    pub fn set_flag(&mut self, f: Flags, set: bool) {
         let mut flag = self.reg[RegisterCode::Flags as usize];
         if set {
             flag |= 1 << f as u8;
@@ -218,6 +298,13 @@ impl Cpu {
         self.reg[RegisterCode::Flags as usize] = flag;
     }
 
+    /// Replace the flags in `mask` with the ones in `flags`, keeping the others
+    #[inline]
+    fn set_flags(&mut self, mask: u8, flags: u8) {
+        let f = &mut self.reg[RegisterCode::Flags as usize];
+        *f = (*f & !(mask as u16)) | (flags & mask) as u16;
+    }
+
     /// Get the value of a flag
     #[inline]
     pub fn flag(&self, f: Flags) -> bool {
@@ -226,17 +313,24 @@ impl Cpu {
         ((self.reg[RegisterCode::Flags as usize]) >> f as u8) & 1 == 1
     }
 
+    /// Let `n` T-states pass without using the bus, like the cycles an instruction spends
+    /// on its own between two accesses
     pub fn tick_clock(&mut self, n: u64) {
-        if self.clock_queue > 0 {
-            self.clock += self.clock_queue as u64;
-            self.clock_queue = 0;
-        }
         self.clock += n
     }
 
+    /// Make the instruction that is running take `n` more T-states, see `timing`
+    #[inline]
+    fn add_ticks(&mut self, n: u8) {
+        self.end += n as u64;
+    }
+
+    /// Let the bus see a machine cycle, which then passes along with its wait states
     #[inline]
-    fn queue_clock_tick(&mut self, n: i64) {
-        self.clock_queue += n;
+    fn m_cycle(&mut self, cycle: MCycle, ticks: u64) {
+        let wait = self.bus.m_cycle(self.clock, cycle);
+        self.clock += wait + ticks;
+        self.end += wait;
     }
 
     fn set_reg_value(&mut self, code: RegisterCode, value: u16) {
@@ -246,21 +340,21 @@ impl Cpu {
             R => self.set_reg_value_16(RegisterCode16::R, value),
             IXh => {
                 let val = self.reg_value_16(RegisterCode16::IX);
-                self.set_reg_value_16(RegisterCode16::IX, (val & 0x0F) | value << 8);
+                self.set_reg_value_16(RegisterCode16::IX, (val & 0x00FF) | (value & 0xFF) << 8);
             }
 
             IXl => {
                 let val = self.reg_value_16(RegisterCode16::IX);
-                self.set_reg_value_16(RegisterCode16::IX, (val & 0xF0) | value);
+                self.set_reg_value_16(RegisterCode16::IX, (val & 0xFF00) | (value & 0xFF));
             }
             IYh => {
                 let val = self.reg_value_16(RegisterCode16::IY);
-                self.set_reg_value_16(RegisterCode16::IY, (val & 0x0F) | value << 8);
+                self.set_reg_value_16(RegisterCode16::IY, (val & 0x00FF) | (value & 0xFF) << 8);
             }
 
             IYl => {
                 let val = self.reg_value_16(RegisterCode16::IY);
-                self.set_reg_value_16(RegisterCode16::IY, (val & 0xF0) | value);
+                self.set_reg_value_16(RegisterCode16::IY, (val & 0xFF00) | (value & 0xFF));
             }
 
             _ => self.reg[code as usize] = value,
@@ -271,10 +365,7 @@ impl Cpu {
     ///
     /// This function decrements the stack pointer and pushes the value onto the stack
     fn push(&mut self, value: u8) {
-        let mut sp = self.reg_value_16(RegisterCode16::SP);
-
-        sp = if sp == 0 { 0xFFFF } else { sp - 1 };
-
+        let sp = self.reg_value_16(RegisterCode16::SP).wrapping_sub(1);
         self.set_reg_value_16(RegisterCode16::SP, sp);
 
         self.store(sp, value);
@@ -284,10 +375,9 @@ impl Cpu {
     ///
     /// This function pops the value off of the stack and increments the stack pointer.
     fn pop(&mut self) -> u8 {
-        let val = self.fetch(self.reg_value_16(RegisterCode16::SP));
-        let mut pc = self.reg_value_16(RegisterCode16::SP);
-        pc = ((pc as u32 + 1) % 0xFFFF) as u16;
-        self.set_reg_value_16(RegisterCode16::SP, pc);
+        let sp = self.reg_value_16(RegisterCode16::SP);
+        let val = self.fetch(sp);
+        self.set_reg_value_16(RegisterCode16::SP, sp.wrapping_add(1));
 
         val
     }
@@ -308,15 +398,38 @@ impl Cpu {
         self.set_reg_value_16(RegisterCode16::PC, val);
     }
 
-    fn fetch(&self, addr: u16) -> u8 {
-        self.data_bus.borrow().cpu_read(addr).expect(&format!(
-            "Attempted to fetch value at {} but found nothing!",
-            addr,
-        ))
+    fn fetch(&mut self, addr: u16) -> u8 {
+        self.m_cycle(MCycle::Read(addr), 3);
+        self.bus.read(addr)
     }
 
     fn store(&mut self, addr: u16, val: u8) {
-        self.data_bus.borrow_mut().cpu_write(addr, val);
+        bus::log!(Bus, Trace, "write 0x{:04x} <- 0x{:02x}", addr, val);
+        self.m_cycle(MCycle::Write(addr, val), 3);
+        self.bus.write(addr, val);
+    }
+
+    /// Fetch the opcode the PC points at, in an M1 cycle, and move past it
+    fn fetch_opcode(&mut self) -> u8 {
+        let pc = self.get_pc();
+        self.m1_cycle(pc);
+        let opcode = self.bus.read(pc);
+        self.inc_pc();
+
+        opcode
+    }
+
+    /// An opcode fetch, during which the cpu refreshes the dram at R and counts it up
+    fn m1_cycle(&mut self, pc: u16) {
+        self.m_cycle(MCycle::Fetch(pc), 4);
+        self.inc_refresh();
+    }
+
+    /// Count up the low 7 bits of R, bit 7 is only changed by `ld r, a`
+    #[inline]
+    fn inc_refresh(&mut self) {
+        let r = &mut self.spec_reg[RegisterCode16::R as usize];
+        *r = (*r & 0x80) | ((*r + 1) & 0x7F);
     }
 
     pub fn is_halted(&self) -> bool {
@@ -327,6 +440,22 @@ impl Cpu {
         self.halted = false;
     }
 
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop running instructions, while the time still passes for the devices
+    ///
+    /// Unlike a HALT this leaves the PC and the interrupt lines alone, so the program
+    /// carries on where it was after `resume`.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
     pub fn log(&self, mut log: impl Write) -> std::io::Result<()> {
         use RegisterCode::*;
         use RegisterCode16::*;
@@ -379,39 +508,28 @@ impl Cpu {
 }
 
 // The do_operation and related functions
-impl Cpu {
+impl<B: Z80Bus> Cpu<B> {
     /// Get the byte from the current position of program counter
     ///
     /// This function does _not_ increment the program counter.  
     /// Incrementing this value is left up to the calling function
     fn next_byte(&mut self) -> u8 {
-        let byte = self.next_byte_no_inc();
+        let pc = self.get_pc();
+        let byte = self.fetch(pc);
         self.inc_pc();
 
         byte
     }
 
-    fn parity_even(mut val: u32) -> bool {
-        let mut result = 0;
-        while val > 0 {
-            result ^= val & 1;
-            val >>= 1;
-        }
-
-        // Parity is even the result is 0
-        //   (e.g.) 101101 would be 1 xor 1 xor 1 xor 1
-        //          all 1's cancel out
-        result == 0
-    }
-
     /// Get the next byte from the current position of program counter.
     ///
-    /// This function does not increment the program counter after retrieval
-    pub fn next_byte_no_inc(&self) -> u8 {
+    /// This function does not increment the program counter after retrieval, nor does it
+    /// count as a machine cycle
+    pub fn next_byte_no_inc(&mut self) -> u8 {
         let pc = self.get_pc();
 
         // get the byte at the given buffer
-        self.fetch(pc)
+        self.bus.read(pc)
     }
 
     /// Increment the program counter by 1
@@ -419,9 +537,10 @@ impl Cpu {
         self.inc_pc_n(1);
     }
 
-    /// Increment the program counter by the number of _bits_ supplied
-    fn inc_pc_n(&mut self, bits: u16) {
-        self.spec_reg[RegisterCode16::PC as usize] += bits as u32;
+    /// Increment the program counter by `n` bytes, from 0xFFFF it wraps around to 0
+    fn inc_pc_n(&mut self, n: u16) {
+        let pc = self.get_pc().wrapping_add(n);
+        self.set_pc(pc);
     }
 
     /* --------------------------------- ADDRESSING MODES --------------------------------- */
@@ -496,12 +615,12 @@ impl Cpu {
             );
         }
 
-        let reg_val = self.reg_value_16(register) as u32;
+        let reg_val = self.reg_value_16(register);
 
-        let displacement = self.next_byte() as u32;
+        // the displacement is signed, so it reaches from 128 below to 127 above the register
+        let displacement = self.imm_addr() as i8 as u16;
 
-        // in case we wrap around.  this will
-        ((reg_val + displacement) % 0xFFFF) as u16
+        reg_val.wrapping_add(displacement)
     }
 
     /// Indirect Register Addressing
@@ -521,26 +640,29 @@ impl Cpu {
     ///
     /// Returns the number of T-state the operation took
     pub fn do_operation(&mut self) -> u64 {
-        let initial_clock = self.clock;
-        // print!("PC: {}  |  ", self.reg_value_16(RegisterCode16::PC));
-        // println!("Byte 0x{:x}", opcode);
-
-        if self.reset_req {
+        let start = self.clock;
+        self.end = start;
+        if self.paused {
+            // idle for as long as a fetch takes, without touching the bus
+            self.add_ticks(4);
+        } else if self.reset_req {
             self.reset_req = false;
             self.halted = false;
             self.reset();
         } else if self.nomask_interrupt {
             self.nomask_interrupt = false;
-            self.halted = false;
             self.interrupt_nomask();
-        } else if self.mask_interrupt && self.interrupt_count == 0 {
-            self.mask_interrupt = false;
-            self.halted = false;
-            self.interrupt_1();
+        } else if self.mask_interrupt && self.iff1 && self.interrupt_count == 0 {
+            self.accept_interrupt();
         } else if self.halted {
-            Opcode::operate(self, opcode::Opcode::NoOp);
+            // the HALT is fetched again and again, without moving on, until an interrupt
+            let pc = self.get_pc();
+            self.m1_cycle(pc);
+            self.add_ticks(timing::MAIN[Opcode::Halt as usize]);
         } else {
-            let opcode = self.next_byte();
+            let pc = self.get_pc();
+            let opcode = self.fetch_opcode();
+            bus::log!(Cpu, Trace, "0x{:04x}: 0x{:02x}", pc, opcode);
             Opcode::operate_u8(self, opcode);
         }
 
@@ -548,7 +670,12 @@ impl Cpu {
             self.interrupt_count -= 1;
         }
 
-        self.clock - initial_clock
+        // the bus cycles and the internal ones fit in the time of the instruction, the
+        // rest of it the cpu spends on its own after the last access
+        debug_assert!(self.clock <= self.end, "accesses past the end of the instruction");
+        self.clock = self.end;
+
+        self.clock - start
     }
 }
 
@@ -558,11 +685,9 @@ impl Cpu {
  *
  * vvvvvvvvvvvvvvvvvvvvvvvvvvvvvvOperationsvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvv
  * =========================================================================*/
-impl Cpu {
+impl<B: Z80Bus> Cpu<B> {
     /// No Op
     fn noop(&mut self) {
-        //println!("No-Op");
-        self.tick_clock(4);
     }
 
     /// load the dest reg with the src reg value
@@ -584,7 +709,6 @@ impl Cpu {
             }
             _ => {}
         }
-        self.tick_clock(4);
     }
 
     /// load the dest reg with the literal value
@@ -592,10 +716,7 @@ impl Cpu {
     fn ld_reg_lit(&mut self, dst: RegisterCode) {
         let literal = self.next_byte();
 
-        //println!("Loading {:?} with {}", dst, literal);
-
-        self.reg[dst as usize] = literal as u16;
-        self.tick_clock(7);
+        self.set_reg_value(dst, literal as u16);
     }
 
     /// load the dest reg with value pointed to by the address passed in
@@ -603,57 +724,48 @@ impl Cpu {
         let value = self.fetch(addr);
 
         self.set_reg_value(dst, value as u16);
-
-        self.tick_clock(7);
     }
 
     fn ld_addr_reg(&mut self, addr: u16, src: RegisterCode) {
-        let value = self.reg[src as usize];
+        let value = self.reg_value(src);
 
-        self.store(addr, value as u8);
-        self.tick_clock(7);
+        self.store(addr, value);
     }
 
     fn ld_addr_lit(&mut self, addr: u16, lit: u8) {
         self.store(addr, lit);
-        self.tick_clock(10);
     }
 
     fn ld_reg16_lit(&mut self, reg: RegisterCode16, lit: u16) {
         self.set_reg_value_16(reg, lit);
-        self.tick_clock(10);
     }
 
     fn ld_reg16_addr(&mut self, dst: RegisterCode16, addr: u16) {
         let addr_low = self.fetch(addr) as u16;
-        let addr_high = self.fetch(addr + 1) as u16;
+        let addr_high = self.fetch(addr.wrapping_add(1)) as u16;
 
         self.set_reg_value_16(dst, (addr_high << 8) | addr_low);
-        self.tick_clock(16);
     }
 
     fn ld_reg16_reg16(&mut self, dst: RegisterCode16, src: RegisterCode16) {
         let val = self.reg_value_16(src);
         self.set_reg_value_16(dst, val);
-        self.tick_clock(6);
     }
 
     fn ld_addr_reg16(&mut self, addr: u16, src: RegisterCode16) {
         let val = self.reg_value_16(src);
 
         self.store(addr, (val & 0xFF) as u8);
-        self.store(addr + 1, ((val >> 8) & 0xFF) as u8);
-        self.tick_clock(16);
+        self.store(addr.wrapping_add(1), ((val >> 8) & 0xFF) as u8);
     }
 
     fn push_reg16(&mut self, src: RegisterCode16) {
         let val = self.reg_value_16(src);
 
         // high order byte is stored first
+        self.tick_clock(1);
         self.push(((val >> 8) & 0xFF) as u8);
         self.push((val & 0xFF) as u8);
-
-        self.tick_clock(11);
     }
 
     fn pop_reg16(&mut self, dst: RegisterCode16) {
@@ -662,44 +774,23 @@ impl Cpu {
         let val = (high << 8) | low as u16;
 
         self.set_reg_value_16(dst, val);
-        self.tick_clock(10);
     }
 
     /* ---------------------- Incrementing ----------------- */
     fn inc_reg(&mut self, reg: RegisterCode) {
-        let mut val = self.reg[reg as usize];
-
-        self.set_flag(Flags::OverflowParity, val == 0x7F);
-        self.set_flag(Flags::HalfCarry, val & 0b1111 == 0b1111);
-
-        // increment and wrap around to 0
-        val += 1;
-        val %= 0xFF + 1;
-
-        self.set_flag(Flags::Sign, (val & 0x80) > 0);
-        self.set_flag(Flags::Zero, val == 0);
-        self.set_flag(Flags::Subtract, false);
+        let val = self.reg_value(reg).wrapping_add(1);
+        self.set_flags(flags::SZHPN, flags::SZHV_INC[val as usize]);
 
-        self.reg[reg as usize] = val;
-        self.tick_clock(4);
+        self.set_reg_value(reg, val as u16);
     }
 
     fn inc_addr(&mut self, addr: u16) {
-        let mut val = self.fetch(addr) as u16;
-
-        self.set_flag(Flags::OverflowParity, val == 0x7F);
-        self.set_flag(Flags::HalfCarry, val & 0b1111 == 0b1111);
+        let val = self.fetch(addr).wrapping_add(1);
+        self.set_flags(flags::SZHPN, flags::SZHV_INC[val as usize]);
 
-        // increment and wrap around to 0
-        val += 1;
-        val %= 0xFF + 1;
+        self.tick_clock(1);
 
-        self.set_flag(Flags::Sign, (val as i8) < 0);
-        self.set_flag(Flags::Zero, val == 0);
-        self.set_flag(Flags::Subtract, false);
-
-        self.store(addr, val as u8);
-        self.tick_clock(11);
+        self.store(addr, val);
     }
 
     /// Increment a 16 bit register or register pair.  
@@ -711,55 +802,24 @@ impl Cpu {
         val %= 0xFFFF + 1;
 
         self.set_reg_value_16(reg, val as u16);
-        self.tick_clock(6);
     }
 
     /// Decrement the register by 1
     fn dec_reg(&mut self, reg: RegisterCode) {
-        let mut val = self.reg[reg as usize];
-
-        self.set_flag(Flags::Subtract, true);
-        self.set_flag(Flags::OverflowParity, val == 0x80);
-        // set half carry if there is a borrow from bit 4 to 3
-        self.set_flag(Flags::HalfCarry, val & 0b1111 == 0);
-
-        if val == 0 {
-            // wrap around
-            val = 0xFF;
-        } else {
-            val -= 1;
-        }
+        let val = self.reg_value(reg).wrapping_sub(1);
+        self.set_flags(flags::SZHPN, flags::SZHV_DEC[val as usize]);
 
-        // set the sign flag is the first bit is set
-        self.set_flag(Flags::Sign, val > 0x80);
-        self.set_flag(Flags::Zero, val == 0);
-
-        self.reg[reg as usize] = val;
-        self.tick_clock(4);
+        self.set_reg_value(reg, val as u16);
     }
 
     /// Decrement the register by 1
     fn dec_addr(&mut self, addr: u16) {
-        let mut val = self.fetch(addr);
+        let val = self.fetch(addr).wrapping_sub(1);
+        self.set_flags(flags::SZHPN, flags::SZHV_DEC[val as usize]);
 
-        self.set_flag(Flags::Subtract, true);
-        self.set_flag(Flags::OverflowParity, val == 0x80);
-        // set half carry if there is a borrow from bit 4 to 3
-        self.set_flag(Flags::HalfCarry, val & 0b1111 == 0);
-
-        if val == 0 {
-            // wrap around
-            val = 0xFF;
-        } else {
-            val -= 1;
-        }
-
-        // set the sign flag is the first bit is set
-        self.set_flag(Flags::Sign, val > 0x80);
-        self.set_flag(Flags::Zero, val == 0);
+        self.tick_clock(1);
 
         self.store(addr, val);
-        self.tick_clock(11);
     }
 
     /// Decrement a 16 bit register or register pair.  
@@ -775,18 +835,20 @@ impl Cpu {
         }
 
         self.set_reg_value_16(reg, val as u16);
-        self.tick_clock(6);
     }
 
     /// Add 8 bit values
     ///
-    /// This function sets the necessary flags for the addition and returns the result
-    fn add_val_val(&mut self, acc: u16, operand: u16) -> u16 {
+    /// This function sets the necessary flags for the addition and returns the result.  The
+    /// carry is added in with the operand, so ADC sets the half carry and the overflow from
+    /// the whole sum.
+    fn add_val_val(&mut self, acc: u16, operand: u16, carry: bool) -> u16 {
+        let carry = if carry { 1 } else { 0 };
         // add the lower 4 bits of the two operands.
         // If the result > 4 bits then we have half carry
-        self.set_flag(Flags::HalfCarry, (operand & 0x0F) + (acc & 0x0F) > 0x0F);
+        self.set_flag(Flags::HalfCarry, (operand & 0x0F) + (acc & 0x0F) + carry > 0x0F);
 
-        let result = acc + operand;
+        let result = acc + operand + carry;
         // set the carry flag before we wrap around.  We have a carry if we wrapped around to 0
         // (i.e.) went 0xFF to 0
         self.set_flag(Flags::Carry, result > 0xFF);
@@ -812,58 +874,47 @@ impl Cpu {
         let operand = self.reg_value(reg) as u16;
         let acc = self.reg_value(RegisterCode::A) as u16;
 
-        //println!("Adding {:?} to {:?}", operand, acc);
-
-        let result = self.add_val_val(acc, operand);
+        let result = self.add_val_val(acc, operand, false);
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(4);
     }
 
     fn add_a_addr(&mut self, addr: u16) {
         let acc = self.reg_value(RegisterCode::A) as u16;
         let operand = self.fetch(addr) as u16;
 
-        let result = self.add_val_val(acc, operand);
+        let result = self.add_val_val(acc, operand, false);
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     fn add_a_lit(&mut self, lit: u8) {
         let acc = self.reg_value(RegisterCode::A) as u16;
 
-        let result = self.add_val_val(acc, lit as u16);
+        let result = self.add_val_val(acc, lit as u16, false);
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     /// Add the contents of the specified register to the accumlator register
     fn add_a_reg_carry(&mut self, reg: RegisterCode) {
         let operand = self.reg_value(reg) as u16;
         let acc = self.reg_value(RegisterCode::A) as u16;
-        let carry = if self.flag(Flags::Carry) { 1 } else { 0 };
 
-        let result = self.add_val_val(acc + carry, operand);
+        let result = self.add_val_val(acc, operand, self.flag(Flags::Carry));
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(4);
     }
 
     fn add_a_addr_carry(&mut self, addr: u16) {
         let acc = self.reg_value(RegisterCode::A) as u16;
         let operand = self.fetch(addr) as u16;
-        let carry = if self.flag(Flags::Carry) { 1 } else { 0 };
 
-        let result = self.add_val_val(acc + carry, operand);
+        let result = self.add_val_val(acc, operand, self.flag(Flags::Carry));
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     fn add_a_lit_carry(&mut self, lit: u8) {
         let acc = self.reg_value(RegisterCode::A) as u16;
-        let carry = if self.flag(Flags::Carry) { 1 } else { 0 };
 
-        let result = self.add_val_val(acc + carry, lit as u16);
+        let result = self.add_val_val(acc, lit as u16, self.flag(Flags::Carry));
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     fn add_reg16_reg16(&mut self, to: RegisterCode16, operand: RegisterCode16) {
@@ -872,14 +923,13 @@ impl Cpu {
 
         let mut result = add_to + with;
         self.set_flag(Flags::Carry, result > 0xFFFF);
-        result %= 0xFFFF;
+        result &= 0xFFFF;
 
         self.set_flag(Flags::Subtract, false);
         // set the flag with the first 3 nibble overlow into the highnibble
         self.set_flag(Flags::HalfCarry, (add_to & 0xFFF) + (with & 0xFFF) > 0xFFF);
 
         self.set_reg_value_16(to, result as u16);
-        self.tick_clock(11);
     }
 
     fn adc_reg16_reg16(&mut self, src: RegisterCode16, op: RegisterCode16) {
@@ -889,7 +939,7 @@ impl Cpu {
 
         let mut result = add_to + with + carry;
         self.set_flag(Flags::Carry, result > 0xFFFF);
-        result %= 0xFFFF;
+        result &= 0xFFFF;
 
         self.set_flag(Flags::Sign, result >= 0x8000);
         self.set_flag(Flags::Zero, result == 0);
@@ -904,12 +954,11 @@ impl Cpu {
         self.set_flag(Flags::Subtract, false);
 
         self.set_reg_value_16(src, result as u16);
-        self.tick_clock(15);
     }
 
     fn sbc_reg16_reg16(&mut self, to: RegisterCode16, operand: RegisterCode16) {
-        let src = self.reg_value_16(to);
-        let op = self.reg_value_16(operand);
+        let src = self.reg_value_16(to) as u32;
+        let op = self.reg_value_16(operand) as u32;
         let carry = if self.flag(Flags::Carry) { 1 } else { 0 };
 
         let result = if src >= op + carry {
@@ -917,7 +966,7 @@ impl Cpu {
             src - op - carry
         } else {
             self.set_flag(Flags::Carry, true);
-            0xFFFF - op - carry + src + 1
+            0x10000 - op - carry + src
         };
 
         self.set_flag(Flags::Sign, result >= 0x8000);
@@ -931,8 +980,7 @@ impl Cpu {
         );
         self.set_flag(Flags::Subtract, true);
 
-        self.set_reg_value_16(to, result);
-        self.tick_clock(15);
+        self.set_reg_value_16(to, result as u16);
     }
 
     /// Subtract the value in the operand from the Accumulator
@@ -942,7 +990,7 @@ impl Cpu {
     fn sub_val_val(&mut self, acc: u16, operand: u16, carry: bool) -> u16 {
         let carry = if carry { 1 } else { 0 };
         let result = if acc >= (operand + carry) {
-            self.set_flag(Flags::Carry, true);
+            self.set_flag(Flags::Carry, false);
             acc - operand - carry
         } else {
             self.set_flag(Flags::Carry, true);
@@ -950,13 +998,11 @@ impl Cpu {
             0xFF - remainder + 1
         };
 
-        //println!("Subtracting... {} - {} = {}", acc, operand, result);
-
-        self.set_flag(Flags::Sign, result > 0x80);
+        self.set_flag(Flags::Sign, result >= 0x80);
         self.set_flag(Flags::Zero, result == 0);
         self.set_flag(Flags::Subtract, true);
-        // set half carry if 4th bit of acc == 0 and 4th bit of result != 0
-        self.set_flag(Flags::HalfCarry, acc & 0x0F < operand & 0x0F);
+        // set half carry if the low nibble has to borrow from the high one
+        self.set_flag(Flags::HalfCarry, acc & 0x0F < (operand & 0x0F) + carry);
         self.set_flag(
             Flags::OverflowParity,
             acc & 0x80 != operand & 0x80 && acc & 0x80 != result & 0x80,
@@ -972,7 +1018,6 @@ impl Cpu {
         let result = self.sub_val_val(acc, val, false);
 
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(4);
     }
 
     fn sub_a_addr(&mut self, addr: u16) {
@@ -981,7 +1026,6 @@ impl Cpu {
 
         let result = self.sub_val_val(acc, operand, false);
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     fn sub_a_lit(&mut self, lit: u8) {
@@ -989,7 +1033,6 @@ impl Cpu {
 
         let result = self.sub_val_val(acc, lit as u16, false);
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     fn sub_a_reg_carry(&mut self, reg: RegisterCode) {
@@ -999,7 +1042,6 @@ impl Cpu {
         let result = self.sub_val_val(acc, val, self.flag(Flags::Carry));
 
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(4);
     }
 
     fn sub_a_addr_carry(&mut self, addr: u16) {
@@ -1008,7 +1050,6 @@ impl Cpu {
 
         let result = self.sub_val_val(acc, operand, self.flag(Flags::Carry));
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     fn sub_a_lit_carry(&mut self, lit: u8) {
@@ -1016,18 +1057,11 @@ impl Cpu {
 
         let result = self.sub_val_val(acc, lit as u16, self.flag(Flags::Carry));
         self.set_reg_value(RegisterCode::A, result);
-        self.tick_clock(7);
     }
 
     fn and_val_val(&mut self, acc: u8, operand: u8) -> u8 {
         let result = acc & operand;
-        self.set_flag(Flags::Sign, result >= 0x80);
-        self.set_flag(Flags::Zero, result == 0);
-        self.set_flag(Flags::HalfCarry, true);
-        self.set_flag(Flags::Subtract, false);
-        self.set_flag(Flags::Carry, false);
-
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(result as u32));
+        self.set_flags(flags::SZHPNC, flags::SZP[result as usize] | flags::HALF_CARRY);
 
         result
     }
@@ -1038,8 +1072,6 @@ impl Cpu {
 
         let result = self.and_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-
-        self.tick_clock(4);
     }
 
     fn and_a_addr(&mut self, addr: u16) {
@@ -1048,8 +1080,6 @@ impl Cpu {
 
         let result = self.and_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-
-        self.tick_clock(7);
     }
 
     fn and_a_lit(&mut self, val: u8) {
@@ -1057,25 +1087,11 @@ impl Cpu {
 
         let result = self.and_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-
-        self.tick_clock(7);
     }
 
     fn or_val_val(&mut self, acc: u8, operand: u8) -> u8 {
         let result = acc | operand;
-        self.set_flag(Flags::Sign, result >= 0x80);
-        self.set_flag(Flags::Zero, result == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::Subtract, false);
-        self.set_flag(Flags::Carry, false);
-
-        // let mut parity = 0;
-        // let mut val = result;
-        // while val > 0 {
-        //     parity ^= val & 1;
-        //     val >>= 1;
-        // }
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(result as u32));
+        self.set_flags(flags::SZHPNC, flags::SZP[result as usize]);
 
         result
     }
@@ -1086,7 +1102,6 @@ impl Cpu {
 
         let result = self.or_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-        self.tick_clock(4);
     }
 
     fn or_a_addr(&mut self, addr: u16) {
@@ -1095,7 +1110,6 @@ impl Cpu {
 
         let result = self.or_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-        self.tick_clock(7);
     }
 
     fn or_a_lit(&mut self, val: u8) {
@@ -1104,18 +1118,11 @@ impl Cpu {
         let result = self.or_val_val(acc, val);
 
         self.set_reg_value(RegisterCode::A, result as u16);
-        self.tick_clock(7);
     }
 
     fn xor_val_val(&mut self, acc: u8, operand: u8) -> u8 {
         let result = acc ^ operand;
-        self.set_flag(Flags::Sign, result >= 0x80);
-        self.set_flag(Flags::Zero, result == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::Subtract, false);
-        self.set_flag(Flags::Carry, false);
-
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(result as u32));
+        self.set_flags(flags::SZHPNC, flags::SZP[result as usize]);
 
         result
     }
@@ -1126,8 +1133,6 @@ impl Cpu {
 
         let result = self.xor_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-
-        self.tick_clock(4);
     }
 
     fn xor_a_addr(&mut self, addr: u16) {
@@ -1136,8 +1141,6 @@ impl Cpu {
 
         let result = self.xor_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-
-        self.tick_clock(7);
     }
 
     fn xor_a_lit(&mut self, val: u8) {
@@ -1145,18 +1148,15 @@ impl Cpu {
 
         let result = self.xor_val_val(acc, val);
         self.set_reg_value(RegisterCode::A, result as u16);
-
-        self.tick_clock(7);
     }
 
     fn cp_a_val(&mut self, val: u8) -> bool {
         let a = self.reg_value(RegisterCode::A);
         let result = if val > a { 0xFF - val + a + 1 } else { a - val };
 
-        //println!("Comparing {}, {}", a, val);
-
         self.set_flag(Flags::Zero, result == 0);
-        self.set_flag(Flags::OverflowParity, val > a);
+        // overflow if the signs differ and the result does not have the sign of A
+        self.set_flag(Flags::OverflowParity, (a ^ val) & (a ^ result) & 0x80 != 0);
         self.set_flag(Flags::Subtract, true);
         self.set_flag(Flags::HalfCarry, (val & 0x0F) > (a & 0x0F));
         self.set_flag(Flags::Carry, val > a);
@@ -1169,19 +1169,16 @@ impl Cpu {
         let val = self.reg_value(reg);
 
         self.cp_a_val(val);
-        self.tick_clock(4);
     }
 
     fn cp_a_addr(&mut self, addr: u16) {
         let val = self.fetch(addr);
 
         self.cp_a_val(val);
-        self.tick_clock(7);
     }
 
     fn cp_a_lit(&mut self, val: u8) {
         self.cp_a_val(val);
-        self.tick_clock(7);
     }
 
     fn rot_l_carry(&self, val: u8) -> (u8, bool) {
@@ -1196,16 +1193,8 @@ impl Cpu {
 
         let (output, carry) = self.rot_l_carry(val);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
         self.set_reg_value(src, output as u16);
-
-        self.tick_clock(8);
     }
 
     fn rlc_addr(&mut self, addr: u16) {
@@ -1213,16 +1202,9 @@ impl Cpu {
 
         let (output, carry) = self.rot_l_carry(val);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
+        self.tick_clock(1);
         self.store(addr, output);
-
-        self.tick_clock(15);
     }
 
     fn rlca(&mut self) {
@@ -1235,7 +1217,6 @@ impl Cpu {
 
         self.set_flag(Flags::Carry, carry);
         self.set_reg_value(RegisterCode::A, val as u16);
-        self.tick_clock(4);
     }
 
     fn rot_l(&self, val: u8) -> (u8, bool) {
@@ -1251,16 +1232,9 @@ impl Cpu {
         let reg = self.reg_value(src);
         let (output, carry) = self.rot_l(reg);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn rl_addr(&mut self, addr: u16) {
@@ -1268,16 +1242,9 @@ impl Cpu {
 
         let (output, carry) = self.rot_l(val);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
+        self.tick_clock(1);
         self.store(addr, output);
-
-        self.tick_clock(15);
     }
 
     fn rla(&mut self) {
@@ -1290,7 +1257,6 @@ impl Cpu {
         self.set_flag(Flags::Subtract, false);
 
         self.set_reg_value(RegisterCode::A, val as u16);
-        self.tick_clock(4);
     }
 
     fn sl(&mut self, val: u8, bit1: u8) -> (u8, bool) {
@@ -1304,64 +1270,40 @@ impl Cpu {
         let reg = self.reg_value(src);
         let (output, carry) = self.sl(reg, 0);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn sla_addr(&mut self, addr: u16) {
         let val = self.fetch(addr);
         let (output, carry) = self.sl(val, 0);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
-        self.set_flag(Flags::Carry, carry);
+        self.tick_clock(1);
 
         self.store(addr, output);
-        self.tick_clock(15);
     }
 
     fn sll_reg(&mut self, src: RegisterCode) {
         let reg = self.reg_value(src);
         let (output, carry) = self.sl(reg, 1);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn sll_addr(&mut self, addr: u16) {
         let val = self.fetch(addr);
         let (output, carry) = self.sl(val, 1);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
-        self.set_flag(Flags::Carry, carry);
+        self.tick_clock(1);
 
         self.store(addr, output);
-        self.tick_clock(15);
     }
 
     fn rot_r_carry(&self, val: u8) -> (u8, bool) {
@@ -1375,16 +1317,9 @@ impl Cpu {
         let reg = self.reg_value(src);
         let (output, carry) = self.rot_r_carry(reg);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn rrc_addr(&mut self, addr: u16) {
@@ -1392,16 +1327,9 @@ impl Cpu {
 
         let (output, carry) = self.rot_r_carry(val);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
+        self.tick_clock(1);
         self.store(addr, output);
-
-        self.tick_clock(15);
     }
 
     fn rrca(&mut self) {
@@ -1413,7 +1341,6 @@ impl Cpu {
         self.set_flag(Flags::Subtract, false);
 
         self.set_reg_value(RegisterCode::A, ((a >> 1) | (carry << 7)) as u16);
-        self.tick_clock(4);
     }
 
     fn rot_r(&self, val: u8) -> (u8, bool) {
@@ -1428,16 +1355,9 @@ impl Cpu {
         let reg = self.reg_value(src);
         let (output, carry) = self.rot_r(reg);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn rr_addr(&mut self, addr: u16) {
@@ -1445,16 +1365,9 @@ impl Cpu {
 
         let (output, carry) = self.rot_r(val);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
+        self.tick_clock(1);
         self.store(addr, output);
-
-        self.tick_clock(15);
     }
 
     fn rra(&mut self) {
@@ -1462,12 +1375,11 @@ impl Cpu {
         let carry_out = a & 1;
         let carry_in = if self.flag(Flags::Carry) { 1 } else { 0 };
 
-        self.set_flag(Flags::Carry, carry_in > 0);
+        self.set_flag(Flags::Carry, carry_out > 0);
         self.set_flag(Flags::HalfCarry, false);
         self.set_flag(Flags::Subtract, false);
 
-        self.set_reg_value(RegisterCode::A, ((a >> 1) | (carry_out << 7)) as u16);
-        self.tick_clock(4);
+        self.set_reg_value(RegisterCode::A, ((a >> 1) | (carry_in << 7)) as u16);
     }
 
     fn sr(&self, val: u8, retain_bit7: bool) -> (u8, bool) {
@@ -1482,104 +1394,77 @@ impl Cpu {
         let reg = self.reg_value(src);
         let (output, carry) = self.sr(reg, true);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn sra_addr(&mut self, addr: u16) {
         let val = self.fetch(addr);
         let (output, carry) = self.sr(val, true);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
-        self.set_flag(Flags::Carry, carry);
+        self.tick_clock(1);
 
         self.store(addr, output);
-        self.tick_clock(15);
     }
 
     fn srl_reg(&mut self, src: RegisterCode) {
         let reg = self.reg_value(src);
         let (output, carry) = self.sr(reg, false);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
-
-        self.set_flag(Flags::Carry, carry);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn srl_addr(&mut self, addr: u16) {
         let val = self.fetch(addr);
         let (output, carry) = self.sr(val, false);
 
-        self.set_flag(Flags::Sign, output >= 0x80);
-        self.set_flag(Flags::Zero, output == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(output as u32));
-        self.set_flag(Flags::Subtract, false);
+        self.set_flags(flags::SZHPNC, flags::SZP[output as usize] | carry as u8);
 
-        self.set_flag(Flags::Carry, carry);
+        self.tick_clock(1);
 
         self.store(addr, output);
-        self.tick_clock(15);
     }
 
     /* ============================ JUMP INSTRUCTIONS ============================= */
     /// Jump to the specified address
     fn jmp(&mut self, addr: u16) {
         self.set_reg_value_16(RegisterCode16::PC, addr);
-        //println!("Jumping to 0x{:x}", addr);
-        self.tick_clock(10);
     }
 
     fn jmp_addr(&mut self, src: RegisterCode16) {
         let addr = self.reg_value_16(src);
         self.set_reg_value_16(RegisterCode16::PC, addr);
-        //println!("Jumping to 0x{:x}", addr);
-        self.tick_clock(4);
     }
 
     /// Jump to the offset specified by the next byte
     fn jmp_rel(&mut self) {
         let addr = self.rel_addr();
-        //println!(
-        //    "Jumping from 0x{:x} to 0x{:x}",
-        //    self.reg_value_16(RegisterCode16::PC),
-        //    addr
-        //);
         self.set_reg_value_16(RegisterCode16::PC, addr);
-        self.tick_clock(12);
     }
 
     /// Execute a jump to the specified address if the flag matches the condition passed in
     fn jmp_cond(&mut self, addr: u16, flag: Flags, is_set: bool) {
         if self.flag(flag) == is_set {
             self.jmp(addr);
-            self.tick_clock(12);
-        } else {
-            self.tick_clock(7);
+        }
+    }
+
+    /// Jump by the offset in the next byte if the flag matches the condition passed in
+    fn jmp_rel_cond(&mut self, flag: Flags, is_set: bool) {
+        let addr = self.rel_addr();
+        if self.flag(flag) == is_set {
+            self.jmp(addr);
+            self.add_ticks(timing::JR_TAKEN);
         }
     }
 
     fn djnz(&mut self) {
+        self.tick_clock(1);
         let addr = self.rel_addr();
         let mut b = self.reg_value(RegisterCode::B);
         b -= 1;
@@ -1587,9 +1472,7 @@ impl Cpu {
 
         if b > 0 {
             self.set_reg_value_16(RegisterCode16::PC, addr);
-            self.tick_clock(13);
-        } else {
-            self.tick_clock(8);
+            self.add_ticks(timing::JR_TAKEN);
         }
     }
 
@@ -1598,8 +1481,6 @@ impl Cpu {
             .swap(RegisterCode::D as usize, RegisterCode::H as usize);
         self.reg
             .swap(RegisterCode::E as usize, RegisterCode::L as usize);
-
-        self.tick_clock(4);
     }
 
     fn ex_af_altaf(&mut self) {
@@ -1612,8 +1493,6 @@ impl Cpu {
             &mut self.reg[RegisterCode::Flags as usize],
             &mut self.alt_reg[RegisterCode::Flags as usize],
         );
-
-        self.tick_clock(4);
     }
 
     fn exx(&mut self) {
@@ -1623,14 +1502,12 @@ impl Cpu {
             .for_each(|(r, ar)| {
                 mem::swap(r, ar);
             });
-
-        self.tick_clock(4);
     }
 
     fn ex_spptr_reg(&mut self, reg_code: RegisterCode16) {
         let sp = self.reg_value_16(RegisterCode16::SP);
         let mut fetched_low = self.fetch(sp) as u16;
-        let mut fetched_high = self.fetch(sp + 1) as u16;
+        let mut fetched_high = self.fetch(sp.wrapping_add(1)) as u16;
 
         match reg_code {
             RegisterCode16::HL => {
@@ -1651,10 +1528,9 @@ impl Cpu {
                 reg_code
             ),
         }
+        self.tick_clock(1);
         self.store(sp, fetched_low as u8);
-        self.store(sp + 1, fetched_high as u8);
-
-        self.tick_clock(19);
+        self.store(sp.wrapping_add(1), fetched_high as u8);
     }
 
     /// This instruction conditionally adjusts the Accumulator for BCD addition and
@@ -1662,46 +1538,31 @@ impl Cpu {
     ///
     /// See: https://stackoverflow.com/questions/8119577/z80-daa-instruction
     fn daa(&mut self) {
-        let mut acc = self.reg_value(RegisterCode::A) as u16;
-        let carry = self.flag(Flags::Carry)
-            || (acc & 0xF0) > 0x90
-            || (acc & 0xF0) >= 0x90 && (acc & 0x0F) > 0x90;
-
-        let hcarry = (acc & 0x0F) > 0x90
-            || self.flag(Flags::Subtract) && self.flag(Flags::HalfCarry) && (acc & 0xF0) <= 0x5;
-
-        let func = |dest: &mut u16, val| {
-            if self.flag(Flags::Subtract) {
-                *dest += 0x100 - val;
-            } else {
-                *dest += val;
-            }
-        };
-
-        // we have to add 6 to in order to "wrap" the lower 4 bits to 0
-        if self.flag(Flags::HalfCarry) || (acc & 0xF) > 9 {
-            func(&mut acc, 0x6);
-        }
-
-        if self.flag(Flags::Carry) || (acc & 0xF0) > 0x90 {
-            func(&mut acc, 0x60);
+        let acc = self.reg_value(RegisterCode::A);
+        let subtract = self.flag(Flags::Subtract);
+        let half_carry = self.flag(Flags::HalfCarry);
+        let carry = self.flag(Flags::Carry) || acc > 0x99;
+
+        // a low nibble past 9 has to wrap around to the next ten, and so does the high one
+        let mut correction = 0;
+        if half_carry || (acc & 0x0F) > 9 {
+            correction |= 0x06;
         }
-
-        self.set_flag(Flags::Carry, carry);
-        self.set_flag(Flags::HalfCarry, hcarry);
-        self.set_flag(Flags::Zero, acc == 0);
-        self.set_flag(Flags::Sign, acc >= 080);
-
-        let mut parity = 0;
-        let mut val = acc;
-        while val > 0 {
-            parity ^= val & 1;
-            val >>= 1;
+        if carry {
+            correction |= 0x60;
         }
 
-        self.set_flag(Flags::OverflowParity, parity > 0);
+        let result = if subtract {
+            self.set_flag(Flags::HalfCarry, half_carry && (acc & 0x0F) < 6);
+            acc.wrapping_sub(correction)
+        } else {
+            self.set_flag(Flags::HalfCarry, (acc & 0x0F) > 9);
+            acc.wrapping_add(correction)
+        };
 
-        self.tick_clock(4);
+        let szpc = flags::SIGN | flags::ZERO | flags::OVERFLOW_PARITY | flags::CARRY;
+        self.set_flags(szpc, flags::SZP[result as usize] | carry as u8);
+        self.set_reg_value(RegisterCode::A, result as u16);
     }
 
     fn cpl(&mut self) {
@@ -1711,45 +1572,46 @@ impl Cpu {
 
         self.set_flag(Flags::HalfCarry, true);
         self.set_flag(Flags::Subtract, true);
-
-        self.tick_clock(4);
     }
 
     fn neg(&mut self) {
         let acc = self.reg_value(RegisterCode::A);
-        let result = 0xFF - acc + 1;
+        let result = acc.wrapping_neg();
 
         self.set_flag(Flags::Sign, result >= 0x80);
         self.set_flag(Flags::Zero, result == 0);
-        self.set_flag(Flags::Zero, result & 0x0F > 0);
+        self.set_flag(Flags::HalfCarry, result & 0x0F > 0);
         self.set_flag(Flags::OverflowParity, acc == 0x80);
         self.set_flag(Flags::Subtract, true);
         self.set_flag(Flags::Carry, acc > 0);
 
         self.set_reg_value(RegisterCode::A, result as u16);
-        self.tick_clock(8);
     }
 
     fn ccf(&mut self) {
         self.set_flag(Flags::HalfCarry, self.flag(Flags::Carry));
         self.set_flag(Flags::Subtract, false);
         self.set_flag(Flags::Carry, !self.flag(Flags::Carry));
-
-        self.tick_clock(4);
     }
 
     fn scf(&mut self) {
         self.set_flag(Flags::HalfCarry, false);
         self.set_flag(Flags::Subtract, false);
-        self.set_flag(Flags::Sign, true);
-
-        self.tick_clock(4);
+        self.set_flag(Flags::Carry, true);
     }
 
-    pub fn halt(&mut self) {
+    /// Stop at the HALT the PC points at until an interrupt is accepted, which returns to
+    /// the instruction after it
+    pub(crate) fn halt(&mut self) {
         self.halted = true;
+    }
 
-        self.tick_clock(4);
+    /// Leave the HALT that stopped the cpu, moving on to the instruction after it
+    fn wake(&mut self) {
+        if self.halted {
+            self.halted = false;
+            self.inc_pc();
+        }
     }
 
     pub fn reset(&mut self) {
@@ -1758,54 +1620,63 @@ impl Cpu {
         self.set_reg_value(RegisterCode::R, 0);
         self.iff1 = RESET;
         self.iff2 = RESET;
+        self.interrupt_mode = 0;
+    }
+
+    /// Put the cpu into the state it is in when the console is switched on
+    ///
+    /// This performs a reset (PC, I and R cleared, interrupts disabled) and then sets AF
+    /// and SP to 0xFFFF.  The remaining registers, including the alternate set, are
+    /// undefined on hardware and are also set to 0xFFFF so that software that relies on
+    /// them being cleared is easy to spot.
+    pub fn power_on(&mut self) {
+        self.reset();
+        self.halted = false;
+        self.interrupt_count = 0;
+        self.reg = [0xFF; 8];
+        self.alt_reg = [0xFF; 8];
+        self.set_reg_value_16(RegisterCode16::SP, 0xFFFF);
+        self.set_reg_value_16(RegisterCode16::IX, 0xFFFF);
+        self.set_reg_value_16(RegisterCode16::IY, 0xFFFF);
     }
 
     fn disable_intrpt(&mut self) {
         self.iff2 = false;
         self.iff1 = false;
-
-        self.tick_clock(4);
     }
 
     fn enable_intrpt(&mut self) {
         self.iff2 = true;
         self.iff1 = true;
         self.interrupt_count = 2;
-
-        self.tick_clock(4);
     }
 
     /* ====================== CALL and RET ============================= */
 
     fn call_addr(&mut self, addr: u16) {
+        self.tick_clock(1);
         self.push_pc();
         self.set_reg_value_16(RegisterCode16::PC, addr);
-
-        //println!("Calling 0x{:x}", addr);
-
-        self.tick_clock(17);
     }
 
     fn call_cond_addr(&mut self, addr: u16, flag: Flags, is_set: bool) {
         if self.flag(flag) != is_set {
-            self.tick_clock(10);
             return;
         }
 
+        self.tick_clock(1);
         self.push_pc();
         self.set_reg_value_16(RegisterCode16::PC, addr);
-        self.tick_clock(17);
+        self.add_ticks(timing::CALL_TAKEN);
     }
 
     fn ret(&mut self) {
         self.pop_pc();
-
-        self.tick_clock(10);
     }
 
     fn ret_cond(&mut self, flag: Flags, is_set: bool) {
+        self.tick_clock(1);
         if self.flag(flag) != is_set {
-            self.tick_clock(5);
             return;
         }
 
@@ -1813,50 +1684,75 @@ impl Cpu {
         let high = self.pop() as u16;
 
         self.set_reg_value_16(RegisterCode16::PC, (high << 8) | low);
-        self.tick_clock(11);
+        self.add_ticks(timing::RET_TAKEN);
     }
 
     fn rst_lit(&mut self, offset: u8) {
         let offset = offset as u16;
 
+        self.tick_clock(1);
         self.push_pc();
 
         self.set_reg_value_16(RegisterCode16::PC, offset);
-        self.tick_clock(11);
     }
 
     fn retn(&mut self) {
         self.iff1 = self.iff2;
 
         self.pop_pc();
-        self.tick_clock(14);
     }
 
     fn reti(&mut self) {
         self.pop_pc();
-        self.tick_clock(14);
     }
 
-    /// This is not supported in the sg-1000 and is a no op
-    fn interrupt_0(&mut self) {
-        self.tick_clock(8);
+    /// IM 0, 1 or 2: how the cpu answers an interrupt
+    fn set_interrupt_mode(&mut self, mode: u8) {
+        self.interrupt_mode = mode;
     }
 
-    fn interrupt_1(&mut self) {
-        if self.iff1 && self.interrupt_count == 0 {
-            self.halted = false;
-            self.push_pc();
-            self.set_reg_value_16(RegisterCode16::PC, 0x0038);
-            self.tick_clock(8);
+    /// Answer an interrupt request, which is only done while interrupts are enabled
+    ///
+    /// Nothing drives the data bus during the acknowledge on the SG-1000, so it reads 0xFF:
+    /// mode 0 runs RST 38h like mode 1 and mode 2 finds its handler at (I << 8) | 0xFF.
+    fn accept_interrupt(&mut self) {
+        bus::log!(Cpu, Debug, "interrupt at 0x{:04x}", self.get_pc());
+        self.wake();
+        self.iff1 = false;
+        self.iff2 = false;
+
+        // the acknowledge is an opcode fetch with 2 wait states
+        self.m_cycle(MCycle::InterruptAck, 6);
+        self.inc_refresh();
+        self.tick_clock(1);
+        self.push_pc();
+        match self.interrupt_mode {
+            2 => {
+                self.add_ticks(timing::IM2);
+                let vector = ((self.reg_value(RegisterCode::I) as u16) << 8) | 0xFF;
+                let low = self.fetch(vector) as u16;
+                let high = self.fetch(vector.wrapping_add(1)) as u16;
+                self.set_reg_value_16(RegisterCode16::PC, (high << 8) | low);
+            }
+            mode => {
+                self.add_ticks(if mode == 0 { timing::IM0 } else { timing::IM1 });
+                self.set_reg_value_16(RegisterCode16::PC, 0x0038);
+            }
         }
     }
 
     fn interrupt_nomask(&mut self) {
+        bus::log!(Cpu, Debug, "nmi at 0x{:04x}", self.get_pc());
+        self.wake();
+        // the opcode is fetched and ignored
+        let pc = self.get_pc();
+        self.m1_cycle(pc);
+        self.tick_clock(1);
         self.push_pc();
         self.iff2 = self.iff1;
         self.iff1 = false;
         self.set_reg_value_16(RegisterCode16::PC, 0x0066);
-        self.tick_clock(11);
+        self.add_ticks(timing::NMI);
     }
 
     fn ld_id(&mut self, is_inc: bool) {
@@ -1866,12 +1762,12 @@ impl Cpu {
         let val = self.fetch(src);
         self.store(dst, val);
 
-        let inc = |val: u16| (((val as u32) + 1) % 0xFFFF) as u16;
-        let dec = |val: u16| if val == 0 { 0xFFFF } else { val - 1 };
+        let inc = |val: u16| val.wrapping_add(1);
+        let dec = |val: u16| val.wrapping_sub(1);
 
         if is_inc {
             src = inc(src);
-            dst = inc(src);
+            dst = inc(dst);
         } else {
             src = dec(src);
             dst = dec(dst);
@@ -1887,8 +1783,6 @@ impl Cpu {
         self.set_flag(Flags::HalfCarry, RESET);
         self.set_flag(Flags::OverflowParity, bc != 0);
         self.set_flag(Flags::Subtract, RESET);
-
-        self.tick_clock(16);
     }
 
     fn ld_id_r(&mut self, is_inc: bool) {
@@ -1899,9 +1793,9 @@ impl Cpu {
         // non-maskable interrupts from perripherals
         if self.reg_value_16(RegisterCode16::BC) > 0 {
             let addr = self.reg_value_16(RegisterCode16::PC);
-            self.set_reg_value_16(RegisterCode16::PC, addr - 2);
+            self.set_reg_value_16(RegisterCode16::PC, addr.wrapping_sub(2));
             // we only have extra clock ticks if we need to change the PC to repeat the command
-            self.tick_clock(5);
+            self.add_ticks(timing::REPEAT);
         } else {
             // we are done repeating, so we can now set the flags
             self.set_flag(Flags::HalfCarry, RESET);
@@ -1911,8 +1805,8 @@ impl Cpu {
     }
 
     fn cp_id(&mut self, is_inc: bool) {
-        let inc = |val: u16| (((val as u32) + 1) % 0xFFFF) as u16;
-        let dec = |val: u16| if val == 0 { 0xFFFF } else { val - 1 };
+        let inc = |val: u16| val.wrapping_add(1);
+        let dec = |val: u16| val.wrapping_sub(1);
 
         let mut hl = self.reg_value_16(RegisterCode16::HL);
         let val = self.fetch(hl);
@@ -1942,13 +1836,13 @@ impl Cpu {
         // the cpu to register non-maskable interrupts from perripherals
         if self.reg_value_16(RegisterCode16::BC) > 0 && !self.flag(Flags::Zero) {
             let addr = self.reg_value_16(RegisterCode16::PC);
-            self.set_reg_value_16(RegisterCode16::PC, addr - 2);
+            self.set_reg_value_16(RegisterCode16::PC, addr.wrapping_sub(2));
             // we only have extra clock ticks if we need to change the PC to repeat the command
-            self.tick_clock(5);
+            self.add_ticks(timing::REPEAT);
         }
     }
 
-    fn rrd(&mut self) {
+    fn rld(&mut self) {
         let mut acc = self.reg_value(RegisterCode::A);
         let addr = self.reg_value_16(RegisterCode16::HL);
         let mut val = self.fetch(addr);
@@ -1957,19 +1851,14 @@ impl Cpu {
         val = ((val & 0b1111) << 4) | acc & 0b1111; // set the high bits to the low bits and the low bits to the low bits of the accumulator
         acc = (acc & 0b1111_0000) | temp;
 
-        self.set_flag(Flags::Sign, acc >= 0x80);
-        self.set_flag(Flags::Zero, acc == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(acc as u32));
-        self.set_flag(Flags::Subtract, false);
+        self.set_flags(flags::SZHPN, flags::SZP[acc as usize]);
 
+        self.tick_clock(4);
         self.store(addr, val);
         self.set_reg_value(RegisterCode::A, acc as u16);
-
-        self.tick_clock(18);
     }
 
-    fn rld(&mut self) {
+    fn rrd(&mut self) {
         let mut acc = self.reg_value(RegisterCode::A);
         let addr = self.reg_value_16(RegisterCode16::HL);
         let mut val = self.fetch(addr);
@@ -1978,40 +1867,39 @@ impl Cpu {
         val = ((acc & 0b1111) << 4) | val >> 4; // set the high bits to the low bits and the low bits to the low bits of the accumulator
         acc = (acc & 0b1111_0000) | temp;
 
-        self.set_flag(Flags::Sign, acc >= 0x80);
-        self.set_flag(Flags::Zero, acc == 0);
-        self.set_flag(Flags::HalfCarry, false);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(acc as u32));
-        self.set_flag(Flags::Subtract, false);
+        self.set_flags(flags::SZHPN, flags::SZP[acc as usize]);
 
+        self.tick_clock(4);
         self.store(addr, val);
         self.set_reg_value(RegisterCode::A, acc as u16);
-
-        self.tick_clock(18);
     }
 
     fn test_bit_val(val: u8, bit: u8) -> bool {
         (val >> bit) & 1 > 0
     }
 
-    fn test_bit_reg(&mut self, src: RegisterCode, bit: u8) {
-        let reg = self.reg_value(src);
-        let is_set = Cpu::test_bit_val(reg, bit);
-
+    /// Z is set if the bit is clear, and P/V is a copy of it.  S is only set when bit 7 is
+    /// set, as if the result were the tested bit alone.
+    fn set_bit_flags(&mut self, bit: u8, is_set: bool) {
+        self.set_flag(Flags::Sign, bit == 7 && is_set);
         self.set_flag(Flags::Zero, !is_set);
+        self.set_flag(Flags::OverflowParity, !is_set);
         self.set_flag(Flags::HalfCarry, true);
         self.set_flag(Flags::Subtract, false);
-        self.tick_clock(8);
+    }
+
+    fn test_bit_reg(&mut self, src: RegisterCode, bit: u8) {
+        let reg = self.reg_value(src);
+        let is_set = Self::test_bit_val(reg, bit);
+
+        self.set_bit_flags(bit, is_set);
     }
 
     fn test_bit_addr(&mut self, addr: u16, bit: u8) {
         let val = self.fetch(addr);
-        let is_set = Cpu::test_bit_val(val, bit);
+        let is_set = Self::test_bit_val(val, bit);
 
-        self.set_flag(Flags::Zero, !is_set);
-        self.set_flag(Flags::HalfCarry, true);
-        self.set_flag(Flags::Subtract, false);
-        self.tick_clock(12);
+        self.set_bit_flags(bit, is_set);
     }
 
     fn res_bit(val: u8, bit: u8) -> u8 {
@@ -2025,29 +1913,31 @@ impl Cpu {
     fn change_bit_reg(&mut self, src: RegisterCode, bit: u8, set: bool) {
         let val = self.reg_value(src);
         let output = if set {
-            Cpu::set_bit(val, bit)
+            Self::set_bit(val, bit)
         } else {
-            Cpu::res_bit(val, bit)
+            Self::res_bit(val, bit)
         };
 
         self.set_reg_value(src, output as u16);
-        self.tick_clock(8);
     }
 
     fn change_bit_addr(&mut self, addr: u16, bit: u8, set: bool) {
         let val = self.fetch(addr);
         let output = if set {
-            Cpu::set_bit(val, bit)
+            Self::set_bit(val, bit)
         } else {
-            Cpu::res_bit(val, bit)
+            Self::res_bit(val, bit)
         };
 
+        self.tick_clock(1);
+
         self.store(addr, output);
-        self.tick_clock(15);
     }
 
     fn out_addr_val(&mut self, addr: u16, val: u8) {
-        self.io_bus.borrow_mut().cpu_write(addr, val);
+        bus::log!(Io, Trace, "out 0x{:02x} <- 0x{:02x}", addr & 0xFF, val);
+        self.m_cycle(MCycle::Output(addr, val), 4);
+        self.bus.output(addr, val);
     }
 
     fn out_a_lit(&mut self) {
@@ -2057,7 +1947,6 @@ impl Cpu {
 
         let addr = (addr_high << 8) | addr_low;
         self.out_addr_val(addr, val);
-        self.tick_clock(11);
     }
 
     fn out_c_reg(&mut self, src: Option<RegisterCode>) {
@@ -2065,10 +1954,10 @@ impl Cpu {
         let dst = self.reg_value_16(RegisterCode16::BC);
 
         self.out_addr_val(dst, val);
-        self.tick_clock(12);
     }
 
     fn out_id(&mut self, inc: bool) {
+        self.tick_clock(1);
         let mut hl = self.reg_value_16(RegisterCode16::HL);
         let val = self.fetch(hl);
 
@@ -2080,35 +1969,32 @@ impl Cpu {
 
         self.out_addr_val(addr, val);
 
-        if inc {
-            hl = (((hl as u32) + 1) % 0xFFFF) as u16;
+        hl = if inc {
+            hl.wrapping_add(1)
         } else {
-            hl = if hl == 0 { 0xFFFF } else { hl - 1 };
-        }
+            hl.wrapping_sub(1)
+        };
         self.set_reg_value_16(RegisterCode16::HL, hl);
 
         // set flags
         self.set_flag(Flags::Zero, b == 0);
         self.set_flag(Flags::Subtract, SET);
-        self.tick_clock(16);
     }
 
     fn out_id_rep(&mut self, inc: bool) {
         self.out_id(inc);
-        //println!("B after OutIR: {}", self.reg_value(RegisterCode::B));
-
         if self.reg_value(RegisterCode::B) != 0 {
-            self.tick_clock(5);
+            self.add_ticks(timing::REPEAT);
             let pc = self.get_pc();
-            self.set_pc(if pc < 2 { 0xFFFF - pc + 1 } else { pc - 2 });
+            self.set_pc(pc.wrapping_sub(2));
         }
     }
 
-    fn in_addr(&self, addr: u16) -> u8 {
-        self.io_bus.borrow_mut().cpu_read(addr).expect(&format!(
-            "Attempting to read from IO bus at 0x{:04x} but there was no mapping for that address",
-            addr
-        ))
+    fn in_addr(&mut self, addr: u16) -> u8 {
+        self.m_cycle(MCycle::Input(addr), 4);
+        let val = self.bus.input(addr);
+        bus::log!(Io, Trace, "in 0x{:02x} -> 0x{:02x}", addr & 0xFF, val);
+        val
     }
 
     fn in_a_lit(&mut self) {
@@ -2118,7 +2004,6 @@ impl Cpu {
         let val = self.in_addr(addr);
 
         self.set_reg_value(RegisterCode::A, val as u16);
-        self.tick_clock(11);
     }
 
     /// dst is set to `Some` if we store the value to a register or `None` if it
@@ -2129,16 +2014,11 @@ impl Cpu {
         let val = self.in_addr(addr);
         dst.map(|reg| self.set_reg_value(reg, val as u16));
 
-        self.set_flag(Flags::Sign, val >= 0x80);
-        self.set_flag(Flags::Zero, val == 0);
-        self.set_flag(Flags::HalfCarry, RESET);
-        self.set_flag(Flags::OverflowParity, Cpu::parity_even(val.into()));
-        self.set_flag(Flags::Subtract, RESET);
-
-        self.tick_clock(12);
+        self.set_flags(flags::SZHPN, flags::SZP[val as usize]);
     }
 
     fn in_id(&mut self, inc: bool) {
+        self.tick_clock(1);
         let mut hl = self.reg_value_16(RegisterCode16::HL);
 
         let mut b = self.reg_value(RegisterCode::B);
@@ -2150,109 +2030,146 @@ impl Cpu {
         let val = self.in_addr(addr);
         self.store(hl, val);
 
-        if inc {
-            hl = (((hl as u32) + 1) % 0xFFFF) as u16;
+        hl = if inc {
+            hl.wrapping_add(1)
         } else {
-            hl = if hl == 0 { 0xFFFF } else { hl - 1 };
-        }
+            hl.wrapping_sub(1)
+        };
         self.set_reg_value_16(RegisterCode16::HL, hl);
 
         // set flags
         self.set_flag(Flags::Zero, b == 0);
         self.set_flag(Flags::Subtract, SET);
-        self.tick_clock(16);
     }
 
     fn in_id_rep(&mut self, inc: bool) {
         self.in_id(inc);
 
         if self.reg_value(RegisterCode::B) != 0 {
-            self.tick_clock(5);
+            self.add_ticks(timing::REPEAT);
             let pc = self.get_pc();
-            self.set_pc(if pc < 2 { 0xFFFF - pc + 1 } else { pc - 2 });
+            self.set_pc(pc.wrapping_sub(2));
         }
     }
 }
 
 /* --------------------------------- TESTING --------------------------------- */
 
-pub trait BitsOperator {
-    fn pre_operate(&mut self, _cpu: &mut Cpu, _src: RegisterCode) {}
-    fn post_operate(&mut self, _cpu: &mut Cpu, _src: RegisterCode) {}
-    fn pointer(&mut self, _cpu: &mut Cpu) -> u16;
-    fn prepare(&mut self, _cpu: &mut Cpu) {}
+pub trait BitsOperator<B: Z80Bus> {
+    fn pre_operate(&mut self, _cpu: &mut Cpu<B>, _src: RegisterCode) {}
+    fn post_operate(&mut self, _cpu: &mut Cpu<B>, _src: RegisterCode) {}
+    fn pointer(&mut self, _cpu: &mut Cpu<B>) -> u16;
+    fn test_bit(&mut self, cpu: &mut Cpu<B>, src: RegisterCode, bit: u8) {
+        cpu.test_bit_reg(src, bit);
+    }
 }
 
 struct BitsOperatorDefault {}
 
-impl BitsOperator for BitsOperatorDefault {
-    fn pointer(&mut self, cpu: &mut Cpu) -> u16 {
+impl<B: Z80Bus> BitsOperator<B> for BitsOperatorDefault {
+    fn pointer(&mut self, cpu: &mut Cpu<B>) -> u16 {
         cpu.indirect_reg_addr(RegisterCode16::HL)
     }
 }
 
+/// (IX+d) or (IY+d) of a DD CB or FD CB instruction, whose displacement comes before the
+/// opcode
+///
+/// Every instruction works on the memory, the register of the opcode gets a copy of the
+/// result.
 struct IndexedBitsOperator {
-    reg: RegisterCode16,
-    addr: Option<u16>,
+    addr: u16,
 }
 
 impl IndexedBitsOperator {
-    pub fn new(reg: RegisterCode16) -> IndexedBitsOperator {
-        IndexedBitsOperator { reg, addr: None }
+    pub fn new(addr: u16) -> IndexedBitsOperator {
+        IndexedBitsOperator { addr }
     }
 }
 
-impl BitsOperator for IndexedBitsOperator {
-    fn pre_operate(&mut self, cpu: &mut Cpu, src: RegisterCode) {
-        if self.addr.is_none() {
-            self.addr = Some(cpu.index_addr(self.reg));
-        }
-
-        let val = cpu.fetch(self.addr.unwrap());
+impl<B: Z80Bus> BitsOperator<B> for IndexedBitsOperator {
+    fn pre_operate(&mut self, cpu: &mut Cpu<B>, src: RegisterCode) {
+        let val = cpu.fetch(self.addr);
         cpu.set_reg_value(src, val as u16);
-        cpu.queue_clock_tick(4);
     }
 
-    fn post_operate(&mut self, cpu: &mut Cpu, src: RegisterCode) {
-        if self.addr.is_none() {
-            self.addr = Some(cpu.index_addr(self.reg));
-        }
-
+    fn post_operate(&mut self, cpu: &mut Cpu<B>, src: RegisterCode) {
         let val = cpu.reg_value(src);
-        cpu.store(self.addr.unwrap(), val);
-        cpu.queue_clock_tick(4);
+        cpu.tick_clock(1);
+        cpu.store(self.addr, val);
     }
 
-    fn pointer(&mut self, cpu: &mut Cpu) -> u16 {
-        cpu.index_addr(self.reg)
+    fn pointer(&mut self, _cpu: &mut Cpu<B>) -> u16 {
+        self.addr
     }
 
-    fn prepare(&mut self, cpu: &mut Cpu) {
-        self.addr = Some(cpu.index_addr(self.reg));
+    /// `bit n, (IX+d)` whichever register the opcode names
+    fn test_bit(&mut self, cpu: &mut Cpu<B>, _src: RegisterCode, bit: u8) {
+        cpu.test_bit_addr(self.addr, bit);
+    }
+}
+
+/// The registers and interrupt state of the cpu, the `CpuState` and the lines and timing
+/// that are not part of it.  The buses are not part of the state.
+impl<B: Z80Bus> SaveState for Cpu<B> {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        let state = self.state();
+        out.extend_from_slice(&self.clock.to_le_bytes());
+        for reg in &[
+            state.af, state.bc, state.de, state.hl,
+            state.af_alt, state.bc_alt, state.de_alt, state.hl_alt,
+            state.ix, state.iy, state.sp, state.pc,
+        ] {
+            out.extend_from_slice(&reg.to_le_bytes());
+        }
+        out.push(state.i);
+        out.push(state.r);
+        out.push(state.iff1 as u8);
+        out.push(state.iff2 as u8);
+        out.push(state.interrupt_mode);
+        out.push(state.halted as u8);
+        out.push(self.interrupt_count);
+        out.push(self.reset_req as u8);
+        out.push(self.nomask_interrupt as u8);
+        out.push(self.mask_interrupt as u8);
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        self.clock = take_u64(state);
+        let mut cpu = CpuState::default();
+        for reg in &mut [
+            &mut cpu.af, &mut cpu.bc, &mut cpu.de, &mut cpu.hl,
+            &mut cpu.af_alt, &mut cpu.bc_alt, &mut cpu.de_alt, &mut cpu.hl_alt,
+            &mut cpu.ix, &mut cpu.iy, &mut cpu.sp, &mut cpu.pc,
+        ] {
+            **reg = take_u16(state);
+        }
+        cpu.i = take_u8(state);
+        cpu.r = take_u8(state);
+        cpu.iff1 = take_bool(state);
+        cpu.iff2 = take_bool(state);
+        cpu.interrupt_mode = take_u8(state);
+        cpu.halted = take_bool(state);
+        self.set_state(&cpu);
+        self.interrupt_count = take_u8(state);
+        self.reset_req = take_bool(state);
+        self.nomask_interrupt = take_bool(state);
+        self.mask_interrupt = take_bool(state);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{cell::RefCell, rc::Rc};
 
     #[inline]
-    fn get_cpu() -> Cpu {
-        Cpu::new(
-            &Rc::new(RefCell::new(Bus::new(vec![Rc::new(RefCell::new(vec![
-                0xab, 0xcd, 0xef,
-            ]))]))),
-            &Rc::new(RefCell::new(Bus::default())),
-        )
+    fn get_cpu() -> Cpu<Vec<u8>> {
+        Cpu::with_bus(vec![0xab, 0xcd, 0xef])
     }
 
     #[test]
     fn test_inc_clock() {
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::default())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(Vec::new());
         assert_eq!(0, cpu.clock());
 
         cpu.tick_clock(1);
@@ -2264,20 +2181,14 @@ mod tests {
 
     #[test]
     fn test_set_reg_a() {
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::default())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(Vec::new());
         cpu.set_reg_value(RegisterCode::A, 10);
         assert_eq!(10, cpu.reg_value(RegisterCode::A));
     }
 
     #[test]
     fn test_register_16() {
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::default())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(Vec::new());
         cpu.set_reg_value(RegisterCode::B, 0xBB);
         cpu.set_reg_value(RegisterCode::C, 0xCC);
         assert_eq!(0xBBCC, cpu.reg_value_16(RegisterCode16::BC));
@@ -2285,12 +2196,7 @@ mod tests {
 
     #[test]
     fn test_immediate_addressing() {
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::new(vec![Rc::new(RefCell::new(vec![
-                0xab, 0xbc, 0xde,
-            ]))]))),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(vec![0xab, 0xbc, 0xde]);
 
         assert_eq!(0xab, cpu.imm_addr());
     }
@@ -2298,24 +2204,14 @@ mod tests {
     #[test]
     // note that this uses two bytes and we are in little endian order
     fn test_immediate_extended_addressing() {
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::new(vec![Rc::new(RefCell::new(vec![
-                0xab, 0xcd, 0xef,
-            ]))]))),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(vec![0xab, 0xcd, 0xef]);
 
         assert_eq!(0xcdab, cpu.imm_addr_ex());
     }
 
     #[test]
     fn test_relative_addressing() {
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::new(vec![Rc::new(RefCell::new(vec![
-                0xff, 0xff, 0,
-            ]))]))),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(vec![0xff, 0xff, 0]);
         assert_eq!(0, cpu.rel_addr());
 
         let pc = cpu.get_pc() as i16;
@@ -2331,10 +2227,7 @@ mod tests {
             vec[i] = (i % 0xff) as u8;
         }
 
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::new(vec![Rc::new(RefCell::new(vec))]))),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(vec);
         // we have vec[0, 1, 2, 3, 4, ..., 0xff, 0, 1, 2, 3, 4]
         cpu.set_pc(0xf0); // 0xf0 = 240 or 0xf0 = -16
         assert_eq!(0xf1 - 16, cpu.rel_addr());
@@ -2477,6 +2370,13 @@ mod tests {
         assert_eq!(true, cpu.flag(Flags::Sign));
         assert_eq!(true, cpu.flag(Flags::HalfCarry));
         assert_eq!(true, cpu.flag(Flags::Subtract));
+
+        // the sign is bit 7 of the result, 0x80 included
+        cpu.set_reg_value(RegisterCode::A, 0x81);
+        cpu.dec_reg(RegisterCode::A);
+        assert_eq!(0x80, cpu.reg_value(RegisterCode::A));
+        assert_eq!(true, cpu.flag(Flags::Sign));
+        assert_eq!(false, cpu.flag(Flags::HalfCarry));
     }
 
     #[test]
@@ -2535,6 +2435,18 @@ mod tests {
         let result = cpu.sub_val_val(127, 5, false);
         assert_eq!(122, result);
         assert_eq!(false, cpu.flag(Flags::OverflowParity));
+        assert_eq!(false, cpu.flag(Flags::Carry));
+
+        let result = cpu.sub_val_val(0x90, 0x10, false);
+        assert_eq!(0x80, result);
+        assert_eq!(true, cpu.flag(Flags::Sign));
+        assert_eq!(false, cpu.flag(Flags::Carry));
+
+        let result = cpu.sub_val_val(0x10, 0x0F, true);
+        assert_eq!(0, result);
+        assert_eq!(true, cpu.flag(Flags::Zero));
+        assert_eq!(true, cpu.flag(Flags::HalfCarry));
+        assert_eq!(false, cpu.flag(Flags::Carry));
 
         let result = cpu.sub_val_val(1, 0xFF, false);
         assert_eq!(2, result);
@@ -2545,6 +2457,597 @@ mod tests {
         assert_eq!(false, cpu.flag(Flags::Zero));
     }
 
+    #[test]
+    fn test_alu_flags() {
+        use RegisterCode16::{AF, BC, DE, HL, SP};
+
+        // the program, AF before it and the register pairs and (HL) after it, with
+        // HL = 0x4000 and 0x12E3 at (HL)
+        let cases: &[(&[u8], u16, &[(RegisterCode16, u16)], u8)] = &[
+            (&[0x27], 0x3C00, &[(AF, 0x4214)], 0xE3),       // daa after 0x15 + 0x27
+            (&[0x27], 0x2D12, &[(AF, 0x2706)], 0xE3),       // daa after 0x42 - 0x15
+            (&[0x27], 0x9A00, &[(AF, 0x0055)], 0xE3),       // daa, carry out
+            (&[0x37], 0x10D2, &[(AF, 0x10C1)], 0xE3),       // scf
+            (&[0x3F], 0x1001, &[(AF, 0x1010)], 0xE3),       // ccf
+            (&[0x17], 0x8000, &[(AF, 0x0001)], 0xE3),       // rla
+            (&[0x1F], 0x0100, &[(AF, 0x0001)], 0xE3),       // rra
+            (&[0x1F], 0x0201, &[(AF, 0x8100)], 0xE3),       // rra, carry in
+            (&[0xED, 0x67], 0x1900, &[(AF, 0x1300)], 0x9E), // rrd
+            (&[0xED, 0x6F], 0x1900, &[(AF, 0x1E04)], 0x39), // rld
+            (&[0xCB, 0x7E], 0x1001, &[(AF, 0x1091)], 0xE3), // bit 7, (hl)
+            (&[0xCB, 0x56], 0x1081, &[(AF, 0x1055)], 0xE3), // bit 2, (hl)
+            (&[0xCE, 0x0F], 0x7001, &[(AF, 0x8094)], 0xE3), // adc a, 0x0F
+            (&[0xD6, 0x01], 0x8000, &[(AF, 0x7F16)], 0xE3), // sub 0x01
+            (&[0xDE, 0x0F], 0x1001, &[(AF, 0x0052)], 0xE3), // sbc a, 0x0F
+            (&[0xFE, 0x90], 0x1000, &[(AF, 0x1087)], 0xE3), // cp 0x90
+            // the ED loads read the pair from memory
+            (&[0xED, 0x4B, 0x00, 0x40], 0x0000, &[(BC, 0x12E3)], 0xE3), // ld bc, (0x4000)
+            (&[0xED, 0x5B, 0x00, 0x40], 0x0000, &[(DE, 0x12E3)], 0xE3), // ld de, (0x4000)
+            (&[0xED, 0x6B, 0x00, 0x40], 0x0000, &[(HL, 0x12E3)], 0xE3), // ld hl, (0x4000)
+            (&[0xED, 0x7B, 0x00, 0x40], 0x0000, &[(SP, 0x12E3)], 0xE3), // ld sp, (0x4000)
+        ];
+
+        for (program, af, regs, at_hl) in cases {
+            let mut cpu = recorder(program);
+            cpu.set_reg_value_16(AF, *af);
+            cpu.bus_mut().memory[0x4000] = 0xE3;
+            cpu.bus_mut().memory[0x4001] = 0x12;
+
+            cpu.do_operation();
+            for &(reg, val) in regs.iter() {
+                assert_eq!(val, cpu.reg_value_16(reg), "{:02x?} {:?}", program, reg);
+            }
+            assert_eq!(*at_hl, cpu.bus().memory[0x4000], "{:02x?}", program);
+        }
+    }
+
+    #[test]
+    fn test_power_on() {
+        let mut cpu = get_cpu();
+        cpu.set_pc(0x1234);
+        cpu.enable_intrpt();
+
+        cpu.power_on();
+        assert_eq!(0, cpu.get_pc());
+        assert_eq!(0xFFFF, cpu.reg_value_16(RegisterCode16::AF));
+        assert_eq!(0xFFFF, cpu.reg_value_16(RegisterCode16::SP));
+        assert_eq!(0, cpu.reg_value(RegisterCode::I));
+        assert_eq!(0, cpu.reg_value(RegisterCode::R));
+        assert!(!cpu.iff1);
+        assert!(!cpu.iff2);
+    }
+
+    #[test]
+    fn test_save_state() {
+        let mut cpu = get_cpu();
+        cpu.power_on();
+        cpu.set_reg_value_16(RegisterCode16::HL, 0xBEEF);
+        cpu.exx();
+        cpu.set_pc(0x1234);
+        cpu.set_reg_value_16(RegisterCode16::HL, 0xCAFE);
+        cpu.set_reg_value(RegisterCode::R, 0x85);
+        cpu.enable_intrpt();
+        let mut state = Vec::new();
+        cpu.save_state(&mut state);
+
+        let mut other = get_cpu();
+        let mut rest = state.as_slice();
+        other.load_state(&mut rest);
+        assert!(rest.is_empty());
+        assert_eq!(0x1234, other.get_pc());
+        assert_eq!(0xCAFE, other.reg_value_16(RegisterCode16::HL));
+        assert_eq!(0xBEEF, other.state().hl_alt);
+        assert_eq!(0x85, other.reg_value(RegisterCode::R));
+        assert_eq!(0xFFFF, other.reg_value_16(RegisterCode16::IX));
+        assert_eq!(cpu.clock(), other.clock());
+        assert!(other.iff1);
+        assert_eq!(2, other.interrupt_count);
+        assert_eq!(cpu.state(), other.state());
+    }
+
+    /// 64K of ram that records the machine cycles, each one `wait` T-states longer
+    struct Recorder {
+        memory: Vec<u8>,
+        cycles: Vec<(u64, MCycle)>,
+        wait: u64,
+    }
+
+    impl Z80Bus for Recorder {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.memory[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            self.memory[addr as usize] = val;
+        }
+
+        fn input(&mut self, _port: u16) -> u8 {
+            0xFF
+        }
+
+        fn output(&mut self, _port: u16, _val: u8) {}
+
+        fn m_cycle(&mut self, clock: u64, cycle: MCycle) -> u64 {
+            self.cycles.push((clock, cycle));
+            self.wait
+        }
+    }
+
+    fn recorder(program: &[u8]) -> Cpu<Recorder> {
+        let mut memory = vec![0; 0x10000];
+        memory[..program.len()].copy_from_slice(program);
+
+        let mut cpu = Cpu::with_bus(Recorder { memory, cycles: Vec::new(), wait: 0 });
+        cpu.power_on();
+        for reg in [RegisterCode16::HL, RegisterCode16::DE, RegisterCode16::IX, RegisterCode16::IY]
+        {
+            cpu.set_reg_value_16(reg, 0x4000);
+        }
+        cpu.set_reg_value_16(RegisterCode16::SP, 0x8000);
+        cpu
+    }
+
+    /// The flags that make the condition of `jp cc`, `jr cc`, `call cc` or `ret cc` fail
+    fn untaken_flags(opcode: u8) -> u8 {
+        let cc = match opcode {
+            0x20 | 0x28 | 0x30 | 0x38 => (opcode >> 3) & 0x03,
+            _ if opcode & 0xC7 == 0xC0 || opcode & 0xC7 == 0xC2 || opcode & 0xC7 == 0xC4 => {
+                (opcode >> 3) & 0x07
+            }
+            _ => return 0,
+        };
+        // nz, nc, po and p fail when their flag is set, the others when it is clear
+        if cc & 1 == 0 {
+            [flags::ZERO, flags::CARRY, flags::OVERFLOW_PARITY, flags::SIGN][cc as usize >> 1]
+        } else {
+            0
+        }
+    }
+
+    #[test]
+    fn test_instruction_timing() {
+        let prefixes: [(&[u8], &[u8; 256]); 7] = [
+            (&[], &timing::MAIN),
+            (&[0xCB], &timing::CB),
+            (&[0xED], &timing::ED),
+            (&[0xDD], &timing::XY),
+            (&[0xFD], &timing::XY),
+            (&[0xDD, 0xCB, 0x00], &timing::XYCB),
+            (&[0xFD, 0xCB, 0x00], &timing::XYCB),
+        ];
+
+        for (prefix, table) in prefixes.iter() {
+            for opcode in 0..=0xFF {
+                let unprefixed = prefix.is_empty() || *prefix == [0xDD] || *prefix == [0xFD];
+                if unprefixed && [0xCB, 0xDD, 0xED, 0xFD].contains(&opcode) {
+                    continue;
+                }
+
+                let mut program = prefix.to_vec();
+                program.push(opcode);
+                let mut cpu = recorder(&program);
+                let flags = if unprefixed { untaken_flags(opcode) } else { 0 };
+                cpu.set_reg_value(RegisterCode::Flags, flags as u16);
+                // B and BC run out together: no djnz, ldir or otir repeats
+                let bc = if *prefix == [0xED] && opcode & 0xF2 == 0xB0 { 0x0001 } else { 0x0101 };
+                cpu.set_reg_value_16(RegisterCode16::BC, bc);
+
+                let ticks = cpu.do_operation();
+                assert_eq!(table[opcode as usize] as u64, ticks, "{:02x?} {:02x}", prefix, opcode);
+                assert_eq!(ticks, cpu.clock());
+            }
+        }
+    }
+
+    #[test]
+    fn test_conditions_taken() {
+        for opcode in [0x10, 0x20, 0x28, 0x30, 0x38, 0xC0, 0xC4, 0xE8, 0xEC] {
+            let mut cpu = recorder(&[opcode]);
+            cpu.set_reg_value(RegisterCode::Flags, !untaken_flags(opcode) as u16);
+            cpu.set_reg_value(RegisterCode::B, 2);
+
+            let extra = match opcode {
+                0xC0 | 0xE8 => timing::RET_TAKEN,
+                0xC4 | 0xEC => timing::CALL_TAKEN,
+                _ => timing::JR_TAKEN,
+            };
+            assert_eq!((timing::MAIN[opcode as usize] + extra) as u64, cpu.do_operation());
+        }
+
+        let mut cpu = recorder(&[0xED, 0xB0]);
+        cpu.set_reg_value_16(RegisterCode16::BC, 2);
+        assert_eq!(21, cpu.do_operation());
+        assert_eq!(16, cpu.do_operation());
+    }
+
+    #[test]
+    fn test_m_cycles() {
+        // out (0x12), a
+        let mut cpu = recorder(&[0xD3, 0x12]);
+        cpu.set_reg_value(RegisterCode::A, 0x34);
+        assert_eq!(11, cpu.do_operation());
+        assert_eq!(
+            vec![(0, MCycle::Fetch(0)), (4, MCycle::Read(1)), (7, MCycle::Output(0x3412, 0x34))],
+            cpu.bus().cycles
+        );
+
+        // set 0, (ix+5): the displacement and the opcode are read, not fetched
+        let mut cpu = recorder(&[0xDD, 0xCB, 0x05, 0xC6]);
+        assert_eq!(23, cpu.do_operation());
+        assert_eq!(
+            vec![
+                (0, MCycle::Fetch(0)),
+                (4, MCycle::Fetch(1)),
+                (8, MCycle::Read(2)),
+                (11, MCycle::Read(3)),
+                (16, MCycle::Read(0x4005)),
+                (20, MCycle::Write(0x4005, 0x01)),
+            ],
+            cpu.bus().cycles
+        );
+
+        // every cycle waits a T-state, which adds up
+        let mut cpu = recorder(&[0xDD, 0xCB, 0x05, 0xC6]);
+        cpu.bus_mut().wait = 1;
+        assert_eq!(29, cpu.do_operation());
+        assert_eq!((25, MCycle::Write(0x4005, 0x01)), cpu.bus().cycles[5]);
+    }
+
+    #[test]
+    fn test_accept_interrupt() {
+        // im 1, then an interrupt once ei has let an instruction pass
+        let mut cpu = recorder(&[0xED, 0x56, 0xFB, 0x00, 0x00]);
+        cpu.do_operation();
+        cpu.do_operation();
+        cpu.do_operation();
+        cpu.mask_interrupt = true;
+        assert_eq!(timing::IM1 as u64, cpu.do_operation());
+        assert_eq!(0x0038, cpu.get_pc());
+        assert_eq!(0x7FFE, cpu.reg_value_16(RegisterCode16::SP));
+        assert_eq!(0x04, cpu.bus().memory[0x7FFE]);
+        assert!(!cpu.iff1);
+
+        // im 2 reads the handler from the table I points at
+        let mut cpu = recorder(&[0xED, 0x5E, 0xFB, 0x00, 0x00]);
+        cpu.set_reg_value(RegisterCode::I, 0x12);
+        cpu.bus_mut().memory[0x12FF] = 0x78;
+        cpu.bus_mut().memory[0x1300] = 0x56;
+        cpu.do_operation();
+        cpu.do_operation();
+        cpu.do_operation();
+        cpu.mask_interrupt = true;
+        assert_eq!(timing::IM2 as u64, cpu.do_operation());
+        assert_eq!(0x5678, cpu.get_pc());
+    }
+
+    #[test]
+    fn test_pending_interrupt() {
+        // di, nop, ei, nop, nop: the request waits for ei and the instruction after it
+        let mut cpu = recorder(&[0xED, 0x56, 0xF3, 0x00, 0xFB, 0x00, 0x00]);
+        cpu.do_operation();
+        cpu.mask_interrupt = true;
+        cpu.do_operation();
+        assert_eq!(4, cpu.do_operation());
+        assert_eq!(0x0004, cpu.get_pc());
+        cpu.do_operation();
+        cpu.do_operation();
+        assert_eq!(0x0006, cpu.get_pc());
+        assert_eq!(timing::IM1 as u64, cpu.do_operation());
+        assert_eq!(0x0038, cpu.get_pc());
+
+        // the line is still held, but interrupts are off until the handler enables them
+        assert!(cpu.mask_interrupt);
+        assert!(!cpu.iff1);
+        cpu.do_operation();
+        assert_eq!(0x0039, cpu.get_pc());
+    }
+
+    #[test]
+    fn test_index_halves() {
+        let mut cpu = get_cpu();
+        cpu.set_reg_value_16(RegisterCode16::IX, 0x1234);
+        cpu.set_reg_value_16(RegisterCode16::IY, 0x5678);
+        assert_eq!(0x12, cpu.reg_value(RegisterCode::IXh));
+        assert_eq!(0x34, cpu.reg_value(RegisterCode::IXl));
+        assert_eq!(0x56, cpu.reg_value(RegisterCode::IYh));
+        assert_eq!(0x78, cpu.reg_value(RegisterCode::IYl));
+
+        cpu.set_reg_value(RegisterCode::IXh, 0xAB);
+        cpu.set_reg_value(RegisterCode::IYl, 0xCD);
+        assert_eq!(0xAB34, cpu.reg_value_16(RegisterCode16::IX));
+        assert_eq!(0x56CD, cpu.reg_value_16(RegisterCode16::IY));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_undocumented_index() {
+        use RegisterCode16::{AF, BC, HL, IX, IY};
+
+        // the program, the register pairs after it and a byte of memory it wrote, starting
+        // from A = 0x10, F = 0, BC = 0x0203, HL = 0x0607, IX = 0x4020, IY = 0x5030 and
+        // 0x81 at (IX+5) and (IY+5)
+        let cases: &[(&[u8], &[(RegisterCode16, u16)], Option<(u16, u8)>)] = &[
+            // the halves of IX and IY stand in for H and L
+            (&[0xDD, 0x26, 0x12], &[(IX, 0x1220)], None),                   // ld ixh, 0x12
+            (&[0xFD, 0x2E, 0x34], &[(IY, 0x5034)], None),                   // ld iyl, 0x34
+            (&[0xDD, 0x44], &[(BC, 0x4003)], None),                         // ld b, ixh
+            (&[0xFD, 0x4D], &[(BC, 0x0230)], None),                         // ld c, iyl
+            (&[0xDD, 0x65], &[(IX, 0x2020), (HL, 0x0607)], None),           // ld ixh, ixl
+            (&[0xFD, 0x6C], &[(IY, 0x5050)], None),                         // ld iyl, iyh
+            (&[0xDD, 0x67], &[(IX, 0x1020)], None),                         // ld ixh, a
+            (&[0xDD, 0x24], &[(IX, 0x4120), (AF, 0x1000)], None),           // inc ixh
+            (&[0xFD, 0x2D], &[(IY, 0x502F), (AF, 0x1012)], None),           // dec iyl
+            (&[0xDD, 0x84], &[(AF, 0x5000)], None),                         // add a, ixh
+            (&[0xFD, 0x84], &[(AF, 0x6000)], None),                         // add a, iyh
+            (&[0xDD, 0x8D], &[(AF, 0x3000)], None),                         // adc a, ixl
+            (&[0xFD, 0x8D], &[(AF, 0x4000)], None),                         // adc a, iyl
+            (&[0xDD, 0x94], &[(AF, 0xD083)], None),                         // sub ixh
+            (&[0xFD, 0x95], &[(AF, 0xE083)], None),                         // sub iyl
+            (&[0xDD, 0x9D], &[(AF, 0xF083)], None),                         // sbc a, ixl
+            (&[0xFD, 0x9C], &[(AF, 0xC083)], None),                         // sbc a, iyh
+            (&[0xDD, 0xA5], &[(AF, 0x0054)], None),                         // and ixl
+            (&[0xFD, 0xA5], &[(AF, 0x1010)], None),                         // and iyl
+            (&[0xDD, 0xAC], &[(AF, 0x5004)], None),                         // xor ixh
+            (&[0xFD, 0xAC], &[(AF, 0x4000)], None),                         // xor iyh
+            (&[0xDD, 0xB4], &[(AF, 0x5004)], None),                         // or ixh
+            (&[0xFD, 0xB5], &[(AF, 0x3004)], None),                         // or iyl
+            (&[0xDD, 0xBD], &[(AF, 0x1083)], None),                         // cp ixl
+            (&[0xFD, 0xBC], &[(AF, 0x1083)], None),                         // cp iyh
+            // but not next to (IX+d)
+            (&[0xDD, 0x66, 0x05], &[(HL, 0x8107), (IX, 0x4020)], None),     // ld h, (ix+5)
+            (&[0xDD, 0x74, 0x05], &[], Some((0x4025, 0x06))),               // ld (ix+5), h
+            (&[0xFD, 0x75, 0x05], &[], Some((0x5035, 0x07))),               // ld (iy+5), l
+            // DD CB and FD CB copy the result to the register
+            (&[0xDD, 0xCB, 0x05, 0x00], &[(BC, 0x0303)], Some((0x4025, 0x03))), // rlc (ix+5), b
+            (&[0xDD, 0xCB, 0x05, 0x11], &[(BC, 0x0202)], Some((0x4025, 0x02))), // rl (ix+5), c
+            (&[0xFD, 0xCB, 0x05, 0x3F], &[(AF, 0x4001)], Some((0x5035, 0x40))), // srl (iy+5), a
+            (&[0xDD, 0xCB, 0x05, 0x80], &[(BC, 0x8003)], Some((0x4025, 0x80))), // res 0, (ix+5), b
+            (&[0xFD, 0xCB, 0x05, 0xCF], &[(AF, 0x8300)], Some((0x5035, 0x83))), // set 1, (iy+5), a
+            // and test the memory whatever the register
+            (&[0xDD, 0xCB, 0x05, 0x48], &[(BC, 0x0203), (AF, 0x1054)], None),   // bit 1, (ix+5)
+            (&[0xDD, 0xCB, 0x05, 0x78], &[(AF, 0x1090)], None),                 // bit 7, (ix+5)
+            // the same table without a prefix
+            (&[0xCB, 0x11], &[(BC, 0x0206), (AF, 0x1004)], None),           // rl c
+            (&[0xCB, 0xA7], &[(AF, 0x0000)], None),                         // res 4, a
+        ];
+
+        for (program, regs, written) in cases {
+            let mut cpu = recorder(program);
+            cpu.set_reg_value_16(AF, 0x1000);
+            cpu.set_reg_value_16(BC, 0x0203);
+            cpu.set_reg_value_16(HL, 0x0607);
+            cpu.set_reg_value_16(IX, 0x4020);
+            cpu.set_reg_value_16(IY, 0x5030);
+            cpu.bus_mut().memory[0x4025] = 0x81;
+            cpu.bus_mut().memory[0x5035] = 0x81;
+
+            cpu.do_operation();
+            assert_eq!(program.len() as u16, cpu.get_pc(), "{:02x?}", program);
+            for &(reg, val) in regs.iter() {
+                assert_eq!(val, cpu.reg_value_16(reg), "{:02x?} {:?}", program, reg);
+            }
+            if let Some((addr, val)) = written {
+                assert_eq!(*val, cpu.bus().memory[*addr as usize], "{:02x?}", program);
+            }
+        }
+
+        // adc and sbc take the carry in
+        let cases: &[(&[u8], u16)] = &[
+            (&[0xDD, 0x8D], 0x3100), // adc a, ixl
+            (&[0xFD, 0x9D], 0xDF93), // sbc a, iyl
+        ];
+        for (program, af) in cases {
+            let mut cpu = recorder(program);
+            cpu.set_reg_value_16(AF, 0x1001);
+            cpu.set_reg_value_16(IX, 0x4020);
+            cpu.set_reg_value_16(IY, 0x5030);
+
+            cpu.do_operation();
+            assert_eq!(*af, cpu.reg_value_16(AF), "{:02x?}", program);
+        }
+    }
+
+    #[test]
+    fn test_refresh() {
+        // nop, ld ix, 0, bit 0, (ix+0), ld a, r
+        let mut cpu = recorder(&[0x00, 0xDD, 0x21, 0x00, 0x00, 0xDD, 0xCB, 0x00, 0x46, 0xED, 0x5F]);
+        for _ in 0..4 {
+            cpu.do_operation();
+        }
+        // every prefix is fetched like an opcode, the opcode of DD CB is only read
+        assert_eq!(7, cpu.reg_value(RegisterCode::A));
+
+        // bit 7 stays as it was loaded
+        let mut cpu = recorder(&[0x00]);
+        cpu.set_reg_value(RegisterCode::R, 0xFF);
+        cpu.do_operation();
+        assert_eq!(0x80, cpu.reg_value(RegisterCode::R));
+    }
+
+    #[test]
+    fn test_halt() {
+        // ei, halt, inc a
+        let mut cpu = recorder(&[0xFB, 0x76, 0x3C]);
+        cpu.set_reg_value(RegisterCode::A, 0);
+        cpu.do_operation();
+        cpu.do_operation();
+        assert!(cpu.is_halted());
+        assert_eq!(1, cpu.get_pc());
+
+        // the cpu stays on the HALT, refreshing
+        assert_eq!(4, cpu.do_operation());
+        assert_eq!(4, cpu.do_operation());
+        assert_eq!(1, cpu.get_pc());
+        assert_eq!(4, cpu.reg_value(RegisterCode::R));
+        assert_eq!(0, cpu.reg_value(RegisterCode::A));
+
+        // and returns to the instruction after it
+        cpu.mask_interrupt = true;
+        cpu.do_operation();
+        assert!(!cpu.is_halted());
+        assert_eq!(0x0038, cpu.get_pc());
+        assert_eq!(0x02, cpu.bus().memory[0x7FFE]);
+    }
+
+    #[test]
+    fn test_pause() {
+        // ei, inc a, inc a
+        let mut cpu = recorder(&[0xFB, 0x3C, 0x3C]);
+        cpu.set_reg_value(RegisterCode::A, 0);
+        cpu.do_operation();
+        cpu.do_operation();
+
+        // a paused cpu lets the time pass, but neither moves on nor takes interrupts
+        cpu.pause();
+        cpu.mask_interrupt = true;
+        assert_eq!(4, cpu.do_operation());
+        assert_eq!(4, cpu.do_operation());
+        assert_eq!(2, cpu.get_pc());
+        assert_eq!(1, cpu.reg_value(RegisterCode::A));
+
+        // and carries on with the next instruction after resuming
+        cpu.mask_interrupt = false;
+        cpu.resume();
+        cpu.do_operation();
+        assert_eq!(3, cpu.get_pc());
+        assert_eq!(2, cpu.reg_value(RegisterCode::A));
+    }
+
+    #[test]
+    fn test_block_load() {
+        // ldi, ldd, ldir
+        let mut cpu = recorder(&[0xED, 0xA0, 0xED, 0xA8, 0xED, 0xB0]);
+        cpu.bus_mut().memory[0x4000..0x4003].copy_from_slice(&[0x11, 0x22, 0x33]);
+
+        // the source and the destination each move on by themselves
+        cpu.set_reg_value_16(RegisterCode16::DE, 0x5000);
+        cpu.set_reg_value_16(RegisterCode16::BC, 3);
+        cpu.do_operation();
+        assert_eq!(0x4001, cpu.reg_value_16(RegisterCode16::HL));
+        assert_eq!(0x5001, cpu.reg_value_16(RegisterCode16::DE));
+        assert_eq!(2, cpu.reg_value_16(RegisterCode16::BC));
+        assert_eq!(0x11, cpu.bus().memory[0x5000]);
+
+        cpu.do_operation();
+        assert_eq!(0x4000, cpu.reg_value_16(RegisterCode16::HL));
+        assert_eq!(0x5000, cpu.reg_value_16(RegisterCode16::DE));
+        assert_eq!(0x22, cpu.bus().memory[0x5001]);
+
+        // so a copy between places that are apart is not smeared over the destination
+        cpu.set_reg_value_16(RegisterCode16::DE, 0x6000);
+        cpu.set_reg_value_16(RegisterCode16::BC, 3);
+        while cpu.get_pc() != 6 {
+            cpu.do_operation();
+        }
+        assert_eq!([0x11, 0x22, 0x33], cpu.bus().memory[0x6000..0x6003]);
+        assert_eq!(0x4003, cpu.reg_value_16(RegisterCode16::HL));
+        assert_eq!(0x6003, cpu.reg_value_16(RegisterCode16::DE));
+        assert_eq!(0, cpu.reg_value_16(RegisterCode16::BC));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_wraparound() {
+        use RegisterCode16::{AF, BC, DE, HL, IX, IY, PC, SP};
+
+        // where the program starts, the program, the registers and the memory before it,
+        // and the registers and the memory after it, starting from AF = 0
+        type Case = (
+            u16,
+            &'static [u8],
+            &'static [(RegisterCode16, u16)],
+            &'static [(u16, u8)],
+            &'static [(RegisterCode16, u16)],
+            &'static [(u16, u8)],
+        );
+        let cases: &[Case] = &[
+            // nop
+            (0xFFFF, &[0x00], &[], &[], &[(PC, 0x0000)], &[]),
+            // ld bc, 0x1234
+            (0xFFFE, &[0x01, 0x34, 0x12], &[], &[], &[(PC, 0x0001), (BC, 0x1234)], &[]),
+            // jr -4
+            (0x0000, &[0x18, 0xFC], &[], &[], &[(PC, 0xFFFE)], &[]),
+            // pop bc
+            (0x1000, &[0xC1], &[(SP, 0xFFFF)], &[(0xFFFF, 0x34), (0x0000, 0x12)],
+             &[(SP, 0x0001), (BC, 0x1234)], &[]),
+            // push bc
+            (0x1000, &[0xC5], &[(SP, 0x0000), (BC, 0x1234)], &[],
+             &[(SP, 0xFFFE)], &[(0xFFFF, 0x12), (0xFFFE, 0x34)]),
+            // ld a, (ix-1)
+            (0x1000, &[0xDD, 0x7E, 0xFF], &[(IX, 0x0000)], &[(0xFFFF, 0x5A)],
+             &[(AF, 0x5A00)], &[]),
+            // ld a, (iy+127)
+            (0x1000, &[0xFD, 0x7E, 0x7F], &[(IY, 0xFFF0)], &[(0x006F, 0x5A)],
+             &[(AF, 0x5A00)], &[]),
+            // ld (ix-128), b
+            (0x1000, &[0xDD, 0x70, 0x80], &[(IX, 0x4000), (BC, 0x1234)], &[],
+             &[], &[(0x3F80, 0x12)]),
+            // ld hl, (0xFFFF)
+            (0x1000, &[0x2A, 0xFF, 0xFF], &[], &[(0xFFFF, 0x34), (0x0000, 0x12)],
+             &[(HL, 0x1234)], &[]),
+            // ld (0xFFFF), hl
+            (0x1000, &[0x22, 0xFF, 0xFF], &[(HL, 0x1234)], &[],
+             &[], &[(0xFFFF, 0x34), (0x0000, 0x12)]),
+            // ex (sp), hl
+            (0x1000, &[0xE3], &[(SP, 0xFFFF), (HL, 0xABCD)], &[(0xFFFF, 0x34), (0x0000, 0x12)],
+             &[(SP, 0xFFFF), (HL, 0x1234)], &[(0xFFFF, 0xCD), (0x0000, 0xAB)]),
+            // ldi
+            (0x1000, &[0xED, 0xA0], &[(HL, 0xFFFF), (DE, 0x2000), (BC, 0x0002)], &[(0xFFFF, 0x77)],
+             &[(HL, 0x0000), (DE, 0x2001), (BC, 0x0001)], &[(0x2000, 0x77)]),
+            // ldd
+            (0x1000, &[0xED, 0xA8], &[(HL, 0x0000), (DE, 0x0000), (BC, 0x0002)], &[(0x0000, 0x77)],
+             &[(HL, 0xFFFF), (DE, 0xFFFF), (BC, 0x0001)], &[]),
+            // ldir, repeating from the end of memory
+            (0xFFFE, &[0xED, 0xB0], &[(HL, 0x2000), (DE, 0x3000), (BC, 0x0002)], &[],
+             &[(PC, 0xFFFE), (HL, 0x2001), (DE, 0x3001)], &[]),
+            // add hl, de
+            (0x1000, &[0x19], &[(HL, 0xFFFF), (DE, 0x0001)], &[],
+             &[(HL, 0x0000), (AF, 0x0011)], &[]),
+            // adc hl, de
+            (0x1000, &[0xED, 0x5A], &[(HL, 0xFFFF), (DE, 0x0000), (AF, 0x0001)], &[],
+             &[(HL, 0x0000), (AF, 0x0051)], &[]),
+            // sbc hl, de
+            (0x1000, &[0xED, 0x52], &[(HL, 0x0000), (DE, 0xFFFF), (AF, 0x0001)], &[],
+             &[(HL, 0x0000), (AF, 0x0053)], &[]),
+            // ini
+            (0x1000, &[0xED, 0xA2], &[(HL, 0xFFFF), (BC, 0x0201)], &[],
+             &[(HL, 0x0000), (BC, 0x0101)], &[(0xFFFF, 0xFF)]),
+            // ind
+            (0x1000, &[0xED, 0xAA], &[(HL, 0x0000), (BC, 0x0201)], &[],
+             &[(HL, 0xFFFF), (BC, 0x0101)], &[(0x0000, 0xFF)]),
+            // outi
+            (0x1000, &[0xED, 0xA3], &[(HL, 0xFFFF), (BC, 0x0201)], &[],
+             &[(HL, 0x0000), (BC, 0x0101)], &[]),
+            // otir, repeating from the end of memory
+            (0xFFFE, &[0xED, 0xB3], &[(HL, 0x2000), (BC, 0x0201)], &[],
+             &[(PC, 0xFFFE), (HL, 0x2001), (BC, 0x0101)], &[]),
+        ];
+
+        for &(pc, program, before, memory, after, written) in cases {
+            let mut cpu = recorder(&[]);
+            for (i, &byte) in program.iter().enumerate() {
+                cpu.bus_mut().memory[pc.wrapping_add(i as u16) as usize] = byte;
+            }
+            for &(addr, val) in memory {
+                cpu.bus_mut().memory[addr as usize] = val;
+            }
+            cpu.set_pc(pc);
+            cpu.set_reg_value_16(AF, 0);
+            for &(reg, val) in before {
+                cpu.set_reg_value_16(reg, val);
+            }
+
+            cpu.do_operation();
+            for &(reg, val) in after {
+                assert_eq!(val, cpu.reg_value_16(reg), "{:02x?} {:?}", program, reg);
+            }
+            for &(addr, val) in written {
+                let actual = cpu.bus().memory[addr as usize];
+                assert_eq!(val, actual, "{:02x?} (0x{:04x})", program, addr);
+            }
+        }
+    }
+
     #[test]
     fn test_sbc_reg16_reg16() {
         let mut cpu = get_cpu();
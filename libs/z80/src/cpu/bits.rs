@@ -1,6 +1,7 @@
 extern crate num;
 
-use crate::cpu::{BitsOperator, Cpu, RegisterCode};
+use crate::cpu::{BitsOperator, Cpu, RegisterCode, Z80Bus};
+use std::marker::PhantomData;
 
 #[repr(u8)]
 #[derive(FromPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
@@ -318,635 +319,1096 @@ pub enum BitsOpcode {
     Set7A = 0xFF,
 }
 
+/// A CB prefixed instruction, which reads and writes its operand through the operator
+pub type BitsInstruction<B, U> = fn(&mut Cpu<B>, &mut U);
+
+/// The instructions indexed by opcode, for the operator of the prefix
+struct Table<B, U>(PhantomData<(B, U)>);
+
+impl<B: Z80Bus, U: BitsOperator<B>> Table<B, U> {
+    const INSTRUCTIONS: [BitsInstruction<B, U>; 256] = BitsOpcode::table::<B, U>();
+}
+
 impl BitsOpcode {
     pub fn from_u8(value: u8) -> BitsOpcode {
         num::FromPrimitive::from_u8(value).unwrap()
     }
 
-    pub fn operate_u8<U>(cpu: &mut Cpu, value: u8, bits_op: &mut U)
+    pub fn operate_u8<B: Z80Bus, U>(cpu: &mut Cpu<B>, value: u8, bits_op: &mut U)
     where
-        U: BitsOperator,
+        U: BitsOperator<B>,
     {
-        BitsOpcode::operate(cpu, BitsOpcode::from_u8(value), bits_op);
+        Table::<B, U>::INSTRUCTIONS[value as usize](cpu, bits_op);
     }
 
-    pub fn operate<U>(cpu: &mut Cpu, opcode: BitsOpcode, bits_op: &mut U)
+    pub fn operate<B: Z80Bus, U>(cpu: &mut Cpu<B>, opcode: BitsOpcode, bits_op: &mut U)
     where
-        U: BitsOperator,
+        U: BitsOperator<B>,
     {
-        //println!("Found Bits Opcode: {:?}", opcode);
-
-        bits_op.prepare(cpu);
-
-        use BitsOpcode::*;
-        match opcode {
-            RlcB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.rlc_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            RlcC => {
-                bits_op.pre_operate(cpu, RegisterCode::C);
-                cpu.rlc_reg(RegisterCode::C);
-                bits_op.post_operate(cpu, RegisterCode::C);
-            }
-            RlcD => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.rlc_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            RlcE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.rlc_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            RlcH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.rlc_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            RlcL => {
-                bits_op.pre_operate(cpu, RegisterCode::L);
-                cpu.rlc_reg(RegisterCode::L);
-                bits_op.post_operate(cpu, RegisterCode::L);
-            }
-            RlcA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.rlc_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            RlcHLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.rlc_addr(addr);
-            }
-
-            RlB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.rl_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            RlC => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.rl_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            RlD => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.rl_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            RlE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.rl_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            RlH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.rl_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            RlL => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.rl_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            RlA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.rl_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            RlHLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.rl_addr(addr);
-            }
-
-            SlaB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.sla_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            SlaC => {
-                bits_op.pre_operate(cpu, RegisterCode::C);
-                cpu.sla_reg(RegisterCode::C);
-                bits_op.post_operate(cpu, RegisterCode::C);
-            }
-            SlaD => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.sla_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            SlaE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.sla_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            SlaH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.sla_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            SlaL => {
-                bits_op.pre_operate(cpu, RegisterCode::L);
-                cpu.sla_reg(RegisterCode::L);
-                bits_op.post_operate(cpu, RegisterCode::L);
-            }
-            SlaA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.sla_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            SlaHLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.sla_addr(addr);
-            }
-
-            SllB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.sll_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            SllC => {
-                bits_op.pre_operate(cpu, RegisterCode::C);
-                cpu.sll_reg(RegisterCode::C);
-                bits_op.post_operate(cpu, RegisterCode::C);
-            }
-            SllD => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.sll_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            SllE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.sll_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            SllH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.sll_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            SllL => {
-                bits_op.pre_operate(cpu, RegisterCode::L);
-                cpu.sll_reg(RegisterCode::L);
-                bits_op.post_operate(cpu, RegisterCode::L);
-            }
-            SllA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.sll_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            SllHLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.sll_addr(addr);
-            }
-
-            RrcB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.rrc_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            RrcC => {
-                bits_op.pre_operate(cpu, RegisterCode::C);
-                cpu.rrc_reg(RegisterCode::C);
-                bits_op.post_operate(cpu, RegisterCode::C);
-            }
-            RrcD => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.rrc_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            RrcE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.rrc_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            RrcH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.rrc_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            RrcL => {
-                bits_op.pre_operate(cpu, RegisterCode::L);
-                cpu.rrc_reg(RegisterCode::L);
-                bits_op.post_operate(cpu, RegisterCode::L);
-            }
-            RrcA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.rrc_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            RrcHLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.rrc_addr(addr);
-            }
-
-            RrB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.rr_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            RrC => {
-                bits_op.pre_operate(cpu, RegisterCode::C);
-                cpu.rr_reg(RegisterCode::C);
-                bits_op.post_operate(cpu, RegisterCode::C);
-            }
-            RrD => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.rr_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            RrE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.rr_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            RrH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.rr_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            RrL => {
-                bits_op.pre_operate(cpu, RegisterCode::L);
-                cpu.rr_reg(RegisterCode::L);
-                bits_op.post_operate(cpu, RegisterCode::L);
-            }
-            RrA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.rr_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            RrHLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.rr_addr(addr);
-            }
-
-            SraB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.sra_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            SraC => {
-                bits_op.pre_operate(cpu, RegisterCode::C);
-                cpu.sra_reg(RegisterCode::C);
-                bits_op.post_operate(cpu, RegisterCode::C);
-            }
-            SraD => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.sra_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            SraE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.sra_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            SraH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.sra_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            SraL => {
-                bits_op.pre_operate(cpu, RegisterCode::L);
-                cpu.sra_reg(RegisterCode::L);
-                bits_op.post_operate(cpu, RegisterCode::L);
-            }
-            SraA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.sra_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            SraFLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.sra_addr(addr);
-            }
-
-            SrlB => {
-                bits_op.pre_operate(cpu, RegisterCode::B);
-                cpu.srl_reg(RegisterCode::B);
-                bits_op.post_operate(cpu, RegisterCode::B);
-            }
-            SrlC => {
-                bits_op.pre_operate(cpu, RegisterCode::C);
-                cpu.srl_reg(RegisterCode::C);
-                bits_op.post_operate(cpu, RegisterCode::C);
-            }
-            SrlD => {
-                bits_op.pre_operate(cpu, RegisterCode::D);
-                cpu.srl_reg(RegisterCode::D);
-                bits_op.post_operate(cpu, RegisterCode::D);
-            }
-            SrlE => {
-                bits_op.pre_operate(cpu, RegisterCode::E);
-                cpu.srl_reg(RegisterCode::E);
-                bits_op.post_operate(cpu, RegisterCode::E);
-            }
-            SrlH => {
-                bits_op.pre_operate(cpu, RegisterCode::H);
-                cpu.srl_reg(RegisterCode::H);
-                bits_op.post_operate(cpu, RegisterCode::H);
-            }
-            SrlL => {
-                bits_op.pre_operate(cpu, RegisterCode::L);
-                cpu.srl_reg(RegisterCode::L);
-                bits_op.post_operate(cpu, RegisterCode::L);
-            }
-            SrlA => {
-                bits_op.pre_operate(cpu, RegisterCode::A);
-                cpu.srl_reg(RegisterCode::A);
-                bits_op.post_operate(cpu, RegisterCode::A);
-            }
-            SrlHLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.srl_addr(addr);
-            }
-
-            Bit0B => cpu.test_bit_reg(RegisterCode::B, 0),
-            Bit1B => cpu.test_bit_reg(RegisterCode::B, 1),
-            Bit2B => cpu.test_bit_reg(RegisterCode::B, 2),
-            Bit3B => cpu.test_bit_reg(RegisterCode::B, 3),
-            Bit4B => cpu.test_bit_reg(RegisterCode::B, 4),
-            Bit5B => cpu.test_bit_reg(RegisterCode::B, 5),
-            Bit6B => cpu.test_bit_reg(RegisterCode::B, 6),
-            Bit7B => cpu.test_bit_reg(RegisterCode::B, 7),
-
-            Bit0C => cpu.test_bit_reg(RegisterCode::C, 0),
-            Bit1C => cpu.test_bit_reg(RegisterCode::C, 1),
-            Bit2C => cpu.test_bit_reg(RegisterCode::C, 2),
-            Bit3C => cpu.test_bit_reg(RegisterCode::C, 3),
-            Bit4C => cpu.test_bit_reg(RegisterCode::C, 4),
-            Bit5C => cpu.test_bit_reg(RegisterCode::C, 5),
-            Bit6C => cpu.test_bit_reg(RegisterCode::C, 6),
-            Bit7C => cpu.test_bit_reg(RegisterCode::C, 7),
-
-            Bit0D => cpu.test_bit_reg(RegisterCode::D, 0),
-            Bit1D => cpu.test_bit_reg(RegisterCode::D, 1),
-            Bit2D => cpu.test_bit_reg(RegisterCode::D, 2),
-            Bit3D => cpu.test_bit_reg(RegisterCode::D, 3),
-            Bit4D => cpu.test_bit_reg(RegisterCode::D, 4),
-            Bit5D => cpu.test_bit_reg(RegisterCode::D, 5),
-            Bit6D => cpu.test_bit_reg(RegisterCode::D, 6),
-            Bit7D => cpu.test_bit_reg(RegisterCode::D, 7),
-
-            Bit0E => cpu.test_bit_reg(RegisterCode::E, 0),
-            Bit1E => cpu.test_bit_reg(RegisterCode::E, 1),
-            Bit2E => cpu.test_bit_reg(RegisterCode::E, 2),
-            Bit3E => cpu.test_bit_reg(RegisterCode::E, 3),
-            Bit4E => cpu.test_bit_reg(RegisterCode::E, 4),
-            Bit5E => cpu.test_bit_reg(RegisterCode::E, 5),
-            Bit6E => cpu.test_bit_reg(RegisterCode::E, 6),
-            Bit7E => cpu.test_bit_reg(RegisterCode::E, 7),
-
-            Bit0H => cpu.test_bit_reg(RegisterCode::H, 0),
-            Bit1H => cpu.test_bit_reg(RegisterCode::H, 1),
-            Bit2H => cpu.test_bit_reg(RegisterCode::H, 2),
-            Bit3H => cpu.test_bit_reg(RegisterCode::H, 3),
-            Bit4H => cpu.test_bit_reg(RegisterCode::H, 4),
-            Bit5H => cpu.test_bit_reg(RegisterCode::H, 5),
-            Bit6H => cpu.test_bit_reg(RegisterCode::H, 6),
-            Bit7H => cpu.test_bit_reg(RegisterCode::H, 7),
-
-            Bit0L => cpu.test_bit_reg(RegisterCode::L, 0),
-            Bit1L => cpu.test_bit_reg(RegisterCode::L, 1),
-            Bit2L => cpu.test_bit_reg(RegisterCode::L, 2),
-            Bit3L => cpu.test_bit_reg(RegisterCode::L, 3),
-            Bit4L => cpu.test_bit_reg(RegisterCode::L, 4),
-            Bit5L => cpu.test_bit_reg(RegisterCode::L, 5),
-            Bit6L => cpu.test_bit_reg(RegisterCode::L, 6),
-            Bit7L => cpu.test_bit_reg(RegisterCode::L, 7),
-
-            Bit0HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 0);
-            }
-            Bit1HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 1);
-            }
-            Bit2HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 2);
-            }
-            Bit3HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 3);
-            }
-            Bit4HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 4);
-            }
-            Bit5HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 5);
-            }
-            Bit6HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 6);
-            }
-            Bit7HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.test_bit_addr(addr, 7);
-            }
-
-            Bit0A => cpu.test_bit_reg(RegisterCode::A, 0),
-            Bit1A => cpu.test_bit_reg(RegisterCode::A, 1),
-            Bit2A => cpu.test_bit_reg(RegisterCode::A, 2),
-            Bit3A => cpu.test_bit_reg(RegisterCode::A, 3),
-            Bit4A => cpu.test_bit_reg(RegisterCode::A, 4),
-            Bit5A => cpu.test_bit_reg(RegisterCode::A, 5),
-            Bit6A => cpu.test_bit_reg(RegisterCode::A, 6),
-            Bit7A => cpu.test_bit_reg(RegisterCode::A, 7),
-
-            Res0B => cpu.change_bit_reg(RegisterCode::B, 0, false),
-            Res1B => cpu.change_bit_reg(RegisterCode::B, 1, false),
-            Res2B => cpu.change_bit_reg(RegisterCode::B, 2, false),
-            Res3B => cpu.change_bit_reg(RegisterCode::B, 3, false),
-            Res4B => cpu.change_bit_reg(RegisterCode::B, 4, false),
-            Res5B => cpu.change_bit_reg(RegisterCode::B, 5, false),
-            Res6B => cpu.change_bit_reg(RegisterCode::B, 6, false),
-            Res7B => cpu.change_bit_reg(RegisterCode::B, 7, false),
-
-            Res0C => cpu.change_bit_reg(RegisterCode::C, 0, false),
-            Res1C => cpu.change_bit_reg(RegisterCode::C, 1, false),
-            Res2C => cpu.change_bit_reg(RegisterCode::C, 2, false),
-            Res3C => cpu.change_bit_reg(RegisterCode::C, 3, false),
-            Res4C => cpu.change_bit_reg(RegisterCode::C, 4, false),
-            Res5C => cpu.change_bit_reg(RegisterCode::C, 5, false),
-            Res6C => cpu.change_bit_reg(RegisterCode::C, 6, false),
-            Res7C => cpu.change_bit_reg(RegisterCode::C, 7, false),
-
-            Res0D => cpu.change_bit_reg(RegisterCode::D, 0, false),
-            Res1D => cpu.change_bit_reg(RegisterCode::D, 1, false),
-            Res2D => cpu.change_bit_reg(RegisterCode::D, 2, false),
-            Res3D => cpu.change_bit_reg(RegisterCode::D, 3, false),
-            Res4D => cpu.change_bit_reg(RegisterCode::D, 4, false),
-            Res5D => cpu.change_bit_reg(RegisterCode::D, 5, false),
-            Res6D => cpu.change_bit_reg(RegisterCode::D, 6, false),
-            Res7D => cpu.change_bit_reg(RegisterCode::D, 7, false),
-
-            Res0E => cpu.change_bit_reg(RegisterCode::E, 0, false),
-            Res1E => cpu.change_bit_reg(RegisterCode::E, 1, false),
-            Res2E => cpu.change_bit_reg(RegisterCode::E, 2, false),
-            Res3E => cpu.change_bit_reg(RegisterCode::E, 3, false),
-            Res4E => cpu.change_bit_reg(RegisterCode::E, 4, false),
-            Res5E => cpu.change_bit_reg(RegisterCode::E, 5, false),
-            Res6E => cpu.change_bit_reg(RegisterCode::E, 6, false),
-            Res7E => cpu.change_bit_reg(RegisterCode::E, 7, false),
-
-            Res0H => cpu.change_bit_reg(RegisterCode::H, 0, false),
-            Res1H => cpu.change_bit_reg(RegisterCode::H, 1, false),
-            Res2H => cpu.change_bit_reg(RegisterCode::H, 2, false),
-            Res3H => cpu.change_bit_reg(RegisterCode::H, 3, false),
-            Res4H => cpu.change_bit_reg(RegisterCode::H, 4, false),
-            Res5H => cpu.change_bit_reg(RegisterCode::H, 5, false),
-            Res6H => cpu.change_bit_reg(RegisterCode::H, 6, false),
-            Res7H => cpu.change_bit_reg(RegisterCode::H, 7, false),
-
-            Res0L => cpu.change_bit_reg(RegisterCode::L, 0, false),
-            Res1L => cpu.change_bit_reg(RegisterCode::L, 1, false),
-            Res2L => cpu.change_bit_reg(RegisterCode::L, 2, false),
-            Res3L => cpu.change_bit_reg(RegisterCode::L, 3, false),
-            Res4L => cpu.change_bit_reg(RegisterCode::L, 4, false),
-            Res5L => cpu.change_bit_reg(RegisterCode::L, 5, false),
-            Res6L => cpu.change_bit_reg(RegisterCode::L, 6, false),
-            Res7L => cpu.change_bit_reg(RegisterCode::L, 7, false),
-
-            Res0HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 0, false);
-            }
-            Res1HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 1, false);
-            }
-            Res2HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 2, false);
-            }
-            Res3HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 3, false);
-            }
-            Res4HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 4, false);
-            }
-            Res5HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 5, false);
-            }
-            Res6HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 6, false);
-            }
-            Res7HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 7, false);
-            }
-
-            Res0A => cpu.change_bit_reg(RegisterCode::A, 0, true),
-            Res1A => cpu.change_bit_reg(RegisterCode::A, 1, true),
-            Res2A => cpu.change_bit_reg(RegisterCode::A, 2, true),
-            Res3A => cpu.change_bit_reg(RegisterCode::A, 3, true),
-            Res4A => cpu.change_bit_reg(RegisterCode::A, 4, true),
-            Res5A => cpu.change_bit_reg(RegisterCode::A, 5, true),
-            Res6A => cpu.change_bit_reg(RegisterCode::A, 6, true),
-            Res7A => cpu.change_bit_reg(RegisterCode::A, 7, true),
-
-            Set0B => cpu.change_bit_reg(RegisterCode::B, 0, true),
-            Set1B => cpu.change_bit_reg(RegisterCode::B, 1, true),
-            Set2B => cpu.change_bit_reg(RegisterCode::B, 2, true),
-            Set3B => cpu.change_bit_reg(RegisterCode::B, 3, true),
-            Set4B => cpu.change_bit_reg(RegisterCode::B, 4, true),
-            Set5B => cpu.change_bit_reg(RegisterCode::B, 5, true),
-            Set6B => cpu.change_bit_reg(RegisterCode::B, 6, true),
-            Set7B => cpu.change_bit_reg(RegisterCode::B, 7, true),
-
-            Set0C => cpu.change_bit_reg(RegisterCode::C, 0, true),
-            Set1C => cpu.change_bit_reg(RegisterCode::C, 1, true),
-            Set2C => cpu.change_bit_reg(RegisterCode::C, 2, true),
-            Set3C => cpu.change_bit_reg(RegisterCode::C, 3, true),
-            Set4C => cpu.change_bit_reg(RegisterCode::C, 4, true),
-            Set5C => cpu.change_bit_reg(RegisterCode::C, 5, true),
-            Set6C => cpu.change_bit_reg(RegisterCode::C, 6, true),
-            Set7C => cpu.change_bit_reg(RegisterCode::C, 7, true),
-
-            Set0D => cpu.change_bit_reg(RegisterCode::D, 0, true),
-            Set1D => cpu.change_bit_reg(RegisterCode::D, 1, true),
-            Set2D => cpu.change_bit_reg(RegisterCode::D, 2, true),
-            Set3D => cpu.change_bit_reg(RegisterCode::D, 3, true),
-            Set4D => cpu.change_bit_reg(RegisterCode::D, 4, true),
-            Set5D => cpu.change_bit_reg(RegisterCode::D, 5, true),
-            Set6D => cpu.change_bit_reg(RegisterCode::D, 6, true),
-            Set7D => cpu.change_bit_reg(RegisterCode::D, 7, true),
-
-            Set0E => cpu.change_bit_reg(RegisterCode::E, 0, true),
-            Set1E => cpu.change_bit_reg(RegisterCode::E, 1, true),
-            Set2E => cpu.change_bit_reg(RegisterCode::E, 2, true),
-            Set3E => cpu.change_bit_reg(RegisterCode::E, 3, true),
-            Set4E => cpu.change_bit_reg(RegisterCode::E, 4, true),
-            Set5E => cpu.change_bit_reg(RegisterCode::E, 5, true),
-            Set6E => cpu.change_bit_reg(RegisterCode::E, 6, true),
-            Set7E => cpu.change_bit_reg(RegisterCode::E, 7, true),
-
-            Set0H => cpu.change_bit_reg(RegisterCode::H, 0, true),
-            Set1H => cpu.change_bit_reg(RegisterCode::H, 1, true),
-            Set2H => cpu.change_bit_reg(RegisterCode::H, 2, true),
-            Set3H => cpu.change_bit_reg(RegisterCode::H, 3, true),
-            Set4H => cpu.change_bit_reg(RegisterCode::H, 4, true),
-            Set5H => cpu.change_bit_reg(RegisterCode::H, 5, true),
-            Set6H => cpu.change_bit_reg(RegisterCode::H, 6, true),
-            Set7H => cpu.change_bit_reg(RegisterCode::H, 7, true),
-
-            Set0L => cpu.change_bit_reg(RegisterCode::L, 0, true),
-            Set1L => cpu.change_bit_reg(RegisterCode::L, 1, true),
-            Set2L => cpu.change_bit_reg(RegisterCode::L, 2, true),
-            Set3L => cpu.change_bit_reg(RegisterCode::L, 3, true),
-            Set4L => cpu.change_bit_reg(RegisterCode::L, 4, true),
-            Set5L => cpu.change_bit_reg(RegisterCode::L, 5, true),
-            Set6L => cpu.change_bit_reg(RegisterCode::L, 6, true),
-            Set7L => cpu.change_bit_reg(RegisterCode::L, 7, true),
-
-            Set0HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 0, true);
-            }
-            Set1HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 1, true);
-            }
-            Set2HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 2, true);
-            }
-            Set3HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 3, true);
-            }
-            Set4HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 4, true);
-            }
-            Set5HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 5, true);
-            }
-            Set6HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 6, true);
-            }
-            Set7HLptr => {
-                let addr = bits_op.pointer(cpu);
-                cpu.change_bit_addr(addr, 7, true);
-            }
-
-            Set0A => cpu.change_bit_reg(RegisterCode::A, 0, true),
-            Set1A => cpu.change_bit_reg(RegisterCode::A, 1, true),
-            Set2A => cpu.change_bit_reg(RegisterCode::A, 2, true),
-            Set3A => cpu.change_bit_reg(RegisterCode::A, 3, true),
-            Set4A => cpu.change_bit_reg(RegisterCode::A, 4, true),
-            Set5A => cpu.change_bit_reg(RegisterCode::A, 5, true),
-            Set6A => cpu.change_bit_reg(RegisterCode::A, 6, true),
-            Set7A => cpu.change_bit_reg(RegisterCode::A, 7, true),
-        }
+        BitsOpcode::operate_u8(cpu, opcode as u8, bits_op);
+    }
+
+    /// The instructions of every opcode, which reach their register through `U`
+    const fn table<B: Z80Bus, U: BitsOperator<B>>() -> [BitsInstruction<B, U>; 256] {
+        // every opcode is overwritten below, all 256 of them are instructions
+        let mut t: [BitsInstruction<B, U>; 256] = [|_, _| unreachable!(); 256];
+
+        t[BitsOpcode::RlcB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.rlc_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::RlcC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.rlc_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::RlcD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.rlc_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::RlcE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.rlc_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::RlcH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.rlc_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::RlcL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.rlc_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::RlcA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.rlc_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::RlcHLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.rlc_addr(addr);
+        };
+
+        t[BitsOpcode::RlB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.rl_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::RlC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.rl_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::RlD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.rl_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::RlE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.rl_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::RlH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.rl_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::RlL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.rl_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::RlA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.rl_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::RlHLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.rl_addr(addr);
+        };
+
+        t[BitsOpcode::SlaB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.sla_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::SlaC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.sla_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::SlaD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.sla_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::SlaE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.sla_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::SlaH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.sla_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::SlaL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.sla_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::SlaA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.sla_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::SlaHLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.sla_addr(addr);
+        };
+
+        t[BitsOpcode::SllB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.sll_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::SllC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.sll_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::SllD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.sll_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::SllE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.sll_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::SllH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.sll_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::SllL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.sll_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::SllA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.sll_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::SllHLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.sll_addr(addr);
+        };
+
+        t[BitsOpcode::RrcB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.rrc_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::RrcC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.rrc_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::RrcD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.rrc_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::RrcE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.rrc_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::RrcH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.rrc_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::RrcL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.rrc_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::RrcA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.rrc_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::RrcHLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.rrc_addr(addr);
+        };
+
+        t[BitsOpcode::RrB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.rr_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::RrC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.rr_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::RrD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.rr_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::RrE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.rr_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::RrH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.rr_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::RrL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.rr_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::RrA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.rr_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::RrHLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.rr_addr(addr);
+        };
+
+        t[BitsOpcode::SraB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.sra_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::SraC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.sra_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::SraD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.sra_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::SraE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.sra_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::SraH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.sra_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::SraL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.sra_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::SraA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.sra_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::SraFLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.sra_addr(addr);
+        };
+
+        t[BitsOpcode::SrlB as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.srl_reg(RegisterCode::B);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::SrlC as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.srl_reg(RegisterCode::C);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::SrlD as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.srl_reg(RegisterCode::D);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::SrlE as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.srl_reg(RegisterCode::E);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::SrlH as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.srl_reg(RegisterCode::H);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::SrlL as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.srl_reg(RegisterCode::L);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::SrlA as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.srl_reg(RegisterCode::A);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::SrlHLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.srl_addr(addr);
+        };
+
+        t[BitsOpcode::Bit0B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 0);
+        t[BitsOpcode::Bit1B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 1);
+        t[BitsOpcode::Bit2B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 2);
+        t[BitsOpcode::Bit3B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 3);
+        t[BitsOpcode::Bit4B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 4);
+        t[BitsOpcode::Bit5B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 5);
+        t[BitsOpcode::Bit6B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 6);
+        t[BitsOpcode::Bit7B as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::B, 7);
+
+        t[BitsOpcode::Bit0C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 0);
+        t[BitsOpcode::Bit1C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 1);
+        t[BitsOpcode::Bit2C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 2);
+        t[BitsOpcode::Bit3C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 3);
+        t[BitsOpcode::Bit4C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 4);
+        t[BitsOpcode::Bit5C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 5);
+        t[BitsOpcode::Bit6C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 6);
+        t[BitsOpcode::Bit7C as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::C, 7);
+
+        t[BitsOpcode::Bit0D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 0);
+        t[BitsOpcode::Bit1D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 1);
+        t[BitsOpcode::Bit2D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 2);
+        t[BitsOpcode::Bit3D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 3);
+        t[BitsOpcode::Bit4D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 4);
+        t[BitsOpcode::Bit5D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 5);
+        t[BitsOpcode::Bit6D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 6);
+        t[BitsOpcode::Bit7D as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::D, 7);
+
+        t[BitsOpcode::Bit0E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 0);
+        t[BitsOpcode::Bit1E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 1);
+        t[BitsOpcode::Bit2E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 2);
+        t[BitsOpcode::Bit3E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 3);
+        t[BitsOpcode::Bit4E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 4);
+        t[BitsOpcode::Bit5E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 5);
+        t[BitsOpcode::Bit6E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 6);
+        t[BitsOpcode::Bit7E as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::E, 7);
+
+        t[BitsOpcode::Bit0H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 0);
+        t[BitsOpcode::Bit1H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 1);
+        t[BitsOpcode::Bit2H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 2);
+        t[BitsOpcode::Bit3H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 3);
+        t[BitsOpcode::Bit4H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 4);
+        t[BitsOpcode::Bit5H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 5);
+        t[BitsOpcode::Bit6H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 6);
+        t[BitsOpcode::Bit7H as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::H, 7);
+
+        t[BitsOpcode::Bit0L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 0);
+        t[BitsOpcode::Bit1L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 1);
+        t[BitsOpcode::Bit2L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 2);
+        t[BitsOpcode::Bit3L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 3);
+        t[BitsOpcode::Bit4L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 4);
+        t[BitsOpcode::Bit5L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 5);
+        t[BitsOpcode::Bit6L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 6);
+        t[BitsOpcode::Bit7L as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::L, 7);
+
+        t[BitsOpcode::Bit0HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 0);
+        };
+        t[BitsOpcode::Bit1HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 1);
+        };
+        t[BitsOpcode::Bit2HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 2);
+        };
+        t[BitsOpcode::Bit3HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 3);
+        };
+        t[BitsOpcode::Bit4HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 4);
+        };
+        t[BitsOpcode::Bit5HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 5);
+        };
+        t[BitsOpcode::Bit6HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 6);
+        };
+        t[BitsOpcode::Bit7HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.test_bit_addr(addr, 7);
+        };
+
+        t[BitsOpcode::Bit0A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 0);
+        t[BitsOpcode::Bit1A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 1);
+        t[BitsOpcode::Bit2A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 2);
+        t[BitsOpcode::Bit3A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 3);
+        t[BitsOpcode::Bit4A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 4);
+        t[BitsOpcode::Bit5A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 5);
+        t[BitsOpcode::Bit6A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 6);
+        t[BitsOpcode::Bit7A as usize] = |cpu, bits_op| bits_op.test_bit(cpu, RegisterCode::A, 7);
+
+        t[BitsOpcode::Res0B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 0, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Res1B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 1, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Res2B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 2, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Res3B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 3, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Res4B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 4, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Res5B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 5, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Res6B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 6, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Res7B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 7, false);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+
+        t[BitsOpcode::Res0C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 0, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Res1C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 1, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Res2C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 2, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Res3C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 3, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Res4C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 4, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Res5C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 5, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Res6C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 6, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Res7C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 7, false);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+
+        t[BitsOpcode::Res0D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 0, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Res1D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 1, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Res2D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 2, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Res3D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 3, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Res4D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 4, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Res5D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 5, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Res6D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 6, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Res7D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 7, false);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+
+        t[BitsOpcode::Res0E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 0, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Res1E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 1, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Res2E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 2, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Res3E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 3, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Res4E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 4, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Res5E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 5, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Res6E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 6, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Res7E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 7, false);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+
+        t[BitsOpcode::Res0H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 0, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Res1H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 1, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Res2H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 2, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Res3H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 3, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Res4H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 4, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Res5H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 5, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Res6H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 6, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Res7H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 7, false);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+
+        t[BitsOpcode::Res0L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 0, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Res1L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 1, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Res2L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 2, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Res3L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 3, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Res4L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 4, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Res5L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 5, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Res6L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 6, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Res7L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 7, false);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+
+        t[BitsOpcode::Res0HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 0, false);
+        };
+        t[BitsOpcode::Res1HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 1, false);
+        };
+        t[BitsOpcode::Res2HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 2, false);
+        };
+        t[BitsOpcode::Res3HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 3, false);
+        };
+        t[BitsOpcode::Res4HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 4, false);
+        };
+        t[BitsOpcode::Res5HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 5, false);
+        };
+        t[BitsOpcode::Res6HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 6, false);
+        };
+        t[BitsOpcode::Res7HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 7, false);
+        };
+
+        t[BitsOpcode::Res0A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 0, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Res1A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 1, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Res2A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 2, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Res3A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 3, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Res4A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 4, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Res5A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 5, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Res6A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 6, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Res7A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 7, false);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+
+        t[BitsOpcode::Set0B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 0, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Set1B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 1, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Set2B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 2, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Set3B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 3, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Set4B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 4, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Set5B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 5, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Set6B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 6, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+        t[BitsOpcode::Set7B as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::B);
+            cpu.change_bit_reg(RegisterCode::B, 7, true);
+            bits_op.post_operate(cpu, RegisterCode::B);
+        };
+
+        t[BitsOpcode::Set0C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 0, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Set1C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 1, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Set2C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 2, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Set3C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 3, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Set4C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 4, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Set5C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 5, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Set6C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 6, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+        t[BitsOpcode::Set7C as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::C);
+            cpu.change_bit_reg(RegisterCode::C, 7, true);
+            bits_op.post_operate(cpu, RegisterCode::C);
+        };
+
+        t[BitsOpcode::Set0D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 0, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Set1D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 1, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Set2D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 2, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Set3D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 3, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Set4D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 4, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Set5D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 5, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Set6D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 6, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+        t[BitsOpcode::Set7D as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::D);
+            cpu.change_bit_reg(RegisterCode::D, 7, true);
+            bits_op.post_operate(cpu, RegisterCode::D);
+        };
+
+        t[BitsOpcode::Set0E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 0, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Set1E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 1, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Set2E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 2, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Set3E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 3, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Set4E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 4, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Set5E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 5, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Set6E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 6, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+        t[BitsOpcode::Set7E as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::E);
+            cpu.change_bit_reg(RegisterCode::E, 7, true);
+            bits_op.post_operate(cpu, RegisterCode::E);
+        };
+
+        t[BitsOpcode::Set0H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 0, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Set1H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 1, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Set2H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 2, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Set3H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 3, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Set4H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 4, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Set5H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 5, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Set6H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 6, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+        t[BitsOpcode::Set7H as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::H);
+            cpu.change_bit_reg(RegisterCode::H, 7, true);
+            bits_op.post_operate(cpu, RegisterCode::H);
+        };
+
+        t[BitsOpcode::Set0L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 0, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Set1L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 1, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Set2L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 2, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Set3L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 3, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Set4L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 4, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Set5L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 5, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Set6L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 6, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+        t[BitsOpcode::Set7L as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::L);
+            cpu.change_bit_reg(RegisterCode::L, 7, true);
+            bits_op.post_operate(cpu, RegisterCode::L);
+        };
+
+        t[BitsOpcode::Set0HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 0, true);
+        };
+        t[BitsOpcode::Set1HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 1, true);
+        };
+        t[BitsOpcode::Set2HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 2, true);
+        };
+        t[BitsOpcode::Set3HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 3, true);
+        };
+        t[BitsOpcode::Set4HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 4, true);
+        };
+        t[BitsOpcode::Set5HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 5, true);
+        };
+        t[BitsOpcode::Set6HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 6, true);
+        };
+        t[BitsOpcode::Set7HLptr as usize] = |cpu, bits_op| {
+            let addr = bits_op.pointer(cpu);
+            cpu.change_bit_addr(addr, 7, true);
+        };
+
+        t[BitsOpcode::Set0A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 0, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Set1A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 1, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Set2A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 2, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Set3A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 3, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Set4A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 4, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Set5A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 5, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Set6A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 6, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+        t[BitsOpcode::Set7A as usize] = |cpu, bits_op| {
+            bits_op.pre_operate(cpu, RegisterCode::A);
+            cpu.change_bit_reg(RegisterCode::A, 7, true);
+            bits_op.post_operate(cpu, RegisterCode::A);
+        };
+
+        t
     }
 }
@@ -1,6 +1,7 @@
 extern crate num;
 
-use crate::cpu::{Cpu, RegisterCode, RegisterCode16};
+use crate::cpu::{opcode::Instruction, Cpu, RegisterCode, RegisterCode16, Z80Bus};
+use std::marker::PhantomData;
 
 #[repr(u8)]
 #[derive(FromPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
@@ -19,6 +20,10 @@ pub enum Extnd {
     Neg1 = 0x54,
     Neg2 = 0x64,
     Neg3 = 0x74,
+    Neg4 = 0x4C,
+    Neg5 = 0x5C,
+    Neg6 = 0x6C,
+    Neg7 = 0x7C,
 
     Retn0 = 0x45,
     Retn1 = 0x55,
@@ -31,13 +36,18 @@ pub enum Extnd {
     Reti = 0x4D,
 
     // Interupt Modes
-    // NOTE: SG-1000 only supports interrupt mode 1
+    // NOTE: the SG-1000 leaves the data bus floating, see `Cpu::accept_interrupt`
     Im00 = 0x46,
     Im01 = 0x66,
+    Im02 = 0x4E,
+    Im03 = 0x6E,
 
     Im10 = 0x56,
     Im11 = 0x76,
 
+    Im20 = 0x5E,
+    Im21 = 0x7E,
+
     LdAI = 0x57,
     LdIA = 0x47,
     LdAR = 0x5F,
@@ -98,150 +108,170 @@ pub enum Extnd {
     InDR = 0xBA,
 }
 
+/// The instructions indexed by opcode
+struct Table<B>(PhantomData<B>);
+
+impl<B: Z80Bus> Table<B> {
+    const INSTRUCTIONS: [Instruction<B>; 256] = Extnd::table::<B>();
+}
+
 impl Extnd {
     pub fn from_u8(value: u8) -> Extnd {
-        //println!("Getting Extended for: 0x{:x} | ", value);
-        let op = num::FromPrimitive::from_u8(value).unwrap();
-        //println!("Opcode: {:?}", op);
-        op
+        num::FromPrimitive::from_u8(value).unwrap()
+    }
+
+    pub fn operate_u8<B: Z80Bus>(cpu: &mut Cpu<B>, value: u8) {
+        Table::<B>::INSTRUCTIONS[value as usize](cpu);
+    }
+
+    pub fn operate<B: Z80Bus>(cpu: &mut Cpu<B>, opcode: Extnd) {
+        Extnd::operate_u8(cpu, opcode as u8);
     }
 
-    pub fn operate_u8(cpu: &mut Cpu, value: u8) {
-        Extnd::operate(cpu, Extnd::from_u8(value));
+    /// The instructions of every opcode that has one
+    const fn table<B: Z80Bus>() -> [Instruction<B>; 256] {
+        let mut t: [Instruction<B>; 256] = [Extnd::unknown; 256];
+
+        t[Extnd::SbcHLBC as usize] =
+            |cpu| cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::BC);
+        t[Extnd::SbcHLDE as usize] =
+            |cpu| cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::DE);
+        t[Extnd::SbcHLHL as usize] =
+            |cpu| cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::HL);
+        t[Extnd::SbcHLSP as usize] =
+            |cpu| cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::SP);
+
+        t[Extnd::LdLitBC as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_addr_reg16(addr, RegisterCode16::BC);
+        };
+        t[Extnd::LdLitDE as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_addr_reg16(addr, RegisterCode16::DE);
+        };
+        t[Extnd::LdLitHL as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_addr_reg16(addr, RegisterCode16::HL);
+        };
+        t[Extnd::LdLitSP as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_addr_reg16(addr, RegisterCode16::SP);
+        };
+
+        t[Extnd::Neg0 as usize] = |cpu| cpu.neg();
+        t[Extnd::Neg1 as usize] = |cpu| cpu.neg();
+        t[Extnd::Neg2 as usize] = |cpu| cpu.neg();
+        t[Extnd::Neg3 as usize] = |cpu| cpu.neg();
+        t[Extnd::Neg4 as usize] = |cpu| cpu.neg();
+        t[Extnd::Neg5 as usize] = |cpu| cpu.neg();
+        t[Extnd::Neg6 as usize] = |cpu| cpu.neg();
+        t[Extnd::Neg7 as usize] = |cpu| cpu.neg();
+
+        t[Extnd::Retn0 as usize] = |cpu| cpu.retn();
+        t[Extnd::Retn1 as usize] = |cpu| cpu.retn();
+        t[Extnd::Retn2 as usize] = |cpu| cpu.retn();
+        t[Extnd::Retn3 as usize] = |cpu| cpu.retn();
+        t[Extnd::Retn4 as usize] = |cpu| cpu.retn();
+        t[Extnd::Retn5 as usize] = |cpu| cpu.retn();
+        t[Extnd::Retn6 as usize] = |cpu| cpu.retn();
+
+        t[Extnd::Im00 as usize] = |cpu| cpu.set_interrupt_mode(0);
+        t[Extnd::Im01 as usize] = |cpu| cpu.set_interrupt_mode(0);
+        t[Extnd::Im02 as usize] = |cpu| cpu.set_interrupt_mode(0);
+        t[Extnd::Im03 as usize] = |cpu| cpu.set_interrupt_mode(0);
+
+        t[Extnd::Im10 as usize] = |cpu| cpu.set_interrupt_mode(1);
+        t[Extnd::Im11 as usize] = |cpu| cpu.set_interrupt_mode(1);
+
+        t[Extnd::Im20 as usize] = |cpu| cpu.set_interrupt_mode(2);
+        t[Extnd::Im21 as usize] = |cpu| cpu.set_interrupt_mode(2);
+
+        t[Extnd::LdAI as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, RegisterCode::I);
+        t[Extnd::LdIA as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::I, RegisterCode::A);
+        t[Extnd::LdAR as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, RegisterCode::R);
+        t[Extnd::LdRA as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::R, RegisterCode::A);
+
+        t[Extnd::RRD as usize] = |cpu| cpu.rrd();
+        t[Extnd::RLD as usize] = |cpu| cpu.rld();
+
+        t[Extnd::AdcHLBC as usize] =
+            |cpu| cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::BC);
+        t[Extnd::AdcHLDE as usize] =
+            |cpu| cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::DE);
+        t[Extnd::AdcHLHL as usize] =
+            |cpu| cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::HL);
+        t[Extnd::AdcHLSP as usize] =
+            |cpu| cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::SP);
+
+        t[Extnd::LdBCLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_reg16_addr(RegisterCode16::BC, addr);
+        };
+        t[Extnd::LdDELit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_reg16_addr(RegisterCode16::DE, addr);
+        };
+        t[Extnd::LdHLLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_reg16_addr(RegisterCode16::HL, addr);
+        };
+        t[Extnd::LdSPLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_reg16_addr(RegisterCode16::SP, addr);
+        };
+
+        t[Extnd::Reti as usize] = |cpu| cpu.reti();
+
+        t[Extnd::Ldi as usize] = |cpu| cpu.ld_id(true);
+        t[Extnd::Ldir as usize] = |cpu| cpu.ld_id_r(true);
+
+        t[Extnd::Ldd as usize] = |cpu| cpu.ld_id(false);
+        t[Extnd::Lddr as usize] = |cpu| cpu.ld_id_r(false);
+
+        t[Extnd::Cpi as usize] = |cpu| cpu.cp_id(true);
+        t[Extnd::Cpir as usize] = |cpu| cpu.cp_id_r(true);
+
+        t[Extnd::Cpd as usize] = |cpu| cpu.cp_id(false);
+        t[Extnd::Cpdr as usize] = |cpu| cpu.cp_id_r(false);
+
+        t[Extnd::OutCB as usize] = |cpu| cpu.out_c_reg(Some(RegisterCode::B));
+        t[Extnd::OutCD as usize] = |cpu| cpu.out_c_reg(Some(RegisterCode::D));
+        t[Extnd::OutCH as usize] = |cpu| cpu.out_c_reg(Some(RegisterCode::H));
+        t[Extnd::OutC0 as usize] = |cpu| cpu.out_c_reg(None);
+        t[Extnd::OutCC as usize] = |cpu| cpu.out_c_reg(Some(RegisterCode::C));
+        t[Extnd::OutCE as usize] = |cpu| cpu.out_c_reg(Some(RegisterCode::E));
+        t[Extnd::OutCL as usize] = |cpu| cpu.out_c_reg(Some(RegisterCode::L));
+        t[Extnd::OutCA as usize] = |cpu| cpu.out_c_reg(Some(RegisterCode::A));
+
+        t[Extnd::OutI as usize] = |cpu| cpu.out_id(true);
+        t[Extnd::OutD as usize] = |cpu| cpu.out_id(false);
+        t[Extnd::OutIR as usize] = |cpu| cpu.out_id_rep(true);
+        t[Extnd::OutDR as usize] = |cpu| cpu.out_id_rep(false);
+
+        t[Extnd::InCB as usize] = |cpu| cpu.in_reg_c(Some(RegisterCode::B));
+        t[Extnd::InCD as usize] = |cpu| cpu.in_reg_c(Some(RegisterCode::D));
+        t[Extnd::InCH as usize] = |cpu| cpu.in_reg_c(Some(RegisterCode::H));
+        t[Extnd::InC0 as usize] = |cpu| cpu.in_reg_c(None);
+        t[Extnd::InCC as usize] = |cpu| cpu.in_reg_c(Some(RegisterCode::C));
+        t[Extnd::InCE as usize] = |cpu| cpu.in_reg_c(Some(RegisterCode::E));
+        t[Extnd::InCL as usize] = |cpu| cpu.in_reg_c(Some(RegisterCode::L));
+        t[Extnd::InCA as usize] = |cpu| cpu.in_reg_c(Some(RegisterCode::A));
+
+        t[Extnd::InI as usize] = |cpu| cpu.in_id(true);
+        t[Extnd::InD as usize] = |cpu| cpu.in_id(false);
+        t[Extnd::InIR as usize] = |cpu| cpu.in_id_rep(true);
+        t[Extnd::InDR as usize] = |cpu| cpu.in_id_rep(false);
+
+        t
     }
 
-    pub fn operate(cpu: &mut Cpu, opcode: Extnd) {
-        use Extnd::*;
-        // println!("Found Extnd Opcode: {:?}", opcode);
-        match opcode {
-            SbcHLBC => cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::BC),
-            SbcHLDE => cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::DE),
-            SbcHLHL => cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::HL),
-            SbcHLSP => cpu.sbc_reg16_reg16(RegisterCode16::HL, RegisterCode16::SP),
-
-            LdLitBC => {
-                cpu.queue_clock_tick(4);
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_addr_reg16(addr, RegisterCode16::BC);
-            }
-            LdLitDE => {
-                cpu.queue_clock_tick(4);
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_addr_reg16(addr, RegisterCode16::DE);
-            }
-            LdLitHL => {
-                cpu.queue_clock_tick(4);
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_addr_reg16(addr, RegisterCode16::HL);
-            }
-            LdLitSP => {
-                cpu.queue_clock_tick(4);
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_addr_reg16(addr, RegisterCode16::SP);
-            }
-
-            Neg0 => cpu.neg(),
-            Neg1 => cpu.neg(),
-            Neg2 => cpu.neg(),
-            Neg3 => cpu.neg(),
-
-            Retn0 => cpu.retn(),
-            Retn1 => cpu.retn(),
-            Retn2 => cpu.retn(),
-            Retn3 => cpu.retn(),
-            Retn4 => cpu.retn(),
-            Retn5 => cpu.retn(),
-            Retn6 => cpu.retn(),
-
-            Im00 => cpu.interrupt_0(),
-            Im01 => cpu.interrupt_0(),
-
-            Im10 => cpu.interrupt_1(),
-            Im11 => cpu.interrupt_1(),
-
-            LdAI => {
-                cpu.queue_clock_tick(5);
-                cpu.ld_reg_reg(RegisterCode::A, RegisterCode::I);
-            }
-            LdIA => {
-                cpu.queue_clock_tick(5);
-                cpu.ld_reg_reg(RegisterCode::I, RegisterCode::A);
-            }
-            LdAR => {
-                cpu.queue_clock_tick(5);
-                cpu.ld_reg_reg(RegisterCode::A, RegisterCode::R);
-            }
-            LdRA => {
-                cpu.queue_clock_tick(5);
-                cpu.ld_reg_reg(RegisterCode::R, RegisterCode::A);
-            }
-
-            RRD => cpu.rrd(),
-            RLD => cpu.rld(),
-
-            AdcHLBC => cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::BC),
-            AdcHLDE => cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::DE),
-            AdcHLHL => cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::HL),
-            AdcHLSP => cpu.adc_reg16_reg16(RegisterCode16::HL, RegisterCode16::SP),
-
-            LdBCLit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(RegisterCode16::BC, val);
-            }
-            LdDELit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(RegisterCode16::BC, val);
-            }
-            LdHLLit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(RegisterCode16::BC, val);
-            }
-            LdSPLit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(RegisterCode16::BC, val);
-            }
-
-            Reti => cpu.reti(),
-
-            Ldi => cpu.ld_id(true),
-            Ldir => cpu.ld_id_r(true),
-
-            Ldd => cpu.ld_id(false),
-            Lddr => cpu.ld_id_r(false),
-
-            Cpi => cpu.cp_id(true),
-            Cpir => cpu.cp_id_r(true),
-
-            Cpd => cpu.cp_id(false),
-            Cpdr => cpu.cp_id_r(false),
-
-            OutCB => cpu.out_c_reg(Some(RegisterCode::B)),
-            OutCD => cpu.out_c_reg(Some(RegisterCode::D)),
-            OutCH => cpu.out_c_reg(Some(RegisterCode::H)),
-            OutC0 => cpu.out_c_reg(None),
-            OutCC => cpu.out_c_reg(Some(RegisterCode::C)),
-            OutCE => cpu.out_c_reg(Some(RegisterCode::E)),
-            OutCL => cpu.out_c_reg(Some(RegisterCode::L)),
-            OutCA => cpu.out_c_reg(Some(RegisterCode::A)),
-
-            OutI => cpu.out_id(true),
-            OutD => cpu.out_id(false),
-            OutIR => cpu.out_id_rep(true),
-            OutDR => cpu.out_id_rep(false),
-
-            InCB => cpu.in_reg_c(Some(RegisterCode::B)),
-            InCD => cpu.in_reg_c(Some(RegisterCode::D)),
-            InCH => cpu.in_reg_c(Some(RegisterCode::H)),
-            InC0 => cpu.in_reg_c(None),
-            InCC => cpu.in_reg_c(Some(RegisterCode::C)),
-            InCE => cpu.in_reg_c(Some(RegisterCode::E)),
-            InCL => cpu.in_reg_c(Some(RegisterCode::L)),
-            InCA => cpu.in_reg_c(Some(RegisterCode::A)),
-
-            InI => cpu.in_id(true),
-            InD => cpu.in_id(false),
-            InIR => cpu.in_id_rep(true),
-            InDR => cpu.in_id_rep(false),
-        }
+    /// The opcodes without an instruction do nothing, like two no-ops
+    fn unknown<B: Z80Bus>(cpu: &mut Cpu<B>) {
+        bus::log!(
+            Cpu,
+            Warn,
+            "undefined extended opcode before 0x{:04x}",
+            cpu.get_pc()
+        );
     }
 }
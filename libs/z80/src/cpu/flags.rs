@@ -0,0 +1,103 @@
+//! The flags of 8-bit results, computed once for every value instead of by every
+//! instruction.  The tables are indexed by the result of the operation.
+
+use crate::cpu::Flags;
+
+pub const CARRY: u8 = 1 << Flags::Carry as u8;
+pub const SUBTRACT: u8 = 1 << Flags::Subtract as u8;
+pub const OVERFLOW_PARITY: u8 = 1 << Flags::OverflowParity as u8;
+pub const HALF_CARRY: u8 = 1 << Flags::HalfCarry as u8;
+pub const ZERO: u8 = 1 << Flags::Zero as u8;
+pub const SIGN: u8 = 1 << Flags::Sign as u8;
+
+/// Every flag an 8-bit arithmetic or logic operation sets
+pub const SZHPNC: u8 = SIGN | ZERO | HALF_CARRY | OVERFLOW_PARITY | SUBTRACT | CARRY;
+/// The flags of an 8-bit increment or decrement, which leave the carry alone
+pub const SZHPN: u8 = SZHPNC & !CARRY;
+
+/// Sign, zero and even parity
+pub const SZP: [u8; 256] = szp();
+/// The flags of `inc`, half carry when the low nibble wrapped and overflow at 0x7F + 1
+pub const SZHV_INC: [u8; 256] = szhv_inc();
+/// The flags of `dec`, half carry on a borrow from bit 4 and overflow at 0x80 - 1
+pub const SZHV_DEC: [u8; 256] = szhv_dec();
+
+const fn sz(val: u8) -> u8 {
+    let sign = val & SIGN;
+    if val == 0 {
+        sign | ZERO
+    } else {
+        sign
+    }
+}
+
+const fn szp() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut val = 0;
+    while val < 256 {
+        table[val] = sz(val as u8);
+        if (val as u8).count_ones() & 1 == 0 {
+            table[val] |= OVERFLOW_PARITY;
+        }
+        val += 1;
+    }
+    table
+}
+
+const fn szhv_inc() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut val = 0;
+    while val < 256 {
+        table[val] = sz(val as u8);
+        if val & 0x0F == 0 {
+            table[val] |= HALF_CARRY;
+        }
+        if val == 0x80 {
+            table[val] |= OVERFLOW_PARITY;
+        }
+        val += 1;
+    }
+    table
+}
+
+const fn szhv_dec() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut val = 0;
+    while val < 256 {
+        table[val] = sz(val as u8) | SUBTRACT;
+        if val & 0x0F == 0x0F {
+            table[val] |= HALF_CARRY;
+        }
+        if val == 0x7F {
+            table[val] |= OVERFLOW_PARITY;
+        }
+        val += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_szp() {
+        assert_eq!(ZERO | OVERFLOW_PARITY, SZP[0x00]);
+        assert_eq!(0, SZP[0x01]);
+        assert_eq!(OVERFLOW_PARITY, SZP[0x03]);
+        assert_eq!(SIGN, SZP[0x80]);
+        assert_eq!(SIGN | OVERFLOW_PARITY, SZP[0xFF]);
+    }
+
+    #[test]
+    fn test_szhv() {
+        assert_eq!(ZERO | HALF_CARRY, SZHV_INC[0x00]);
+        assert_eq!(SIGN | HALF_CARRY | OVERFLOW_PARITY, SZHV_INC[0x80]);
+        assert_eq!(0, SZHV_INC[0x7F]);
+
+        assert_eq!(ZERO | SUBTRACT, SZHV_DEC[0x00]);
+        assert_eq!(SIGN | HALF_CARRY | SUBTRACT, SZHV_DEC[0xFF]);
+        assert_eq!(HALF_CARRY | OVERFLOW_PARITY | SUBTRACT, SZHV_DEC[0x7F]);
+        assert_eq!(SIGN | SUBTRACT, SZHV_DEC[0x80]);
+    }
+}
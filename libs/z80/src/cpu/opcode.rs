@@ -1,9 +1,10 @@
 extern crate num;
 
 use crate::cpu::{
-    bits::BitsOpcode, extended::Extnd, BitsOperator, BitsOperatorDefault, Cpu, IndexedBitsOperator,
-    RegisterCode, RegisterCode16,
+    bits::BitsOpcode, extended::Extnd, timing, BitsOperatorDefault, Cpu, Flags,
+    IndexedBitsOperator, RegisterCode, RegisterCode16, Z80Bus,
 };
+use std::marker::PhantomData;
 
 #[repr(u8)]
 #[derive(FromPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
@@ -389,606 +390,672 @@ pub enum Opcode {
     Cpl = 0x2F,
 }
 
-impl Opcode {
-    pub fn from_u8(value: u8) -> Opcode {
-        num::FromPrimitive::from_u8(value).expect(&format!("Opcode not found: {:x}", value))
+/// An instruction, run once its opcode was fetched
+pub type Instruction<B> = fn(&mut Cpu<B>);
+
+/// The instructions indexed by opcode, one table for each register that stands in for HL
+struct Tables<B>(PhantomData<B>);
+
+impl<B: Z80Bus> Tables<B> {
+    const HL: [Instruction<B>; 256] = Opcode::table::<B, Hl>();
+    /// After a DD prefix
+    const IX: [Instruction<B>; 256] = Opcode::table::<B, Ix>();
+    /// After an FD prefix
+    const IY: [Instruction<B>; 256] = Opcode::table::<B, Iy>();
+}
+
+/// The register pair an instruction uses as HL, which the DD and FD prefixes swap for IX
+/// and IY
+trait Index {
+    const REG: RegisterCode16;
+    const UPPER: RegisterCode;
+    const LOWER: RegisterCode;
+
+    /// The address of a (HL) operand, which is (IX+d) or (IY+d) for the index registers
+    fn pointer<B: Z80Bus>(cpu: &mut Cpu<B>) -> u16;
+
+    /// The address and the literal of `ld (HL), n`, the displacement of (IX+d) comes first
+    fn pointer_lit<B: Z80Bus>(cpu: &mut Cpu<B>) -> (u16, u8);
+
+    /// Run the CB prefixed instruction that follows
+    fn bits<B: Z80Bus>(cpu: &mut Cpu<B>);
+}
+
+struct Hl;
+
+impl Index for Hl {
+    const REG: RegisterCode16 = RegisterCode16::HL;
+    const UPPER: RegisterCode = RegisterCode::H;
+    const LOWER: RegisterCode = RegisterCode::L;
+
+    fn pointer<B: Z80Bus>(cpu: &mut Cpu<B>) -> u16 {
+        cpu.indirect_reg_addr(RegisterCode16::HL)
     }
 
-    pub fn operate_u8(cpu: &mut Cpu, value: u8) {
-        Opcode::operate(cpu, Opcode::from_u8(value));
+    fn pointer_lit<B: Z80Bus>(cpu: &mut Cpu<B>) -> (u16, u8) {
+        (cpu.indirect_reg_addr(RegisterCode16::HL), cpu.imm_addr())
     }
 
-    pub fn operate(cpu: &mut Cpu, opcode: Opcode) {
-        Opcode::operate_with(
-            cpu,
-            opcode,
-            RegisterCode16::HL,
-            RegisterCode::H,
-            RegisterCode::L,
-            &mut Opcode::rel_addr(RegisterCode16::HL),
-            BitsOperatorDefault {},
-        );
+    fn bits<B: Z80Bus>(cpu: &mut Cpu<B>) {
+        let opcode = cpu.fetch_opcode();
+        cpu.add_ticks(timing::CB[opcode as usize]);
+        BitsOpcode::operate_u8(cpu, opcode, &mut BitsOperatorDefault {});
     }
+}
 
-    fn rel_addr(reg: RegisterCode16) -> impl FnMut(&mut Cpu) -> u16 {
-        move |cpu| cpu.indirect_reg_addr(reg)
+struct Ix;
+
+impl Index for Ix {
+    const REG: RegisterCode16 = RegisterCode16::IX;
+    const UPPER: RegisterCode = RegisterCode::IXh;
+    const LOWER: RegisterCode = RegisterCode::IXl;
+
+    fn pointer<B: Z80Bus>(cpu: &mut Cpu<B>) -> u16 {
+        indexed_pointer(cpu, RegisterCode16::IX)
     }
 
-    fn index_addr(reg: RegisterCode16) -> impl FnMut(&mut Cpu) -> u16 {
-        move |cpu| {
-            cpu.queue_clock_tick(8);
-            cpu.index_addr(reg)
-        }
+    fn pointer_lit<B: Z80Bus>(cpu: &mut Cpu<B>) -> (u16, u8) {
+        indexed_pointer_lit(cpu, RegisterCode16::IX)
     }
 
-    fn operate_with<T, U>(
-        cpu: &mut Cpu,
-        opcode: Opcode,
-        reg: RegisterCode16,
-        upper: RegisterCode,
-        lower: RegisterCode,
-        pointer: &mut T,
-        mut bits_op: U,
-    ) where
-        T: FnMut(&mut Cpu) -> u16,
-        U: BitsOperator,
-    {
-        use super::Flags;
-        // println!("Found opcode: {:?}", opcode);
-        match opcode {
-            Opcode::NoOp => cpu.noop(),
+    fn bits<B: Z80Bus>(cpu: &mut Cpu<B>) {
+        indexed_bits(cpu, RegisterCode16::IX);
+    }
+}
 
-            // ld Reg, Reg
-            Opcode::LdBB => cpu.ld_reg_reg(RegisterCode::B, RegisterCode::B),
-            Opcode::LdBC => cpu.ld_reg_reg(RegisterCode::B, RegisterCode::C),
-            Opcode::LdBD => cpu.ld_reg_reg(RegisterCode::B, RegisterCode::D),
-            Opcode::LdBE => cpu.ld_reg_reg(RegisterCode::B, RegisterCode::E),
-            Opcode::LdBH => cpu.ld_reg_reg(RegisterCode::B, upper),
-            Opcode::LdBL => cpu.ld_reg_reg(RegisterCode::B, lower),
-            Opcode::LdBA => cpu.ld_reg_reg(RegisterCode::B, RegisterCode::A),
-            Opcode::LdDB => cpu.ld_reg_reg(RegisterCode::D, RegisterCode::B),
-            Opcode::LdDC => cpu.ld_reg_reg(RegisterCode::D, RegisterCode::C),
-            Opcode::LdDD => cpu.ld_reg_reg(RegisterCode::D, RegisterCode::D),
-            Opcode::LdDE => cpu.ld_reg_reg(RegisterCode::D, RegisterCode::E),
-            Opcode::LdDH => cpu.ld_reg_reg(RegisterCode::D, upper),
-            Opcode::LdDL => cpu.ld_reg_reg(RegisterCode::D, lower),
-            Opcode::LdDA => cpu.ld_reg_reg(RegisterCode::D, RegisterCode::A),
-            Opcode::LdHB => cpu.ld_reg_reg(upper, RegisterCode::B),
-            Opcode::LdHC => cpu.ld_reg_reg(upper, RegisterCode::C),
-            Opcode::LdHD => cpu.ld_reg_reg(upper, RegisterCode::D),
-            Opcode::LdHE => cpu.ld_reg_reg(upper, RegisterCode::E),
-            Opcode::LdHH => cpu.ld_reg_reg(upper, upper),
-            Opcode::LdHL => cpu.ld_reg_reg(upper, lower),
-            Opcode::LdHA => cpu.ld_reg_reg(upper, RegisterCode::A),
-            Opcode::LdCB => cpu.ld_reg_reg(RegisterCode::C, RegisterCode::B),
-            Opcode::LdCC => cpu.ld_reg_reg(RegisterCode::C, RegisterCode::C),
-            Opcode::LdCD => cpu.ld_reg_reg(RegisterCode::C, RegisterCode::D),
-            Opcode::LdCE => cpu.ld_reg_reg(RegisterCode::C, RegisterCode::E),
-            Opcode::LdCH => cpu.ld_reg_reg(RegisterCode::C, upper),
-            Opcode::LdCL => cpu.ld_reg_reg(RegisterCode::C, lower),
-            Opcode::LdCA => cpu.ld_reg_reg(RegisterCode::C, RegisterCode::A),
-            Opcode::LdEB => cpu.ld_reg_reg(RegisterCode::E, RegisterCode::B),
-            Opcode::LdEC => cpu.ld_reg_reg(RegisterCode::E, RegisterCode::C),
-            Opcode::LdED => cpu.ld_reg_reg(RegisterCode::E, RegisterCode::D),
-            Opcode::LdEE => cpu.ld_reg_reg(RegisterCode::E, RegisterCode::E),
-            Opcode::LdEH => cpu.ld_reg_reg(RegisterCode::E, upper),
-            Opcode::LdEL => cpu.ld_reg_reg(RegisterCode::E, lower),
-            Opcode::LdEA => cpu.ld_reg_reg(RegisterCode::E, RegisterCode::A),
-            Opcode::LdLB => cpu.ld_reg_reg(lower, RegisterCode::B),
-            Opcode::LdLC => cpu.ld_reg_reg(lower, RegisterCode::C),
-            Opcode::LdLD => cpu.ld_reg_reg(lower, RegisterCode::D),
-            Opcode::LdLE => cpu.ld_reg_reg(lower, RegisterCode::E),
-            Opcode::LdLH => cpu.ld_reg_reg(lower, upper),
-            Opcode::LdLL => cpu.ld_reg_reg(lower, lower),
-            Opcode::LdLA => cpu.ld_reg_reg(lower, RegisterCode::A),
-            Opcode::LdAB => cpu.ld_reg_reg(RegisterCode::A, RegisterCode::B),
-            Opcode::LdAC => cpu.ld_reg_reg(RegisterCode::A, RegisterCode::C),
-            Opcode::LdAD => cpu.ld_reg_reg(RegisterCode::A, RegisterCode::D),
-            Opcode::LdAE => cpu.ld_reg_reg(RegisterCode::A, RegisterCode::E),
-            Opcode::LdAH => cpu.ld_reg_reg(RegisterCode::A, upper),
-            Opcode::LdAL => cpu.ld_reg_reg(RegisterCode::A, lower),
-            Opcode::LdAA => cpu.ld_reg_reg(RegisterCode::A, RegisterCode::A),
+struct Iy;
 
-            // Load Reg, Literal
-            Opcode::LdBLit => cpu.ld_reg_lit(RegisterCode::B),
-            Opcode::LdDLit => cpu.ld_reg_lit(RegisterCode::D),
-            Opcode::LdHLit => cpu.ld_reg_lit(upper),
-            Opcode::LdCLit => cpu.ld_reg_lit(RegisterCode::C),
-            Opcode::LdELit => cpu.ld_reg_lit(RegisterCode::E),
-            Opcode::LdLLit => cpu.ld_reg_lit(lower),
-            Opcode::LdALit => cpu.ld_reg_lit(RegisterCode::A),
+impl Index for Iy {
+    const REG: RegisterCode16 = RegisterCode16::IY;
+    const UPPER: RegisterCode = RegisterCode::IYh;
+    const LOWER: RegisterCode = RegisterCode::IYl;
 
-            // Load Reg, (16 Bit Pair)
-            Opcode::LdBHLptr => {
-                let addr = pointer(cpu);
-                cpu.ld_reg_addr(RegisterCode::B, addr)
-            }
-            Opcode::LdDHLptr => {
-                let addr = pointer(cpu);
-                cpu.ld_reg_addr(RegisterCode::D, addr)
-            }
-            Opcode::LdHHLptr => {
-                let addr = pointer(cpu);
-                cpu.ld_reg_addr(RegisterCode::H, addr)
-            }
-            Opcode::LdCHLptr => {
-                let addr = pointer(cpu);
-                cpu.ld_reg_addr(RegisterCode::C, addr)
-            }
-            Opcode::LdEHLptr => {
-                let addr = pointer(cpu);
-                cpu.ld_reg_addr(RegisterCode::E, addr)
-            }
-            Opcode::LdLHLptr => {
-                let addr = pointer(cpu);
-                cpu.ld_reg_addr(RegisterCode::L, addr)
-            }
-            Opcode::LdAHLptr => {
-                let addr = pointer(cpu);
-                cpu.ld_reg_addr(RegisterCode::A, addr)
-            }
-            Opcode::LdABCptr => {
-                let addr = cpu.indirect_reg_addr(RegisterCode16::BC);
-                cpu.ld_reg_addr(RegisterCode::A, addr)
-            }
-            Opcode::LdADEptr => {
-                let addr = cpu.indirect_reg_addr(RegisterCode16::DE);
-                cpu.ld_reg_addr(RegisterCode::A, addr)
-            }
+    fn pointer<B: Z80Bus>(cpu: &mut Cpu<B>) -> u16 {
+        indexed_pointer(cpu, RegisterCode16::IY)
+    }
 
-            // Load (HL), Reg
-            Opcode::LdHLptrB => {
-                let addr = pointer(cpu);
-                cpu.ld_addr_reg(addr, RegisterCode::B);
-            }
-            Opcode::LdHLptrC => {
-                let addr = pointer(cpu);
-                cpu.ld_addr_reg(addr, RegisterCode::C);
-            }
-            Opcode::LdHLptrD => {
-                let addr = pointer(cpu);
-                cpu.ld_addr_reg(addr, RegisterCode::D);
-            }
-            Opcode::LdHLptrE => {
-                let addr = pointer(cpu);
-                cpu.ld_addr_reg(addr, RegisterCode::E);
-            }
-            Opcode::LdHLptrH => {
-                let addr = pointer(cpu);
-                cpu.ld_addr_reg(addr, upper);
-            }
-            Opcode::LdHLptrL => {
-                let addr = pointer(cpu);
-                cpu.ld_addr_reg(addr, lower);
-            }
-            Opcode::LdHLptrA => {
-                let addr = pointer(cpu);
-                cpu.ld_addr_reg(addr, RegisterCode::A);
-            }
+    fn pointer_lit<B: Z80Bus>(cpu: &mut Cpu<B>) -> (u16, u8) {
+        indexed_pointer_lit(cpu, RegisterCode16::IY)
+    }
 
-            // Ld (HL), literal
-            Opcode::LdHlptrLit => {
-                let addr = pointer(cpu);
-                let val = cpu.imm_addr();
-                cpu.ld_addr_lit(addr, val);
-            }
+    fn bits<B: Z80Bus>(cpu: &mut Cpu<B>) {
+        indexed_bits(cpu, RegisterCode16::IY);
+    }
+}
 
-            // ld (literal), Reg
-            Opcode::LdLitptrH => {
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_addr_reg(addr, upper);
-            }
+/// (IX+d) or (IY+d), the cpu spends 5 T-states adding the displacement
+fn indexed_pointer<B: Z80Bus>(cpu: &mut Cpu<B>, reg: RegisterCode16) -> u16 {
+    let addr = cpu.index_addr(reg);
+    cpu.tick_clock(5);
+    addr
+}
 
-            Opcode::LdLitptrA => {
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_addr_reg(addr, RegisterCode::A);
-            }
+/// The displacement and the literal of `ld (IX+d), n`, the addition overlaps the read of n
+fn indexed_pointer_lit<B: Z80Bus>(cpu: &mut Cpu<B>, reg: RegisterCode16) -> (u16, u8) {
+    let addr = cpu.index_addr(reg);
+    let val = cpu.imm_addr();
+    cpu.tick_clock(2);
+    (addr, val)
+}
 
-            // ld (16 bit pair), reg
-            Opcode::LdBCptrA => {
-                let addr = cpu.reg_value_16(RegisterCode16::BC);
-                cpu.ld_addr_reg(addr, RegisterCode::A);
-            }
-            Opcode::LdDEptrA => {
-                let addr = cpu.reg_value_16(RegisterCode16::DE);
-                cpu.ld_addr_reg(addr, RegisterCode::A);
-            }
-
-            Opcode::LdBCLit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(RegisterCode16::BC, val);
-            }
-
-            Opcode::LdDELit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(RegisterCode16::DE, val);
-            }
-
-            Opcode::LdHLLit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(reg, val);
-            }
-
-            Opcode::LdSpLit => {
-                let val = cpu.imm_addr_ex();
-                cpu.ld_reg16_lit(RegisterCode16::SP, val);
-            }
-
-            Opcode::LdSpHL => {
-                cpu.ld_reg16_reg16(RegisterCode16::SP, reg);
-            }
-
-            Opcode::LdALitptr => {
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_reg_addr(RegisterCode::A, addr);
-            }
-            Opcode::LdHLLitptr => {
-                let addr = cpu.imm_addr_ex();
-                cpu.ld_reg16_addr(reg, addr);
-            }
-            /* ------------- inc Reg ------------- */
-            Opcode::IncB => cpu.inc_reg(RegisterCode::B),
-            Opcode::IncD => cpu.inc_reg(RegisterCode::D),
-            Opcode::IncH => cpu.inc_reg(upper),
-            Opcode::IncC => cpu.inc_reg(RegisterCode::C),
-            Opcode::IncE => cpu.inc_reg(RegisterCode::E),
-            Opcode::IncL => cpu.inc_reg(lower),
-            Opcode::IncA => cpu.inc_reg(RegisterCode::A),
-
-            Opcode::IncHLptr => {
-                let addr = pointer(cpu);
-                cpu.inc_addr(addr);
-            }
-
-            Opcode::IncBC => cpu.inc_reg16(RegisterCode16::BC),
-            Opcode::IncDE => cpu.inc_reg16(RegisterCode16::DE),
-            Opcode::IncHL => cpu.inc_reg16(reg),
-            Opcode::IncSP => cpu.inc_reg16(RegisterCode16::SP),
-
-            Opcode::DecB => cpu.dec_reg(RegisterCode::B),
-            Opcode::DecD => cpu.dec_reg(RegisterCode::D),
-            Opcode::DecH => cpu.dec_reg(upper),
-            Opcode::DecC => cpu.dec_reg(RegisterCode::C),
-            Opcode::DecE => cpu.dec_reg(RegisterCode::E),
-            Opcode::DecL => cpu.dec_reg(lower),
-            Opcode::DecA => cpu.dec_reg(RegisterCode::A),
-
-            Opcode::DecHLptr => {
-                let addr = pointer(cpu);
-                cpu.dec_addr(addr);
-            }
-
-            Opcode::DecBC => cpu.dec_reg16(RegisterCode16::BC),
-            Opcode::DecDE => cpu.dec_reg16(RegisterCode16::DE),
-            Opcode::DecHL => cpu.dec_reg16(reg),
-            Opcode::DecSP => cpu.dec_reg16(RegisterCode16::SP),
-
-            Opcode::AddAB => cpu.add_a_reg(RegisterCode::B),
-            Opcode::AddAC => cpu.add_a_reg(RegisterCode::C),
-            Opcode::AddAD => cpu.add_a_reg(RegisterCode::D),
-            Opcode::AddAE => cpu.add_a_reg(RegisterCode::E),
-            Opcode::AddAH => cpu.add_a_reg(upper),
-            Opcode::AddAL => cpu.add_a_reg(lower),
-            Opcode::AddAA => cpu.add_a_reg(RegisterCode::A),
-            Opcode::AddAHLptr => {
-                let addr = pointer(cpu);
-                cpu.add_a_addr(addr);
-            }
-
-            Opcode::AddALit => {
-                let lit = cpu.imm_addr();
-                cpu.add_a_lit(lit);
-            }
+/// DD CB d op and FD CB d op: the opcode comes after the displacement and is read like it,
+/// not fetched
+fn indexed_bits<B: Z80Bus>(cpu: &mut Cpu<B>, reg: RegisterCode16) {
+    let addr = cpu.index_addr(reg);
+    let opcode = cpu.imm_addr();
+    cpu.tick_clock(2);
+    cpu.add_ticks(timing::XYCB[opcode as usize]);
+    BitsOpcode::operate_u8(cpu, opcode, &mut IndexedBitsOperator::new(addr));
+}
 
-            // ADD Acc, Reg
-            Opcode::AdcAB => cpu.add_a_reg_carry(RegisterCode::A),
-            Opcode::AdcAC => cpu.add_a_reg_carry(RegisterCode::C),
-            Opcode::AdcAD => cpu.add_a_reg_carry(RegisterCode::D),
-            Opcode::AdcAE => cpu.add_a_reg_carry(RegisterCode::E),
-            Opcode::AdcAH => cpu.add_a_reg_carry(upper),
-            Opcode::AdcAL => cpu.add_a_reg_carry(lower),
-            Opcode::AdcAA => cpu.add_a_reg_carry(RegisterCode::A),
-            Opcode::AdcAHLptr => {
-                let addr = pointer(cpu);
-                cpu.add_a_addr_carry(addr);
-            }
-            Opcode::AdcALit => {
-                let val = cpu.imm_addr();
-                cpu.add_a_lit_carry(val);
-            }
-
-            Opcode::AddHLBC => cpu.add_reg16_reg16(reg, RegisterCode16::BC),
-            Opcode::AddHLDE => cpu.add_reg16_reg16(reg, RegisterCode16::DE),
-            Opcode::AddHLHL => cpu.add_reg16_reg16(reg, reg),
-            Opcode::AddHLSP => cpu.add_reg16_reg16(reg, RegisterCode16::SP),
-
-            Opcode::SubAB => cpu.sub_a_reg(RegisterCode::B),
-            Opcode::SubAC => cpu.sub_a_reg(RegisterCode::C),
-            Opcode::SubAD => cpu.sub_a_reg(RegisterCode::D),
-            Opcode::SubAE => cpu.sub_a_reg(RegisterCode::E),
-            Opcode::SubAH => cpu.sub_a_reg(upper),
-            Opcode::SubAL => cpu.sub_a_reg(lower),
-            Opcode::SubAA => cpu.sub_a_reg(RegisterCode::A),
-            Opcode::SubAHLptr => {
-                let addr = pointer(cpu);
-                cpu.sub_a_addr(addr);
-            }
-
-            Opcode::SubALit => {
-                let lit = cpu.imm_addr();
-                cpu.sub_a_lit(lit);
-            }
-
-            Opcode::SubcAB => cpu.sub_a_reg_carry(RegisterCode::A),
-            Opcode::SubcAC => cpu.sub_a_reg_carry(RegisterCode::C),
-            Opcode::SubcAD => cpu.sub_a_reg_carry(RegisterCode::D),
-            Opcode::SubcAE => cpu.sub_a_reg_carry(RegisterCode::E),
-            Opcode::SubcAH => cpu.sub_a_reg_carry(upper),
-            Opcode::SubcAL => cpu.sub_a_reg_carry(lower),
-            Opcode::SubcAA => cpu.sub_a_reg_carry(RegisterCode::A),
-            Opcode::SubcAHLptr => {
-                let addr = pointer(cpu);
-                cpu.sub_a_addr_carry(addr);
-            }
-            Opcode::SubcALit => {
-                let val = cpu.imm_addr();
-                cpu.sub_a_lit_carry(val);
-            }
-            Opcode::AndAB => cpu.and_a_reg(RegisterCode::B),
-            Opcode::AndAC => cpu.and_a_reg(RegisterCode::C),
-            Opcode::AndAD => cpu.and_a_reg(RegisterCode::D),
-            Opcode::AndAE => cpu.and_a_reg(RegisterCode::E),
-            Opcode::AndAH => cpu.and_a_reg(upper),
-            Opcode::AndAL => cpu.and_a_reg(lower),
-            Opcode::AndAA => cpu.and_a_reg(RegisterCode::A),
-            Opcode::AndAHLptr => {
-                let addr = pointer(cpu);
-                cpu.and_a_addr(addr);
-            }
-            Opcode::AndALit => {
-                let val = cpu.imm_addr();
-                cpu.and_a_lit(val);
-            }
-
-            Opcode::OrAB => cpu.or_a_reg(RegisterCode::B),
-            Opcode::OrAC => cpu.or_a_reg(RegisterCode::C),
-            Opcode::OrAD => cpu.or_a_reg(RegisterCode::D),
-            Opcode::OrAE => cpu.or_a_reg(RegisterCode::E),
-            Opcode::OrAH => cpu.or_a_reg(upper),
-            Opcode::OrAL => cpu.or_a_reg(lower),
-            Opcode::OrAA => cpu.or_a_reg(RegisterCode::A),
-            Opcode::OrAHLptr => {
-                let addr = pointer(cpu);
-                cpu.or_a_addr(addr);
-            }
-            Opcode::OrALit => {
-                let val = cpu.imm_addr();
-                cpu.or_a_lit(val);
-            }
-
-            Opcode::XorAB => cpu.xor_a_reg(RegisterCode::B),
-            Opcode::XorAC => cpu.xor_a_reg(RegisterCode::C),
-            Opcode::XorAD => cpu.xor_a_reg(RegisterCode::D),
-            Opcode::XorAE => cpu.xor_a_reg(RegisterCode::E),
-            Opcode::XorAH => cpu.xor_a_reg(upper),
-            Opcode::XorAL => cpu.xor_a_reg(lower),
-            Opcode::XorAA => cpu.xor_a_reg(RegisterCode::A),
-            Opcode::XorAHLptr => {
-                let addr = pointer(cpu);
-                cpu.xor_a_addr(addr);
-            }
-            Opcode::XorALit => {
-                let val = cpu.imm_addr();
-                cpu.xor_a_lit(val);
-            }
-
-            Opcode::CpAB => cpu.cp_a_reg(RegisterCode::B),
-            Opcode::CpAC => cpu.cp_a_reg(RegisterCode::C),
-            Opcode::CpAD => cpu.cp_a_reg(RegisterCode::D),
-            Opcode::CpAE => cpu.cp_a_reg(RegisterCode::E),
-            Opcode::CpAH => cpu.cp_a_reg(upper),
-            Opcode::CpAL => cpu.cp_a_reg(lower),
-            Opcode::CpAA => cpu.cp_a_reg(RegisterCode::A),
-            Opcode::CpAHLptr => {
-                let addr = pointer(cpu);
-                cpu.cp_a_addr(addr);
-            }
-            Opcode::CpALit => {
-                let val = cpu.imm_addr();
-                cpu.cp_a_lit(val);
-            }
-            Opcode::Rlca => cpu.rlca(),
-            Opcode::Rla => cpu.rla(),
-            Opcode::Rrca => cpu.rrca(),
-            Opcode::Rra => cpu.rra(),
-
-            Opcode::JpLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp(addr);
-            }
-            Opcode::JpNzLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::Zero, false);
-            }
-            Opcode::JpNcLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::Carry, false);
-            }
-            Opcode::JpPoLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::OverflowParity, false);
-            }
-            Opcode::JpPLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::Sign, false);
-            }
-            Opcode::JpZLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::Zero, true);
-            }
-            Opcode::JpCLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::Carry, true);
-            }
-            Opcode::JpPeLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::OverflowParity, true);
-            }
-            Opcode::JpMLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.jmp_cond(addr, Flags::Sign, true);
-            }
-
-            Opcode::JrLit => {
-                let addr = cpu.rel_addr();
-                cpu.jmp(addr);
-            }
-
-            Opcode::JrCLit => {
-                let addr = cpu.rel_addr();
-                cpu.jmp_cond(addr, Flags::Carry, true);
-            }
-            Opcode::JrNcLit => {
-                let addr = cpu.rel_addr();
-                cpu.jmp_cond(addr, Flags::Carry, false);
-            }
-            Opcode::JrZLit => {
-                let addr = cpu.rel_addr();
-                cpu.jmp_cond(addr, Flags::Zero, true);
-            }
-            Opcode::JrNzLit => {
-                let addr = cpu.rel_addr();
-                cpu.jmp_cond(addr, Flags::Zero, false);
-            }
-            Opcode::JpHLptr => {
-                let addr = pointer(cpu);
-                cpu.jmp(addr);
-            }
-
-            Opcode::DJNz => cpu.djnz(),
-            Opcode::ExAfAf => cpu.ex_af_altaf(),
-            Opcode::ExSPptrHL => cpu.ex_spptr_reg(reg),
-            Opcode::Exx => cpu.exx(),
-            Opcode::ExDEHL => cpu.ex_de_hl(),
-
-            Opcode::Ei => cpu.enable_intrpt(),
-            Opcode::Di => cpu.disable_intrpt(),
-
-            Opcode::Daa => cpu.daa(),
-
-            Opcode::Scf => cpu.scf(),
-            Opcode::Ccf => cpu.ccf(),
-
-            Opcode::CallLit => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_addr(addr);
-            }
-            Opcode::CallNz => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::Zero, false);
-            }
-            Opcode::CallNc => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::Carry, false);
-            }
-            Opcode::CallPo => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::OverflowParity, false);
-            }
-            Opcode::CallP => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::Sign, false);
-            }
-            Opcode::CallZ => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::Zero, true);
-            }
-            Opcode::CallC => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::Carry, true);
-            }
-            Opcode::CallPe => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::OverflowParity, true);
-            }
-            Opcode::CallM => {
-                let addr = cpu.imm_addr_ex();
-                cpu.call_cond_addr(addr, Flags::Sign, true);
-            }
-
-            Opcode::Ret => cpu.ret(),
-            Opcode::RetNz => cpu.ret_cond(Flags::Zero, false),
-            Opcode::RetNc => cpu.ret_cond(Flags::Carry, false),
-            Opcode::RetPo => cpu.ret_cond(Flags::OverflowParity, false),
-            Opcode::RetP => cpu.ret_cond(Flags::Sign, false),
-            Opcode::RetZ => cpu.ret_cond(Flags::Zero, true),
-            Opcode::RetC => cpu.ret_cond(Flags::Carry, true),
-            Opcode::RetPe => cpu.ret_cond(Flags::OverflowParity, true),
-            Opcode::RetM => cpu.ret_cond(Flags::Sign, true),
-
-            Opcode::Halt => cpu.halt(),
-
-            Opcode::Rst00 => cpu.rst_lit(0x00),
-            Opcode::Rst10 => cpu.rst_lit(0x10),
-            Opcode::Rst20 => cpu.rst_lit(0x20),
-            Opcode::Rst30 => cpu.rst_lit(0x30),
-            Opcode::Rst08 => cpu.rst_lit(0x08),
-            Opcode::Rst18 => cpu.rst_lit(0x18),
-            Opcode::Rst28 => cpu.rst_lit(0x28),
-            Opcode::Rst38 => cpu.rst_lit(0x38),
-
-            Opcode::InALit => cpu.in_a_lit(),
-            Opcode::OutALit => cpu.out_a_lit(),
-
-            Opcode::PshAF => cpu.push_reg16(RegisterCode16::AF),
-            Opcode::PshBC => cpu.push_reg16(RegisterCode16::BC),
-            Opcode::PshDE => cpu.push_reg16(RegisterCode16::DE),
-            Opcode::PshHL => cpu.push_reg16(reg),
-
-            Opcode::PopBC => cpu.pop_reg16(RegisterCode16::BC),
-            Opcode::PopDE => cpu.pop_reg16(RegisterCode16::DE),
-            Opcode::PopHL => cpu.pop_reg16(reg),
-            Opcode::PopAF => cpu.pop_reg16(RegisterCode16::AF),
-
-            Opcode::Cpl => cpu.cpl(),
+impl Opcode {
+    pub fn from_u8(value: u8) -> Opcode {
+        num::FromPrimitive::from_u8(value).expect(&format!("Opcode not found: {:x}", value))
+    }
 
-            // Extended Opcodes
-            Opcode::Ix => {
-                cpu.queue_clock_tick(4);
-                let opcode = Opcode::from_u8(cpu.imm_addr());
-                Opcode::operate_with(
-                    cpu,
-                    opcode,
-                    RegisterCode16::IX,
-                    RegisterCode::IXh,
-                    RegisterCode::IXl,
-                    &mut Opcode::index_addr(RegisterCode16::IX),
-                    IndexedBitsOperator::new(RegisterCode16::IX),
-                );
-            }
-
-            Opcode::Iy => {
-                cpu.queue_clock_tick(4);
-                let opcode = Opcode::from_u8(cpu.imm_addr());
-                Opcode::operate_with(
-                    cpu,
-                    opcode,
-                    RegisterCode16::IY,
-                    RegisterCode::IYh,
-                    RegisterCode::IYl,
-                    &mut Opcode::index_addr(RegisterCode16::IY),
-                    IndexedBitsOperator::new(RegisterCode16::IY),
-                );
-            }
-            Opcode::Bits => {
-                let bits_opcode = cpu.imm_addr();
-                BitsOpcode::operate_u8(cpu, bits_opcode, &mut bits_op);
-            }
-            Opcode::Extd => {
-                let extd_opcode = cpu.imm_addr();
-                Extnd::operate_u8(cpu, extd_opcode);
-            }
-        }
+    /// Run the instruction of an opcode that has been fetched
+    pub fn operate_u8<B: Z80Bus>(cpu: &mut Cpu<B>, value: u8) {
+        cpu.add_ticks(timing::MAIN[value as usize]);
+        Tables::<B>::HL[value as usize](cpu);
+    }
+
+    pub fn operate<B: Z80Bus>(cpu: &mut Cpu<B>, opcode: Opcode) {
+        Opcode::operate_u8(cpu, opcode as u8);
+    }
+
+    /// The instructions of every opcode, with `I` in place of HL
+    const fn table<B: Z80Bus, I: Index>() -> [Instruction<B>; 256] {
+        // every opcode is overwritten below, all 256 of them are instructions
+        let mut t: [Instruction<B>; 256] = [|_| unreachable!(); 256];
+
+        t[Opcode::NoOp as usize] = |cpu| cpu.noop();
+
+        // ld Reg, Reg
+        t[Opcode::LdBB as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::B, RegisterCode::B);
+        t[Opcode::LdBC as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::B, RegisterCode::C);
+        t[Opcode::LdBD as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::B, RegisterCode::D);
+        t[Opcode::LdBE as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::B, RegisterCode::E);
+        t[Opcode::LdBH as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::B, I::UPPER);
+        t[Opcode::LdBL as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::B, I::LOWER);
+        t[Opcode::LdBA as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::B, RegisterCode::A);
+        t[Opcode::LdDB as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::D, RegisterCode::B);
+        t[Opcode::LdDC as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::D, RegisterCode::C);
+        t[Opcode::LdDD as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::D, RegisterCode::D);
+        t[Opcode::LdDE as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::D, RegisterCode::E);
+        t[Opcode::LdDH as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::D, I::UPPER);
+        t[Opcode::LdDL as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::D, I::LOWER);
+        t[Opcode::LdDA as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::D, RegisterCode::A);
+        t[Opcode::LdHB as usize] = |cpu| cpu.ld_reg_reg(I::UPPER, RegisterCode::B);
+        t[Opcode::LdHC as usize] = |cpu| cpu.ld_reg_reg(I::UPPER, RegisterCode::C);
+        t[Opcode::LdHD as usize] = |cpu| cpu.ld_reg_reg(I::UPPER, RegisterCode::D);
+        t[Opcode::LdHE as usize] = |cpu| cpu.ld_reg_reg(I::UPPER, RegisterCode::E);
+        t[Opcode::LdHH as usize] = |cpu| cpu.ld_reg_reg(I::UPPER, I::UPPER);
+        t[Opcode::LdHL as usize] = |cpu| cpu.ld_reg_reg(I::UPPER, I::LOWER);
+        t[Opcode::LdHA as usize] = |cpu| cpu.ld_reg_reg(I::UPPER, RegisterCode::A);
+        t[Opcode::LdCB as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::C, RegisterCode::B);
+        t[Opcode::LdCC as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::C, RegisterCode::C);
+        t[Opcode::LdCD as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::C, RegisterCode::D);
+        t[Opcode::LdCE as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::C, RegisterCode::E);
+        t[Opcode::LdCH as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::C, I::UPPER);
+        t[Opcode::LdCL as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::C, I::LOWER);
+        t[Opcode::LdCA as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::C, RegisterCode::A);
+        t[Opcode::LdEB as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::E, RegisterCode::B);
+        t[Opcode::LdEC as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::E, RegisterCode::C);
+        t[Opcode::LdED as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::E, RegisterCode::D);
+        t[Opcode::LdEE as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::E, RegisterCode::E);
+        t[Opcode::LdEH as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::E, I::UPPER);
+        t[Opcode::LdEL as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::E, I::LOWER);
+        t[Opcode::LdEA as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::E, RegisterCode::A);
+        t[Opcode::LdLB as usize] = |cpu| cpu.ld_reg_reg(I::LOWER, RegisterCode::B);
+        t[Opcode::LdLC as usize] = |cpu| cpu.ld_reg_reg(I::LOWER, RegisterCode::C);
+        t[Opcode::LdLD as usize] = |cpu| cpu.ld_reg_reg(I::LOWER, RegisterCode::D);
+        t[Opcode::LdLE as usize] = |cpu| cpu.ld_reg_reg(I::LOWER, RegisterCode::E);
+        t[Opcode::LdLH as usize] = |cpu| cpu.ld_reg_reg(I::LOWER, I::UPPER);
+        t[Opcode::LdLL as usize] = |cpu| cpu.ld_reg_reg(I::LOWER, I::LOWER);
+        t[Opcode::LdLA as usize] = |cpu| cpu.ld_reg_reg(I::LOWER, RegisterCode::A);
+        t[Opcode::LdAB as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, RegisterCode::B);
+        t[Opcode::LdAC as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, RegisterCode::C);
+        t[Opcode::LdAD as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, RegisterCode::D);
+        t[Opcode::LdAE as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, RegisterCode::E);
+        t[Opcode::LdAH as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, I::UPPER);
+        t[Opcode::LdAL as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, I::LOWER);
+        t[Opcode::LdAA as usize] = |cpu| cpu.ld_reg_reg(RegisterCode::A, RegisterCode::A);
+
+        // Load Reg, Literal
+        t[Opcode::LdBLit as usize] = |cpu| cpu.ld_reg_lit(RegisterCode::B);
+        t[Opcode::LdDLit as usize] = |cpu| cpu.ld_reg_lit(RegisterCode::D);
+        t[Opcode::LdHLit as usize] = |cpu| cpu.ld_reg_lit(I::UPPER);
+        t[Opcode::LdCLit as usize] = |cpu| cpu.ld_reg_lit(RegisterCode::C);
+        t[Opcode::LdELit as usize] = |cpu| cpu.ld_reg_lit(RegisterCode::E);
+        t[Opcode::LdLLit as usize] = |cpu| cpu.ld_reg_lit(I::LOWER);
+        t[Opcode::LdALit as usize] = |cpu| cpu.ld_reg_lit(RegisterCode::A);
+
+        // Load Reg, (16 Bit Pair)
+        t[Opcode::LdBHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_reg_addr(RegisterCode::B, addr)
+        };
+        t[Opcode::LdDHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_reg_addr(RegisterCode::D, addr)
+        };
+        t[Opcode::LdHHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_reg_addr(RegisterCode::H, addr)
+        };
+        t[Opcode::LdCHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_reg_addr(RegisterCode::C, addr)
+        };
+        t[Opcode::LdEHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_reg_addr(RegisterCode::E, addr)
+        };
+        t[Opcode::LdLHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_reg_addr(RegisterCode::L, addr)
+        };
+        t[Opcode::LdAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_reg_addr(RegisterCode::A, addr)
+        };
+        t[Opcode::LdABCptr as usize] = |cpu| {
+            let addr = cpu.indirect_reg_addr(RegisterCode16::BC);
+            cpu.ld_reg_addr(RegisterCode::A, addr)
+        };
+        t[Opcode::LdADEptr as usize] = |cpu| {
+            let addr = cpu.indirect_reg_addr(RegisterCode16::DE);
+            cpu.ld_reg_addr(RegisterCode::A, addr)
+        };
+
+        // Load (HL), Reg
+        t[Opcode::LdHLptrB as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_addr_reg(addr, RegisterCode::B);
+        };
+        t[Opcode::LdHLptrC as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_addr_reg(addr, RegisterCode::C);
+        };
+        t[Opcode::LdHLptrD as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_addr_reg(addr, RegisterCode::D);
+        };
+        t[Opcode::LdHLptrE as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_addr_reg(addr, RegisterCode::E);
+        };
+        t[Opcode::LdHLptrH as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_addr_reg(addr, RegisterCode::H);
+        };
+        t[Opcode::LdHLptrL as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_addr_reg(addr, RegisterCode::L);
+        };
+        t[Opcode::LdHLptrA as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.ld_addr_reg(addr, RegisterCode::A);
+        };
+
+        // Ld (HL), literal
+        t[Opcode::LdHlptrLit as usize] = |cpu| {
+            let (addr, val) = I::pointer_lit(cpu);
+            cpu.ld_addr_lit(addr, val);
+        };
+
+        // ld (literal), Reg
+        t[Opcode::LdLitptrH as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_addr_reg16(addr, I::REG);
+        };
+
+        t[Opcode::LdLitptrA as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_addr_reg(addr, RegisterCode::A);
+        };
+
+        // ld (16 bit pair), reg
+        t[Opcode::LdBCptrA as usize] = |cpu| {
+            let addr = cpu.reg_value_16(RegisterCode16::BC);
+            cpu.ld_addr_reg(addr, RegisterCode::A);
+        };
+        t[Opcode::LdDEptrA as usize] = |cpu| {
+            let addr = cpu.reg_value_16(RegisterCode16::DE);
+            cpu.ld_addr_reg(addr, RegisterCode::A);
+        };
+
+        t[Opcode::LdBCLit as usize] = |cpu| {
+            let val = cpu.imm_addr_ex();
+            cpu.ld_reg16_lit(RegisterCode16::BC, val);
+        };
+
+        t[Opcode::LdDELit as usize] = |cpu| {
+            let val = cpu.imm_addr_ex();
+            cpu.ld_reg16_lit(RegisterCode16::DE, val);
+        };
+
+        t[Opcode::LdHLLit as usize] = |cpu| {
+            let val = cpu.imm_addr_ex();
+            cpu.ld_reg16_lit(I::REG, val);
+        };
+
+        t[Opcode::LdSpLit as usize] = |cpu| {
+            let val = cpu.imm_addr_ex();
+            cpu.ld_reg16_lit(RegisterCode16::SP, val);
+        };
+
+        t[Opcode::LdSpHL as usize] = |cpu| {
+            cpu.ld_reg16_reg16(RegisterCode16::SP, I::REG);
+        };
+
+        t[Opcode::LdALitptr as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_reg_addr(RegisterCode::A, addr);
+        };
+        t[Opcode::LdHLLitptr as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.ld_reg16_addr(I::REG, addr);
+        };
+        /* ------------- inc Reg ------------- */
+        t[Opcode::IncB as usize] = |cpu| cpu.inc_reg(RegisterCode::B);
+        t[Opcode::IncD as usize] = |cpu| cpu.inc_reg(RegisterCode::D);
+        t[Opcode::IncH as usize] = |cpu| cpu.inc_reg(I::UPPER);
+        t[Opcode::IncC as usize] = |cpu| cpu.inc_reg(RegisterCode::C);
+        t[Opcode::IncE as usize] = |cpu| cpu.inc_reg(RegisterCode::E);
+        t[Opcode::IncL as usize] = |cpu| cpu.inc_reg(I::LOWER);
+        t[Opcode::IncA as usize] = |cpu| cpu.inc_reg(RegisterCode::A);
+
+        t[Opcode::IncHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.inc_addr(addr);
+        };
+
+        t[Opcode::IncBC as usize] = |cpu| cpu.inc_reg16(RegisterCode16::BC);
+        t[Opcode::IncDE as usize] = |cpu| cpu.inc_reg16(RegisterCode16::DE);
+        t[Opcode::IncHL as usize] = |cpu| cpu.inc_reg16(I::REG);
+        t[Opcode::IncSP as usize] = |cpu| cpu.inc_reg16(RegisterCode16::SP);
+
+        t[Opcode::DecB as usize] = |cpu| cpu.dec_reg(RegisterCode::B);
+        t[Opcode::DecD as usize] = |cpu| cpu.dec_reg(RegisterCode::D);
+        t[Opcode::DecH as usize] = |cpu| cpu.dec_reg(I::UPPER);
+        t[Opcode::DecC as usize] = |cpu| cpu.dec_reg(RegisterCode::C);
+        t[Opcode::DecE as usize] = |cpu| cpu.dec_reg(RegisterCode::E);
+        t[Opcode::DecL as usize] = |cpu| cpu.dec_reg(I::LOWER);
+        t[Opcode::DecA as usize] = |cpu| cpu.dec_reg(RegisterCode::A);
+
+        t[Opcode::DecHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.dec_addr(addr);
+        };
+
+        t[Opcode::DecBC as usize] = |cpu| cpu.dec_reg16(RegisterCode16::BC);
+        t[Opcode::DecDE as usize] = |cpu| cpu.dec_reg16(RegisterCode16::DE);
+        t[Opcode::DecHL as usize] = |cpu| cpu.dec_reg16(I::REG);
+        t[Opcode::DecSP as usize] = |cpu| cpu.dec_reg16(RegisterCode16::SP);
+
+        t[Opcode::AddAB as usize] = |cpu| cpu.add_a_reg(RegisterCode::B);
+        t[Opcode::AddAC as usize] = |cpu| cpu.add_a_reg(RegisterCode::C);
+        t[Opcode::AddAD as usize] = |cpu| cpu.add_a_reg(RegisterCode::D);
+        t[Opcode::AddAE as usize] = |cpu| cpu.add_a_reg(RegisterCode::E);
+        t[Opcode::AddAH as usize] = |cpu| cpu.add_a_reg(I::UPPER);
+        t[Opcode::AddAL as usize] = |cpu| cpu.add_a_reg(I::LOWER);
+        t[Opcode::AddAA as usize] = |cpu| cpu.add_a_reg(RegisterCode::A);
+        t[Opcode::AddAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.add_a_addr(addr);
+        };
+
+        t[Opcode::AddALit as usize] = |cpu| {
+            let lit = cpu.imm_addr();
+            cpu.add_a_lit(lit);
+        };
+
+        // ADD Acc, Reg
+        t[Opcode::AdcAB as usize] = |cpu| cpu.add_a_reg_carry(RegisterCode::A);
+        t[Opcode::AdcAC as usize] = |cpu| cpu.add_a_reg_carry(RegisterCode::C);
+        t[Opcode::AdcAD as usize] = |cpu| cpu.add_a_reg_carry(RegisterCode::D);
+        t[Opcode::AdcAE as usize] = |cpu| cpu.add_a_reg_carry(RegisterCode::E);
+        t[Opcode::AdcAH as usize] = |cpu| cpu.add_a_reg_carry(I::UPPER);
+        t[Opcode::AdcAL as usize] = |cpu| cpu.add_a_reg_carry(I::LOWER);
+        t[Opcode::AdcAA as usize] = |cpu| cpu.add_a_reg_carry(RegisterCode::A);
+        t[Opcode::AdcAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.add_a_addr_carry(addr);
+        };
+        t[Opcode::AdcALit as usize] = |cpu| {
+            let val = cpu.imm_addr();
+            cpu.add_a_lit_carry(val);
+        };
+
+        t[Opcode::AddHLBC as usize] = |cpu| cpu.add_reg16_reg16(I::REG, RegisterCode16::BC);
+        t[Opcode::AddHLDE as usize] = |cpu| cpu.add_reg16_reg16(I::REG, RegisterCode16::DE);
+        t[Opcode::AddHLHL as usize] = |cpu| cpu.add_reg16_reg16(I::REG, I::REG);
+        t[Opcode::AddHLSP as usize] = |cpu| cpu.add_reg16_reg16(I::REG, RegisterCode16::SP);
+
+        t[Opcode::SubAB as usize] = |cpu| cpu.sub_a_reg(RegisterCode::B);
+        t[Opcode::SubAC as usize] = |cpu| cpu.sub_a_reg(RegisterCode::C);
+        t[Opcode::SubAD as usize] = |cpu| cpu.sub_a_reg(RegisterCode::D);
+        t[Opcode::SubAE as usize] = |cpu| cpu.sub_a_reg(RegisterCode::E);
+        t[Opcode::SubAH as usize] = |cpu| cpu.sub_a_reg(I::UPPER);
+        t[Opcode::SubAL as usize] = |cpu| cpu.sub_a_reg(I::LOWER);
+        t[Opcode::SubAA as usize] = |cpu| cpu.sub_a_reg(RegisterCode::A);
+        t[Opcode::SubAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.sub_a_addr(addr);
+        };
+
+        t[Opcode::SubALit as usize] = |cpu| {
+            let lit = cpu.imm_addr();
+            cpu.sub_a_lit(lit);
+        };
+
+        t[Opcode::SubcAB as usize] = |cpu| cpu.sub_a_reg_carry(RegisterCode::A);
+        t[Opcode::SubcAC as usize] = |cpu| cpu.sub_a_reg_carry(RegisterCode::C);
+        t[Opcode::SubcAD as usize] = |cpu| cpu.sub_a_reg_carry(RegisterCode::D);
+        t[Opcode::SubcAE as usize] = |cpu| cpu.sub_a_reg_carry(RegisterCode::E);
+        t[Opcode::SubcAH as usize] = |cpu| cpu.sub_a_reg_carry(I::UPPER);
+        t[Opcode::SubcAL as usize] = |cpu| cpu.sub_a_reg_carry(I::LOWER);
+        t[Opcode::SubcAA as usize] = |cpu| cpu.sub_a_reg_carry(RegisterCode::A);
+        t[Opcode::SubcAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.sub_a_addr_carry(addr);
+        };
+        t[Opcode::SubcALit as usize] = |cpu| {
+            let val = cpu.imm_addr();
+            cpu.sub_a_lit_carry(val);
+        };
+        t[Opcode::AndAB as usize] = |cpu| cpu.and_a_reg(RegisterCode::B);
+        t[Opcode::AndAC as usize] = |cpu| cpu.and_a_reg(RegisterCode::C);
+        t[Opcode::AndAD as usize] = |cpu| cpu.and_a_reg(RegisterCode::D);
+        t[Opcode::AndAE as usize] = |cpu| cpu.and_a_reg(RegisterCode::E);
+        t[Opcode::AndAH as usize] = |cpu| cpu.and_a_reg(I::UPPER);
+        t[Opcode::AndAL as usize] = |cpu| cpu.and_a_reg(I::LOWER);
+        t[Opcode::AndAA as usize] = |cpu| cpu.and_a_reg(RegisterCode::A);
+        t[Opcode::AndAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.and_a_addr(addr);
+        };
+        t[Opcode::AndALit as usize] = |cpu| {
+            let val = cpu.imm_addr();
+            cpu.and_a_lit(val);
+        };
+
+        t[Opcode::OrAB as usize] = |cpu| cpu.or_a_reg(RegisterCode::B);
+        t[Opcode::OrAC as usize] = |cpu| cpu.or_a_reg(RegisterCode::C);
+        t[Opcode::OrAD as usize] = |cpu| cpu.or_a_reg(RegisterCode::D);
+        t[Opcode::OrAE as usize] = |cpu| cpu.or_a_reg(RegisterCode::E);
+        t[Opcode::OrAH as usize] = |cpu| cpu.or_a_reg(I::UPPER);
+        t[Opcode::OrAL as usize] = |cpu| cpu.or_a_reg(I::LOWER);
+        t[Opcode::OrAA as usize] = |cpu| cpu.or_a_reg(RegisterCode::A);
+        t[Opcode::OrAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.or_a_addr(addr);
+        };
+        t[Opcode::OrALit as usize] = |cpu| {
+            let val = cpu.imm_addr();
+            cpu.or_a_lit(val);
+        };
+
+        t[Opcode::XorAB as usize] = |cpu| cpu.xor_a_reg(RegisterCode::B);
+        t[Opcode::XorAC as usize] = |cpu| cpu.xor_a_reg(RegisterCode::C);
+        t[Opcode::XorAD as usize] = |cpu| cpu.xor_a_reg(RegisterCode::D);
+        t[Opcode::XorAE as usize] = |cpu| cpu.xor_a_reg(RegisterCode::E);
+        t[Opcode::XorAH as usize] = |cpu| cpu.xor_a_reg(I::UPPER);
+        t[Opcode::XorAL as usize] = |cpu| cpu.xor_a_reg(I::LOWER);
+        t[Opcode::XorAA as usize] = |cpu| cpu.xor_a_reg(RegisterCode::A);
+        t[Opcode::XorAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.xor_a_addr(addr);
+        };
+        t[Opcode::XorALit as usize] = |cpu| {
+            let val = cpu.imm_addr();
+            cpu.xor_a_lit(val);
+        };
+
+        t[Opcode::CpAB as usize] = |cpu| cpu.cp_a_reg(RegisterCode::B);
+        t[Opcode::CpAC as usize] = |cpu| cpu.cp_a_reg(RegisterCode::C);
+        t[Opcode::CpAD as usize] = |cpu| cpu.cp_a_reg(RegisterCode::D);
+        t[Opcode::CpAE as usize] = |cpu| cpu.cp_a_reg(RegisterCode::E);
+        t[Opcode::CpAH as usize] = |cpu| cpu.cp_a_reg(I::UPPER);
+        t[Opcode::CpAL as usize] = |cpu| cpu.cp_a_reg(I::LOWER);
+        t[Opcode::CpAA as usize] = |cpu| cpu.cp_a_reg(RegisterCode::A);
+        t[Opcode::CpAHLptr as usize] = |cpu| {
+            let addr = I::pointer(cpu);
+            cpu.cp_a_addr(addr);
+        };
+        t[Opcode::CpALit as usize] = |cpu| {
+            let val = cpu.imm_addr();
+            cpu.cp_a_lit(val);
+        };
+        t[Opcode::Rlca as usize] = |cpu| cpu.rlca();
+        t[Opcode::Rla as usize] = |cpu| cpu.rla();
+        t[Opcode::Rrca as usize] = |cpu| cpu.rrca();
+        t[Opcode::Rra as usize] = |cpu| cpu.rra();
+
+        t[Opcode::JpLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp(addr);
+        };
+        t[Opcode::JpNzLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::Zero, false);
+        };
+        t[Opcode::JpNcLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::Carry, false);
+        };
+        t[Opcode::JpPoLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::OverflowParity, false);
+        };
+        t[Opcode::JpPLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::Sign, false);
+        };
+        t[Opcode::JpZLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::Zero, true);
+        };
+        t[Opcode::JpCLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::Carry, true);
+        };
+        t[Opcode::JpPeLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::OverflowParity, true);
+        };
+        t[Opcode::JpMLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.jmp_cond(addr, Flags::Sign, true);
+        };
+
+        t[Opcode::JrLit as usize] = |cpu| {
+            let addr = cpu.rel_addr();
+            cpu.jmp(addr);
+        };
+
+        t[Opcode::JrCLit as usize] = |cpu| cpu.jmp_rel_cond(Flags::Carry, true);
+        t[Opcode::JrNcLit as usize] = |cpu| cpu.jmp_rel_cond(Flags::Carry, false);
+        t[Opcode::JrZLit as usize] = |cpu| cpu.jmp_rel_cond(Flags::Zero, true);
+        t[Opcode::JrNzLit as usize] = |cpu| cpu.jmp_rel_cond(Flags::Zero, false);
+        // jp (hl) jumps to HL itself, not to what it points at
+        t[Opcode::JpHLptr as usize] = |cpu| cpu.jmp_addr(I::REG);
+
+        t[Opcode::DJNz as usize] = |cpu| cpu.djnz();
+        t[Opcode::ExAfAf as usize] = |cpu| cpu.ex_af_altaf();
+        t[Opcode::ExSPptrHL as usize] = |cpu| cpu.ex_spptr_reg(I::REG);
+        t[Opcode::Exx as usize] = |cpu| cpu.exx();
+        t[Opcode::ExDEHL as usize] = |cpu| cpu.ex_de_hl();
+
+        t[Opcode::Ei as usize] = |cpu| cpu.enable_intrpt();
+        t[Opcode::Di as usize] = |cpu| cpu.disable_intrpt();
+
+        t[Opcode::Daa as usize] = |cpu| cpu.daa();
+
+        t[Opcode::Scf as usize] = |cpu| cpu.scf();
+        t[Opcode::Ccf as usize] = |cpu| cpu.ccf();
+
+        t[Opcode::CallLit as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_addr(addr);
+        };
+        t[Opcode::CallNz as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::Zero, false);
+        };
+        t[Opcode::CallNc as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::Carry, false);
+        };
+        t[Opcode::CallPo as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::OverflowParity, false);
+        };
+        t[Opcode::CallP as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::Sign, false);
+        };
+        t[Opcode::CallZ as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::Zero, true);
+        };
+        t[Opcode::CallC as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::Carry, true);
+        };
+        t[Opcode::CallPe as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::OverflowParity, true);
+        };
+        t[Opcode::CallM as usize] = |cpu| {
+            let addr = cpu.imm_addr_ex();
+            cpu.call_cond_addr(addr, Flags::Sign, true);
+        };
+
+        t[Opcode::Ret as usize] = |cpu| cpu.ret();
+        t[Opcode::RetNz as usize] = |cpu| cpu.ret_cond(Flags::Zero, false);
+        t[Opcode::RetNc as usize] = |cpu| cpu.ret_cond(Flags::Carry, false);
+        t[Opcode::RetPo as usize] = |cpu| cpu.ret_cond(Flags::OverflowParity, false);
+        t[Opcode::RetP as usize] = |cpu| cpu.ret_cond(Flags::Sign, false);
+        t[Opcode::RetZ as usize] = |cpu| cpu.ret_cond(Flags::Zero, true);
+        t[Opcode::RetC as usize] = |cpu| cpu.ret_cond(Flags::Carry, true);
+        t[Opcode::RetPe as usize] = |cpu| cpu.ret_cond(Flags::OverflowParity, true);
+        t[Opcode::RetM as usize] = |cpu| cpu.ret_cond(Flags::Sign, true);
+
+        // the PC stays on the HALT until an interrupt is accepted
+        t[Opcode::Halt as usize] = |cpu| {
+            let pc = cpu.get_pc();
+            cpu.set_pc(pc.wrapping_sub(1));
+            cpu.halt();
+        };
+
+        t[Opcode::Rst00 as usize] = |cpu| cpu.rst_lit(0x00);
+        t[Opcode::Rst10 as usize] = |cpu| cpu.rst_lit(0x10);
+        t[Opcode::Rst20 as usize] = |cpu| cpu.rst_lit(0x20);
+        t[Opcode::Rst30 as usize] = |cpu| cpu.rst_lit(0x30);
+        t[Opcode::Rst08 as usize] = |cpu| cpu.rst_lit(0x08);
+        t[Opcode::Rst18 as usize] = |cpu| cpu.rst_lit(0x18);
+        t[Opcode::Rst28 as usize] = |cpu| cpu.rst_lit(0x28);
+        t[Opcode::Rst38 as usize] = |cpu| cpu.rst_lit(0x38);
+
+        t[Opcode::InALit as usize] = |cpu| cpu.in_a_lit();
+        t[Opcode::OutALit as usize] = |cpu| cpu.out_a_lit();
+
+        t[Opcode::PshAF as usize] = |cpu| cpu.push_reg16(RegisterCode16::AF);
+        t[Opcode::PshBC as usize] = |cpu| cpu.push_reg16(RegisterCode16::BC);
+        t[Opcode::PshDE as usize] = |cpu| cpu.push_reg16(RegisterCode16::DE);
+        t[Opcode::PshHL as usize] = |cpu| cpu.push_reg16(I::REG);
+
+        t[Opcode::PopBC as usize] = |cpu| cpu.pop_reg16(RegisterCode16::BC);
+        t[Opcode::PopDE as usize] = |cpu| cpu.pop_reg16(RegisterCode16::DE);
+        t[Opcode::PopHL as usize] = |cpu| cpu.pop_reg16(I::REG);
+        t[Opcode::PopAF as usize] = |cpu| cpu.pop_reg16(RegisterCode16::AF);
+
+        t[Opcode::Cpl as usize] = |cpu| cpu.cpl();
+
+        // Extended Opcodes
+        // the prefixes take no time of their own, the table of the prefix times the
+        // instruction that follows along with it
+        t[Opcode::Ix as usize] = |cpu| {
+            let opcode = cpu.fetch_opcode();
+            cpu.add_ticks(timing::XY[opcode as usize]);
+            Tables::<B>::IX[opcode as usize](cpu);
+        };
+
+        t[Opcode::Iy as usize] = |cpu| {
+            let opcode = cpu.fetch_opcode();
+            cpu.add_ticks(timing::XY[opcode as usize]);
+            Tables::<B>::IY[opcode as usize](cpu);
+        };
+        t[Opcode::Bits as usize] = I::bits;
+        t[Opcode::Extd as usize] = |cpu| {
+            let extd_opcode = cpu.fetch_opcode();
+            cpu.add_ticks(timing::ED[extd_opcode as usize]);
+            Extnd::operate_u8(cpu, extd_opcode);
+        };
+
+        t
     }
 
     fn decode(opcode: Opcode) -> String {
@@ -1077,7 +1144,7 @@ impl Opcode {
             Opcode::LdHlptrLit => String::from("Ld (HL), *"),
 
             // ld (literal), Reg
-            Opcode::LdLitptrH => String::from("Ld (**), H"),
+            Opcode::LdLitptrH => String::from("Ld (**), HL"),
             Opcode::LdLitptrA => String::from("Ld (**), A"),
             // ld (16 bit pair), reg
             Opcode::LdBCptrA => String::from("Ld (BC), A"),
@@ -0,0 +1,350 @@
+//! Runs single-step test vectors against the cpu
+//!
+//! A vector is one instruction: the registers and the memory before and after it, the
+//! T-states it takes and the ports it uses, in the JSON format of the community
+//! SingleStepTests suite, where a file like `dd 7e.json` holds the vectors of one opcode.
+//! A few hand-checked vectors in that format are kept in `tests/single_step`, named like
+//! `dd_cb_06.json`, with at least one for every prefix and for some of the undocumented
+//! instructions and flags.  To run a whole suite, point `Z80_SINGLE_STEP_DIR` at its files:
+//!     Z80_SINGLE_STEP_DIR=~/z80/v1 cargo test single_step -- --nocapture
+//!
+//! Only what the cpu emulates is compared, so the undocumented bits 3 and 5 of F, WZ
+//! (also called MEMPTR), Q and P are left out.
+
+use super::{Cpu, CpuState, Z80Bus};
+use serde::Deserialize;
+use std::{
+    env, fmt, fs,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+};
+
+/// Set this environment variable to a directory of vectors to run all of them
+const DIR_VAR: &str = "Z80_SINGLE_STEP_DIR";
+
+/// The bits of AF that are compared, all but the undocumented bits 3 and 5 of F
+const FLAGS: u16 = !0x28;
+
+/// The failures that are printed for every opcode, the rest are only counted
+const SHOWN: usize = 3;
+
+/// The vectors that come with the crate
+const BUNDLED: &[(&str, &str)] = &[
+    ("00", include_str!("../../tests/single_step/00.json")),
+    ("08", include_str!("../../tests/single_step/08.json")),
+    ("3e", include_str!("../../tests/single_step/3e.json")),
+    ("77", include_str!("../../tests/single_step/77.json")),
+    ("c5", include_str!("../../tests/single_step/c5.json")),
+    ("cb 00", include_str!("../../tests/single_step/cb_00.json")),
+    ("cb 30", include_str!("../../tests/single_step/cb_30.json")),
+    ("d3", include_str!("../../tests/single_step/d3.json")),
+    ("d9", include_str!("../../tests/single_step/d9.json")),
+    ("db", include_str!("../../tests/single_step/db.json")),
+    ("dd 21", include_str!("../../tests/single_step/dd_21.json")),
+    ("dd 24", include_str!("../../tests/single_step/dd_24.json")),
+    ("dd cb __ 06", include_str!("../../tests/single_step/dd_cb_06.json")),
+    ("ed 44", include_str!("../../tests/single_step/ed_44.json")),
+    ("ed 70", include_str!("../../tests/single_step/ed_70.json")),
+    ("fd 7e", include_str!("../../tests/single_step/fd_7e.json")),
+    ("fd cb __ 46", include_str!("../../tests/single_step/fd_cb_46.json")),
+];
+
+#[derive(Deserialize)]
+struct Vector {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    /// One entry for every T-state, only the number of them is compared
+    cycles: Vec<(u16, Option<u8>, String)>,
+    /// The port accesses in order, as (port, value, "r" or "w")
+    #[serde(default)]
+    ports: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    i: u8,
+    r: u8,
+    ix: u16,
+    iy: u16,
+    af_: u16,
+    bc_: u16,
+    de_: u16,
+    hl_: u16,
+    im: u8,
+    iff1: u8,
+    iff2: u8,
+    /// The bytes the instruction uses, as (address, value)
+    ram: Vec<(u16, u8)>,
+}
+
+impl State {
+    fn cpu_state(&self) -> CpuState {
+        let pair = |high: u8, low: u8| (high as u16) << 8 | low as u16;
+        CpuState {
+            af: pair(self.a, self.f),
+            bc: pair(self.b, self.c),
+            de: pair(self.d, self.e),
+            hl: pair(self.h, self.l),
+            af_alt: self.af_,
+            bc_alt: self.bc_,
+            de_alt: self.de_,
+            hl_alt: self.hl_,
+            ix: self.ix,
+            iy: self.iy,
+            sp: self.sp,
+            pc: self.pc,
+            i: self.i,
+            r: self.r,
+            iff1: self.iff1 != 0,
+            iff2: self.iff2 != 0,
+            interrupt_mode: self.im,
+            halted: false,
+        }
+    }
+}
+
+/// 64K of ram, and ports that read what the vector says they read
+struct TestBus {
+    memory: Vec<u8>,
+    /// The values still to be read, as (port, value)
+    inputs: Vec<(u16, u8)>,
+    outputs: Vec<(u16, u8)>,
+    written: Vec<u16>,
+}
+
+impl Z80Bus for TestBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize] = val;
+        self.written.push(addr);
+    }
+
+    fn input(&mut self, port: u16) -> u8 {
+        match self.inputs.iter().position(|&(p, _)| p == port) {
+            Some(index) => self.inputs.remove(index).1,
+            None => 0xFF,
+        }
+    }
+
+    fn output(&mut self, port: u16, val: u8) {
+        self.outputs.push((port, val));
+    }
+}
+
+/// A cpu in the state before the instruction of `vector`
+fn cpu(vector: &Vector) -> Cpu<TestBus> {
+    let state = &vector.initial;
+    let mut memory = vec![0; 0x10000];
+    for &(addr, val) in &state.ram {
+        memory[addr as usize] = val;
+    }
+    let inputs = vector
+        .ports
+        .iter()
+        .filter(|(_, _, dir)| dir == "r")
+        .map(|&(port, val, _)| (port, val))
+        .collect();
+
+    let mut cpu = Cpu::with_bus(TestBus {
+        memory,
+        inputs,
+        outputs: Vec::new(),
+        written: Vec::new(),
+    });
+
+    cpu.set_state(&state.cpu_state());
+    cpu
+}
+
+/// Run the instruction of `vector`, giving every difference to the expected state
+fn run(vector: &Vector) -> Vec<String> {
+    let mut cpu = cpu(vector);
+    let ticks = cpu.do_operation();
+
+    let state = &vector.expected;
+    let mut errors = Vec::new();
+    let after = cpu.state();
+    let expected_after = state.cpu_state();
+    for &(name, actual, expected) in &[
+        ("af", after.af & FLAGS, expected_after.af & FLAGS),
+        ("bc", after.bc, expected_after.bc),
+        ("de", after.de, expected_after.de),
+        ("hl", after.hl, expected_after.hl),
+        ("af'", after.af_alt, expected_after.af_alt),
+        ("bc'", after.bc_alt, expected_after.bc_alt),
+        ("de'", after.de_alt, expected_after.de_alt),
+        ("hl'", after.hl_alt, expected_after.hl_alt),
+        ("ix", after.ix, expected_after.ix),
+        ("iy", after.iy, expected_after.iy),
+        ("sp", after.sp, expected_after.sp),
+        ("pc", after.pc, expected_after.pc),
+        ("i", after.i as u16, expected_after.i as u16),
+        ("r", after.r as u16, expected_after.r as u16),
+        ("iff1", after.iff1 as u16, expected_after.iff1 as u16),
+        ("iff2", after.iff2 as u16, expected_after.iff2 as u16),
+        (
+            "im",
+            after.interrupt_mode as u16,
+            expected_after.interrupt_mode as u16,
+        ),
+        ("cycles", ticks as u16, vector.cycles.len() as u16),
+    ] {
+        if actual != expected {
+            errors.push(format!(
+                "{} 0x{:x}, expected 0x{:x}",
+                name, actual, expected
+            ));
+        }
+    }
+
+    let bus = cpu.bus();
+    for &(addr, expected) in &state.ram {
+        let actual = bus.memory[addr as usize];
+        if actual != expected {
+            errors.push(format!(
+                "(0x{:04x}) 0x{:x}, expected 0x{:x}",
+                addr, actual, expected
+            ));
+        }
+    }
+    for &addr in &bus.written {
+        if !state.ram.iter().any(|&(a, _)| a == addr) {
+            errors.push(format!("wrote (0x{:04x})", addr));
+        }
+    }
+
+    let outputs: Vec<_> = vector
+        .ports
+        .iter()
+        .filter(|(_, _, dir)| dir == "w")
+        .map(|&(port, val, _)| (port, val))
+        .collect();
+    if bus.outputs != outputs {
+        errors.push(format!("out {:x?}, expected {:x?}", bus.outputs, outputs));
+    }
+    for (port, _) in &bus.inputs {
+        errors.push(format!("did not read port 0x{:04x}", port));
+    }
+
+    errors
+}
+
+/// The vectors of one opcode that did not pass
+struct Report {
+    opcode: String,
+    total: usize,
+    /// The name of every vector that failed, with what went wrong
+    failures: Vec<(String, String)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} of {} failed",
+            self.opcode,
+            self.failures.len(),
+            self.total
+        )?;
+        for (name, error) in self.failures.iter().take(SHOWN) {
+            write!(f, "\n  {}: {}", name, error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every vector of one opcode, where `json` is the contents of its file
+fn check(opcode: &str, json: &str) -> Report {
+    let vectors: Vec<Vector> = serde_json::from_str(json)
+        .unwrap_or_else(|err| panic!("Could not parse the vectors of {}: {}", opcode, err));
+
+    let failures = vectors
+        .iter()
+        .filter_map(|vector| {
+            // an instruction that panics fails, without stopping the others
+            let error = match panic::catch_unwind(AssertUnwindSafe(|| run(vector))) {
+                Ok(errors) if errors.is_empty() => return None,
+                Ok(errors) => errors.join(", "),
+                Err(_) => "panicked".to_string(),
+            };
+            Some((vector.name.clone(), error))
+        })
+        .collect();
+
+    Report {
+        opcode: opcode.to_string(),
+        total: vectors.len(),
+        failures,
+    }
+}
+
+/// Print the opcodes that failed and panic if there are any
+fn assert_passed(reports: Vec<Report>) {
+    let opcodes = reports.len();
+    let failed: Vec<_> = reports
+        .into_iter()
+        .filter(|report| !report.failures.is_empty())
+        .collect();
+    for report in &failed {
+        println!("{}", report);
+    }
+    assert!(
+        failed.is_empty(),
+        "{} of {} opcodes failed",
+        failed.len(),
+        opcodes
+    );
+}
+
+#[test]
+fn test_bundled() {
+    assert_passed(
+        BUNDLED
+            .iter()
+            .map(|(opcode, json)| check(opcode, json))
+            .collect(),
+    );
+}
+
+/// Runs the vectors in `Z80_SINGLE_STEP_DIR`, if it is set
+#[test]
+fn test_dir() {
+    let dir = match env::var_os(DIR_VAR) {
+        Some(dir) => PathBuf::from(dir),
+        None => return,
+    };
+
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|err| panic!("Could not read {}: {}", dir.display(), err))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    assert_passed(
+        files
+            .iter()
+            .map(|path| {
+                let json = fs::read_to_string(path)
+                    .unwrap_or_else(|err| panic!("Could not read {}: {}", path.display(), err));
+                let opcode = path.file_stem().unwrap().to_string_lossy();
+                check(&opcode, &json)
+            })
+            .collect(),
+    );
+}
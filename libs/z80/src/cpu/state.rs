@@ -0,0 +1,191 @@
+//! The registers and the interrupt state of the cpu as one value, for debuggers, test
+//! harnesses and save states
+
+use super::{Cpu, RegisterCode, RegisterCode16, Z80Bus};
+use serde::{Deserialize, Serialize};
+
+/// Everything a program can see of the cpu
+///
+/// Read it with `Cpu::state` and write it back with `Cpu::set_state`.  The timing of the
+/// instruction that is running is not part of it, so it is only complete between two
+/// calls to `Cpu::do_operation`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuState {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    /// The shadow AF, swapped in by `ex af, af'`
+    pub af_alt: u16,
+    /// The shadow BC, DE and HL, swapped in by `exx`
+    pub bc_alt: u16,
+    pub de_alt: u16,
+    pub hl_alt: u16,
+    pub ix: u16,
+    pub iy: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub i: u8,
+    pub r: u8,
+    pub iff1: bool,
+    pub iff2: bool,
+    /// 0, 1 or 2, as set by `im`
+    pub interrupt_mode: u8,
+    /// The cpu ran a `halt` and waits for an interrupt
+    pub halted: bool,
+}
+
+/// The registers of the main and the shadow set, as (high, low) pairs in the order of
+/// AF, BC, DE and HL
+const PAIRS: [(RegisterCode, RegisterCode); 4] = [
+    (RegisterCode::A, RegisterCode::Flags),
+    (RegisterCode::B, RegisterCode::C),
+    (RegisterCode::D, RegisterCode::E),
+    (RegisterCode::H, RegisterCode::L),
+];
+
+fn pair(reg: &[u16; 8], (high, low): (RegisterCode, RegisterCode)) -> u16 {
+    (reg[high as usize] << 8) | reg[low as usize]
+}
+
+fn set_pair(reg: &mut [u16; 8], (high, low): (RegisterCode, RegisterCode), val: u16) {
+    reg[high as usize] = val >> 8;
+    reg[low as usize] = val & 0xFF;
+}
+
+impl<B: Z80Bus> Cpu<B> {
+    /// The registers and the interrupt state
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            af: self.reg_value_16(RegisterCode16::AF),
+            bc: self.reg_value_16(RegisterCode16::BC),
+            de: self.reg_value_16(RegisterCode16::DE),
+            hl: self.reg_value_16(RegisterCode16::HL),
+            af_alt: pair(&self.alt_reg, PAIRS[0]),
+            bc_alt: pair(&self.alt_reg, PAIRS[1]),
+            de_alt: pair(&self.alt_reg, PAIRS[2]),
+            hl_alt: pair(&self.alt_reg, PAIRS[3]),
+            ix: self.reg_value_16(RegisterCode16::IX),
+            iy: self.reg_value_16(RegisterCode16::IY),
+            sp: self.reg_value_16(RegisterCode16::SP),
+            pc: self.get_pc(),
+            i: self.reg_value(RegisterCode::I),
+            r: self.reg_value(RegisterCode::R),
+            iff1: self.iff1,
+            iff2: self.iff2,
+            interrupt_mode: self.interrupt_mode,
+            halted: self.halted,
+        }
+    }
+
+    /// Replace the registers and the interrupt state
+    pub fn set_state(&mut self, state: &CpuState) {
+        for &(code, val) in &[
+            (RegisterCode16::AF, state.af),
+            (RegisterCode16::BC, state.bc),
+            (RegisterCode16::DE, state.de),
+            (RegisterCode16::HL, state.hl),
+            (RegisterCode16::IX, state.ix),
+            (RegisterCode16::IY, state.iy),
+            (RegisterCode16::SP, state.sp),
+            (RegisterCode16::PC, state.pc),
+            (RegisterCode16::I, state.i as u16),
+            (RegisterCode16::R, state.r as u16),
+        ] {
+            self.set_reg_value_16(code, val);
+        }
+        let alt = [state.af_alt, state.bc_alt, state.de_alt, state.hl_alt];
+        for (&regs, &val) in PAIRS.iter().zip(alt.iter()) {
+            set_pair(&mut self.alt_reg, regs, val);
+        }
+        self.iff1 = state.iff1;
+        self.iff2 = state.iff2;
+        self.interrupt_mode = state.interrupt_mode;
+        self.halted = state.halted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Flat {
+        memory: Vec<u8>,
+    }
+
+    impl Z80Bus for Flat {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.memory[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            self.memory[addr as usize] = val;
+        }
+
+        fn input(&mut self, _port: u16) -> u8 {
+            0xFF
+        }
+
+        fn output(&mut self, _port: u16, _val: u8) {}
+    }
+
+    fn cpu(program: &[u8]) -> Cpu<Flat> {
+        let mut memory = vec![0; 0x10000];
+        memory[..program.len()].copy_from_slice(program);
+        Cpu::with_bus(Flat { memory })
+    }
+
+    fn state() -> CpuState {
+        CpuState {
+            af: 0x0102,
+            bc: 0x0304,
+            de: 0x0506,
+            hl: 0x0708,
+            af_alt: 0x1112,
+            bc_alt: 0x1314,
+            de_alt: 0x1516,
+            hl_alt: 0x1718,
+            ix: 0x2122,
+            iy: 0x2324,
+            sp: 0x2526,
+            pc: 0,
+            i: 0x31,
+            r: 0x32,
+            iff1: true,
+            iff2: false,
+            interrupt_mode: 2,
+            halted: false,
+        }
+    }
+
+    #[test]
+    fn test_set_state() {
+        let mut cpu = cpu(&[]);
+        cpu.set_state(&state());
+        assert_eq!(state(), cpu.state());
+        assert_eq!(0x02, cpu.reg_value(RegisterCode::Flags));
+        assert_eq!(0x0708, cpu.reg_value_16(RegisterCode16::HL));
+        assert_eq!(0x22, cpu.reg_value(RegisterCode::IXl));
+    }
+
+    #[test]
+    fn test_shadow_registers() {
+        // ex af, af' ; exx
+        let mut cpu = cpu(&[0x08, 0xD9]);
+        cpu.set_state(&state());
+        cpu.do_operation();
+        cpu.do_operation();
+
+        let swapped = cpu.state();
+        assert_eq!((0x1112, 0x0102), (swapped.af, swapped.af_alt));
+        assert_eq!((0x1314, 0x0304), (swapped.bc, swapped.bc_alt));
+        assert_eq!((0x1516, 0x0506), (swapped.de, swapped.de_alt));
+        assert_eq!((0x1718, 0x0708), (swapped.hl, swapped.hl_alt));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let json = serde_json::to_string(&state()).unwrap();
+        assert_eq!(state(), serde_json::from_str::<CpuState>(&json).unwrap());
+    }
+}
@@ -8,8 +8,6 @@ pub mod cpu;
 #[cfg(test)]
 mod tests {
     use super::cpu::*;
-    use bus::{bus::*, ram::*, MemoryMap};
-    use std::{cell::RefCell, rc::Rc};
     #[rustfmt::skip]
     fn get_buf() -> Vec<u8> {
         vec![
@@ -34,10 +32,7 @@ mod tests {
     #[test]
     fn test_cpu1() {
         let buf = get_buf();
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::builder().add(buf).build())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(buf);
 
         cpu.do_operation();
         cpu.do_operation();
@@ -55,10 +50,7 @@ mod tests {
     #[test]
     fn test_cpu2() {
         let buf = get_buf();
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::builder().add(buf).build())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(buf);
 
         cpu.do_operation();
         cpu.do_operation();
@@ -72,10 +64,7 @@ mod tests {
         assert_eq!(0x01, cpu.reg_value(RegisterCode::B));
         assert!(cpu.flag(Flags::Carry));
 
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::builder().add(get_buf2()).build())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(get_buf2());
         while cpu.reg_value(RegisterCode::A) < 10 {
             cpu.do_operation();
         }
@@ -91,10 +80,7 @@ mod tests {
             0xcb, 0x3c, 0xcb, 0x1d, 0xcb, 0x38, 0xcb, 0x19, 0x30, 0xe3,
         ];
 
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::builder().add(buf).build())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(buf);
 
         while cpu.next_byte_no_inc() != 0 {
             cpu.do_operation();
@@ -111,20 +97,7 @@ mod tests {
             0xcb, 0x3c, 0xcb, 0x1d, 0xcb, 0x38, 0xcb, 0x19, 0x30, 0xe3,
         ];
 
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(
-                Bus::builder()
-                    .add(
-                        Ram::builder()
-                            .data(buf)
-                            .size(0xFF)
-                            .map(MemoryMap::from(0x00..0xFFFF))
-                            .build(),
-                    )
-                    .build(),
-            )),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(buf);
 
         while cpu.next_byte_no_inc() != 0 {
             cpu.do_operation();
@@ -142,10 +115,7 @@ mod tests {
 
         println!("Buf: {:?}", buf);
 
-        let mut cpu = Cpu::new(
-            &Rc::new(RefCell::new(Bus::builder().add(buf).build())),
-            &Rc::new(RefCell::new(Bus::default())),
-        );
+        let mut cpu = Cpu::with_bus(buf);
 
         while cpu.next_byte_no_inc() != 0 {
             cpu.do_operation();
@@ -153,4 +123,58 @@ mod tests {
 
         assert_eq!(9, cpu.reg_value(RegisterCode::A));
     }
+    /// A machine that owns its memory and ports
+    #[derive(Clone)]
+    struct Flat {
+        memory: Vec<u8>,
+        ports: Vec<u8>,
+    }
+
+    impl Z80Bus for Flat {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.memory[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            self.memory[addr as usize] = val;
+        }
+
+        fn input(&mut self, port: u16) -> u8 {
+            self.ports[port as usize & 0xFF]
+        }
+
+        fn output(&mut self, port: u16, val: u8) {
+            self.ports[port as usize & 0xFF] = val;
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_owned_bus() {
+        let mut memory = vec![
+            0x3E, 0x42,       // LD A, 0x42
+            0xD3, 0x10,       // OUT (0x10), A
+            0x32, 0x00, 0x80, // LD (0x8000), A
+            0xDB, 0x11,       // IN A, (0x11)
+        ];
+        memory.resize(0x10000, 0);
+        let mut ports = vec![0; 0x100];
+        ports[0x11] = 0x99;
+
+        let mut cpu = Cpu::with_bus(Flat { memory, ports });
+        cpu.do_operation();
+        cpu.do_operation();
+        cpu.do_operation();
+        let saved = cpu.clone();
+        cpu.do_operation();
+
+        assert_eq!(0x42, cpu.bus().ports[0x10]);
+        assert_eq!(0x42, cpu.bus().memory[0x8000]);
+        assert_eq!(0x99, cpu.reg_value(RegisterCode::A));
+
+        // the clone has its own memory
+        cpu.bus_mut().memory[0x8000] = 0;
+        assert_eq!(0x42, saved.bus().memory[0x8000]);
+        assert_eq!(0x42, saved.reg_value(RegisterCode::A));
+    }
 }
@@ -0,0 +1,589 @@
+use crate::emulator::Emulator;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, Write};
+use std::path::Path;
+
+#[cfg(feature = "audio-device")]
+mod device;
+mod wav;
+
+#[cfg(feature = "audio-device")]
+pub use device::DeviceSink;
+use sn76489::psg::SAMPLE_RATE;
+pub use wav::WavWriter;
+
+/// The rate the host plays audio at (Hz)
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// How far the resampling ratio may be bent to keep the buffer half full.  Half a
+/// percent is not audible as a change in pitch.
+const MAX_RATE_ADJUST: f64 = 0.005;
+
+/// Where the resampled audio goes
+pub trait AudioSink {
+    /// The rate the sink plays samples at (Hz)
+    fn sample_rate(&self) -> u32;
+
+    /// Take as many samples out of the buffer as the sink is ready for
+    fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()>;
+
+    /// Whether the sink plays the samples in real time
+    ///
+    /// The pipeline only adjusts the resampling rate for real time sinks, all others get
+    /// exactly the input length converted to their rate.
+    fn is_real_time(&self) -> bool {
+        false
+    }
+
+    /// How full the buffer of a sink that keeps samples of its own is, from 0 to 1
+    ///
+    /// The rate control keeps this one half full instead of the buffer of the pipeline.
+    fn fill(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// A sink that is picked when the program runs, like the device or nothing when there is
+/// no window
+impl AudioSink for Box<dyn AudioSink> {
+    fn sample_rate(&self) -> u32 {
+        (**self).sample_rate()
+    }
+
+    fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()> {
+        (**self).consume(buffer)
+    }
+
+    fn is_real_time(&self) -> bool {
+        (**self).is_real_time()
+    }
+
+    fn fill(&self) -> Option<f64> {
+        (**self).fill()
+    }
+}
+
+/// A sink that throws all audio away
+pub struct NullSink {
+    sample_rate: u32,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32) -> NullSink {
+        NullSink { sample_rate }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()> {
+        buffer.clear();
+        Ok(())
+    }
+}
+
+/// A sink that writes all audio to a WAV file
+pub struct WavSink<W: Write + Seek> {
+    writer: WavWriter<W>,
+    sample_rate: u32,
+    scratch: Vec<f32>,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<WavSink<BufWriter<File>>> {
+        WavSink::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(out: W, sample_rate: u32) -> io::Result<WavSink<W>> {
+        Ok(WavSink {
+            writer: WavWriter::new(out, sample_rate)?,
+            sample_rate,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// Finish the file and give back the output
+    pub fn finish(self) -> io::Result<W> {
+        self.writer.finish()
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()> {
+        self.scratch.resize(buffer.len(), 0.0);
+        buffer.read(&mut self.scratch);
+        self.writer.write_samples(&self.scratch)
+    }
+}
+
+/// A sink that writes all audio to a file as raw signed 16 bit little endian samples
+pub struct RawSink<W: Write> {
+    out: W,
+    sample_rate: u32,
+    scratch: Vec<f32>,
+}
+
+impl<W: Write> RawSink<W> {
+    pub fn new(out: W, sample_rate: u32) -> RawSink<W> {
+        RawSink {
+            out,
+            sample_rate,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> AudioSink for RawSink<W> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()> {
+        self.scratch.resize(buffer.len(), 0.0);
+        buffer.read(&mut self.scratch);
+        for &sample in self.scratch.iter() {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The PSG output of a run, written to a file as it is emulated
+///
+/// Files ending in `.wav` are written as WAV, all others as raw samples (see `RawSink`).
+/// Both are mono at `DEFAULT_SAMPLE_RATE`.
+pub enum AudioDump {
+    Wav(AudioPipeline<WavSink<BufWriter<File>>>),
+    Raw(AudioPipeline<RawSink<BufWriter<File>>>),
+}
+
+impl AudioDump {
+    /// The number of samples resampled at a time, about one frame
+    const BUFFER_SIZE: usize = DEFAULT_SAMPLE_RATE as usize / 50;
+
+    pub fn create(path: &Path) -> io::Result<AudioDump> {
+        let is_wav = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+
+        Ok(if is_wav {
+            AudioDump::Wav(AudioPipeline::new(
+                SAMPLE_RATE,
+                WavSink::create(path, DEFAULT_SAMPLE_RATE)?,
+                AudioDump::BUFFER_SIZE,
+            ))
+        } else {
+            AudioDump::Raw(AudioPipeline::new(
+                SAMPLE_RATE,
+                RawSink::new(BufWriter::new(File::create(path)?), DEFAULT_SAMPLE_RATE),
+                AudioDump::BUFFER_SIZE,
+            ))
+        })
+    }
+
+    /// Write PSG samples (at `sn76489::psg::SAMPLE_RATE`)
+    pub fn push(&mut self, samples: &[f32]) -> io::Result<()> {
+        match self {
+            AudioDump::Wav(pipeline) => pipeline.push(samples),
+            AudioDump::Raw(pipeline) => pipeline.push(samples),
+        }
+    }
+
+    /// Finish the file
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            AudioDump::Wav(pipeline) => pipeline.into_sink().finish().map(|_| ()),
+            AudioDump::Raw(pipeline) => pipeline.into_sink().finish().map(|_| ()),
+        }
+    }
+}
+
+/// Emulate `frames` frames and write their PSG output to a file (see `AudioDump`)
+pub fn dump_audio(emulator: &mut Emulator, frames: u64, path: &Path) -> io::Result<()> {
+    let mut dump = AudioDump::create(path)?;
+    for _ in 0..frames {
+        emulator.run_frame();
+        dump.push(emulator.audio())?;
+    }
+
+    dump.finish()
+}
+
+/// A fixed size queue of samples
+pub struct RingBuffer {
+    data: Vec<f32>,
+    start: usize,
+    len: usize,
+    /// The last sample read, repeated when the buffer runs dry
+    last: f32,
+}
+
+impl RingBuffer {
+    pub fn with_capacity(capacity: usize) -> RingBuffer {
+        RingBuffer {
+            data: vec![0.0; capacity.max(1)],
+            start: 0,
+            len: 0,
+            last: 0.0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    /// How full the buffer is, from 0 to 1
+    pub fn fill(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    /// Add a sample to the end, dropping it if the buffer is full
+    ///
+    /// Returns false if the sample was dropped.
+    pub fn push(&mut self, sample: f32) -> bool {
+        if self.len == self.capacity() {
+            return false;
+        }
+
+        let end = (self.start + self.len) % self.capacity();
+        self.data[end] = sample;
+        self.len += 1;
+        true
+    }
+
+    /// Fill `out` from the front of the buffer
+    ///
+    /// When the buffer runs dry the rest of `out` is filled with the last sample, which
+    /// is less audible than dropping to silence.  Returns the number of samples that were
+    /// actually in the buffer.
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.len);
+        for sample in out[..count].iter_mut() {
+            *sample = self.data[self.start];
+            self.start = (self.start + 1) % self.capacity();
+        }
+        self.len -= count;
+
+        if count > 0 {
+            self.last = out[count - 1];
+        }
+        for sample in out[count..].iter_mut() {
+            *sample = self.last;
+        }
+
+        count
+    }
+}
+
+/// Converts samples from one rate to a lower one by averaging the input samples that
+/// fall into each output sample
+pub struct Resampler {
+    /// Input samples per output sample
+    ratio: f64,
+    position: f64,
+    sum: f32,
+    count: u32,
+    last: f32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64) -> Resampler {
+        Resampler {
+            ratio: input_rate / output_rate,
+            position: 0.0,
+            sum: 0.0,
+            count: 0,
+            last: 0.0,
+        }
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    pub fn set_ratio(&mut self, ratio: f64) {
+        self.ratio = ratio;
+    }
+
+    /// Resample the input, handing every output sample to `out`
+    pub fn process(&mut self, input: &[f32], mut out: impl FnMut(f32)) {
+        for &sample in input {
+            self.sum += sample;
+            self.count += 1;
+            self.position += 1.0;
+
+            while self.position >= self.ratio {
+                if self.count > 0 {
+                    self.last = self.sum / self.count as f32;
+                }
+                out(self.last);
+                self.sum = 0.0;
+                self.count = 0;
+                self.position -= self.ratio;
+            }
+        }
+    }
+}
+
+/// Takes the chip's native output to a sink at the host's rate
+///
+/// The samples are resampled into a ring buffer that the sink reads from.  For real time
+/// sinks the resampling ratio is bent a little towards keeping the buffer half full: the
+/// emulator and the sound card never run at exactly the same speed, and without this the
+/// buffer would slowly run dry or overflow, both of which are heard as crackle.
+pub struct AudioPipeline<S: AudioSink> {
+    resampler: Resampler,
+    buffer: RingBuffer,
+    sink: S,
+    input_rate: f64,
+    scratch: Vec<f32>,
+}
+
+impl<S: AudioSink> AudioPipeline<S> {
+    /// A pipeline from `input_rate` to the sink, whose buffer holds `buffer_size` samples
+    pub fn new(input_rate: f64, sink: S, buffer_size: usize) -> AudioPipeline<S> {
+        AudioPipeline {
+            resampler: Resampler::new(input_rate, sink.sample_rate() as f64),
+            buffer: RingBuffer::with_capacity(buffer_size),
+            sink,
+            input_rate,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Resample the samples into the buffer and let the sink take them
+    ///
+    /// Samples that do not fit into the buffer because the sink is not ready for them are
+    /// dropped.
+    pub fn push(&mut self, samples: &[f32]) -> io::Result<()> {
+        if self.sink.is_real_time() {
+            // an emptier buffer makes more output samples, a fuller one fewer
+            let fill = self.sink.fill().unwrap_or_else(|| self.buffer.fill());
+            let adjust = 1.0 + MAX_RATE_ADJUST * (1.0 - 2.0 * fill);
+            let output_rate = self.sink.sample_rate() as f64 * adjust;
+            self.resampler.set_ratio(self.input_rate / output_rate);
+        }
+
+        self.scratch.clear();
+        let scratch = &mut self.scratch;
+        self.resampler
+            .process(samples, |sample| scratch.push(sample));
+
+        // hand the samples over a buffer full at a time, until the sink takes no more
+        let mut pending = &self.scratch[..];
+        loop {
+            while let Some((&sample, rest)) = pending.split_first() {
+                if !self.buffer.push(sample) {
+                    break;
+                }
+                pending = rest;
+            }
+
+            let buffered = self.buffer.len();
+            self.sink.consume(&mut self.buffer)?;
+            if pending.is_empty() || self.buffer.len() == buffered {
+                return Ok(());
+            }
+        }
+    }
+
+    /// The number of input samples per output sample
+    pub fn ratio(&self) -> f64 {
+        self.resampler.ratio()
+    }
+
+    pub fn buffer(&self) -> &RingBuffer {
+        &self.buffer
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A real time sink that plays a fixed number of samples per push
+    struct FixedSink {
+        per_push: usize,
+    }
+
+    impl AudioSink for FixedSink {
+        fn sample_rate(&self) -> u32 {
+            DEFAULT_SAMPLE_RATE
+        }
+
+        fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()> {
+            let mut out = vec![0.0; self.per_push];
+            buffer.read(&mut out);
+            Ok(())
+        }
+
+        fn is_real_time(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut buffer = RingBuffer::with_capacity(4);
+        for sample in 0..5 {
+            buffer.push(sample as f32);
+        }
+        assert_eq!(4, buffer.len());
+
+        let mut out = [0.0; 3];
+        assert_eq!(3, buffer.read(&mut out));
+        assert_eq!([0.0, 1.0, 2.0], out);
+
+        // wrap around, then run dry
+        buffer.push(4.0);
+        buffer.push(5.0);
+        assert_eq!(3, buffer.read(&mut out));
+        assert_eq!([3.0, 4.0, 5.0], out);
+        assert_eq!(0, buffer.read(&mut out));
+        assert_eq!([5.0, 5.0, 5.0], out);
+    }
+
+    #[test]
+    fn test_resampler() {
+        let mut resampler = Resampler::new(4.0, 1.0);
+        let mut out = Vec::new();
+        resampler.process(&[1.0, 1.0, 3.0, 3.0, 0.0, 0.0, 0.0, 4.0, 5.0], |s| {
+            out.push(s)
+        });
+        assert_eq!(vec![2.0, 1.0], out);
+    }
+
+    #[test]
+    fn test_rate_control() {
+        // the sink plays fewer samples than are made, so the buffer fills up and the
+        // pipeline has to make fewer samples
+        let mut pipeline = AudioPipeline::new(
+            DEFAULT_SAMPLE_RATE as f64,
+            FixedSink { per_push: 733 },
+            4096,
+        );
+        let input = vec![0.5; 735];
+        pipeline.push(&input).unwrap();
+        let start_ratio = pipeline.ratio();
+        for _ in 0..1000 {
+            pipeline.push(&input).unwrap();
+        }
+
+        assert!(pipeline.ratio() > start_ratio);
+        assert!(pipeline.buffer().fill() > 0.5);
+        assert!(pipeline.buffer().len() < pipeline.buffer().capacity());
+    }
+
+    /// A real time sink that takes everything into a buffer of its own, which is as full
+    /// as it says
+    struct BufferedSink {
+        fill: f64,
+    }
+
+    impl AudioSink for BufferedSink {
+        fn sample_rate(&self) -> u32 {
+            DEFAULT_SAMPLE_RATE
+        }
+
+        fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()> {
+            buffer.clear();
+            Ok(())
+        }
+
+        fn is_real_time(&self) -> bool {
+            true
+        }
+
+        fn fill(&self) -> Option<f64> {
+            Some(self.fill)
+        }
+    }
+
+    #[test]
+    fn test_sink_fill() {
+        // the pipeline's own buffer stays empty, the rate follows the buffer of the sink
+        let nominal = 1.0;
+        for &(fill, faster) in &[(0.9, false), (0.1, true)] {
+            let sink: Box<dyn AudioSink> = Box::new(BufferedSink { fill });
+            let mut pipeline = AudioPipeline::new(DEFAULT_SAMPLE_RATE as f64, sink, 4096);
+            pipeline.push(&[0.5; 100]).unwrap();
+
+            assert!(pipeline.buffer().is_empty());
+            assert_eq!(faster, pipeline.ratio() < nominal);
+        }
+    }
+
+    #[test]
+    fn test_wav_sink() {
+        let sink = WavSink::new(Cursor::new(Vec::new()), 1000).unwrap();
+        let mut pipeline = AudioPipeline::new(4000.0, sink, 64);
+        pipeline.push(&vec![0.25; 400]).unwrap();
+        pipeline.push(&vec![0.25; 400]).unwrap();
+
+        // all 200 samples are written, no matter the size of the buffer
+        let wav = pipeline.into_sink().finish().unwrap().into_inner();
+        assert_eq!(44 + 2 * 200, wav.len());
+    }
+
+    #[test]
+    fn test_dump_audio() {
+        let rom =
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/hello_world2.sg");
+        let dir = std::env::temp_dir();
+        let wav = dir.join(format!("sg-1000-dump-{}.wav", std::process::id()));
+        let raw = dir.join(format!("sg-1000-dump-{}.raw", std::process::id()));
+
+        dump_audio(&mut Emulator::new(&rom), 10, &wav).unwrap();
+        dump_audio(&mut Emulator::new(&rom), 10, &raw).unwrap();
+        let wav_data = std::fs::read(&wav).unwrap();
+        let raw_data = std::fs::read(&raw).unwrap();
+        std::fs::remove_file(&wav).unwrap();
+        std::fs::remove_file(&raw).unwrap();
+
+        // a frame is 262 lines of 228 cycles, except for the first which ends at the
+        // first vblank
+        let frame = 262.0 * 228.0 / 16.0 / SAMPLE_RATE * DEFAULT_SAMPLE_RATE as f64;
+        let samples = raw_data.len() as f64 / 2.0;
+        assert!(samples > 9.0 * frame && samples < 10.0 * frame);
+        assert_eq!(b"RIFF", &wav_data[..4]);
+        assert_eq!(&raw_data[..], &wav_data[44..]);
+    }
+}
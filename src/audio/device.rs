@@ -0,0 +1,134 @@
+use super::{AudioSink, RingBuffer};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Sample, SampleFormat, Stream, StreamConfig};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// The sound card could not be opened
+#[derive(Debug)]
+pub struct DeviceError(String);
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Could not open the audio device: {}", self.0)
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+fn device_error(err: impl fmt::Display) -> DeviceError {
+    DeviceError(err.to_string())
+}
+
+/// A sink that plays the audio on the host's default output device
+///
+/// The device takes the samples from a buffer of its own, on its own thread.  How full
+/// that buffer is drives the rate control of the pipeline, as it is the one that runs dry
+/// or overflows when the emulator and the sound card drift apart.
+pub struct DeviceSink {
+    buffer: Arc<Mutex<RingBuffer>>,
+    /// The error the stream stopped with, reported by the next `consume`
+    error: Arc<Mutex<Option<String>>>,
+    sample_rate: u32,
+    /// Plays for as long as it is kept
+    _stream: Stream,
+}
+
+impl DeviceSink {
+    /// Start playing on the default output device, with `buffer_size` samples buffered
+    pub fn open(buffer_size: usize) -> Result<DeviceSink, DeviceError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| device_error("there is none"))?;
+        let supported = device.default_output_config().map_err(device_error)?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+
+        let buffer = Arc::new(Mutex::new(RingBuffer::with_capacity(buffer_size)));
+        let error = Arc::new(Mutex::new(None));
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, &buffer, &error),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, &buffer, &error),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, &buffer, &error),
+        }?;
+        stream.play().map_err(device_error)?;
+
+        Ok(DeviceSink {
+            buffer,
+            error,
+            sample_rate: config.sample_rate.0,
+            _stream: stream,
+        })
+    }
+}
+
+/// A stream that plays the mono samples of `buffer` on every channel of the device
+fn build_stream<T: Sample>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    buffer: &Arc<Mutex<RingBuffer>>,
+    error: &Arc<Mutex<Option<String>>>,
+) -> Result<Stream, DeviceError> {
+    let channels = config.channels as usize;
+    let buffer = Arc::clone(buffer);
+    let error = Arc::clone(error);
+
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut buffer = match buffer.lock() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return,
+                };
+                let mut sample = [0.0];
+                for frame in data.chunks_mut(channels) {
+                    buffer.read(&mut sample);
+                    let value = T::from(&sample[0]);
+                    for out in frame.iter_mut() {
+                        *out = value;
+                    }
+                }
+            },
+            move |err| {
+                if let Ok(mut error) = error.lock() {
+                    *error = Some(err.to_string());
+                }
+            },
+        )
+        .map_err(device_error)
+}
+
+impl AudioSink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn consume(&mut self, buffer: &mut RingBuffer) -> io::Result<()> {
+        if let Some(err) = self.error.lock().ok().and_then(|mut error| error.take()) {
+            return Err(io::Error::other(err));
+        }
+
+        let mut device = self
+            .buffer
+            .lock()
+            .map_err(|_| io::Error::other("the audio thread panicked"))?;
+
+        let mut sample = [0.0];
+        while !buffer.is_empty() && device.len() < device.capacity() {
+            buffer.read(&mut sample);
+            device.push(sample[0]);
+        }
+
+        Ok(())
+    }
+
+    fn is_real_time(&self) -> bool {
+        true
+    }
+
+    fn fill(&self) -> Option<f64> {
+        self.buffer.lock().ok().map(|buffer| buffer.fill())
+    }
+}
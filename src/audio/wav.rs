@@ -0,0 +1,77 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// The size of the RIFF header in front of the samples
+const HEADER_SIZE: u32 = 44;
+
+/// Writes mono 16 bit PCM samples to a WAV file
+///
+/// The sizes in the header are only filled in by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?; // size of the format chunk
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // channels
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * 2).to_le_bytes())?; // bytes per second
+        out.write_all(&2u16.to_le_bytes())?; // bytes per sample
+        out.write_all(&16u16.to_le_bytes())?; // bits per sample
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter { out, samples: 0 })
+    }
+
+    /// Write samples between -1 and 1, anything outside is clipped
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+
+        Ok(())
+    }
+
+    /// Fill in the header and give back the output
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples * 2;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_header() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+        wav.write_samples(&[0.0, 1.0, -2.0]).unwrap();
+        let data = wav.finish().unwrap().into_inner();
+
+        assert_eq!(HEADER_SIZE as usize + 6, data.len());
+        assert_eq!(b"RIFF", &data[0..4]);
+        assert_eq!(&(36u32 + 6).to_le_bytes(), &data[4..8]);
+        assert_eq!(&44100u32.to_le_bytes(), &data[24..28]);
+        assert_eq!(&6u32.to_le_bytes(), &data[40..44]);
+        assert_eq!(&i16::MAX.to_le_bytes(), &data[46..48]);
+        assert_eq!(&(-i16::MAX).to_le_bytes(), &data[48..50]);
+    }
+}
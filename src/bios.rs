@@ -0,0 +1,256 @@
+use crate::cartridge::Cartridge;
+use bus::{
+    state::{take_u8, SaveState},
+    BusConnectable,
+};
+
+// Bits of the memory control port.  A set bit disables that part of the machine.
+pub const EXPANSION_DISABLE: u8 = 1 << 7;
+pub const CARTRIDGE_DISABLE: u8 = 1 << 6;
+pub const CARD_DISABLE: u8 = 1 << 5;
+pub const WORK_RAM_DISABLE: u8 = 1 << 4;
+pub const BIOS_DISABLE: u8 = 1 << 3;
+pub const IO_DISABLE: u8 = 1 << 2;
+
+/// The memory control value the BIOS starts with: only the BIOS, work ram and I/O are enabled
+pub const BIOS_BOOT: u8 = EXPANSION_DISABLE | CARTRIDGE_DISABLE | CARD_DISABLE;
+
+/// The memory control value the BIOS leaves behind when it hands control to the cartridge
+pub const CARTRIDGE_BOOT: u8 = EXPANSION_DISABLE | CARD_DISABLE | BIOS_DISABLE;
+
+/// The memory control port (0x3E) of the Master System
+///
+/// The port is write only.  Only A7, A6 and A0 are decoded, so every even port below 0x40
+/// mirrors it.
+#[derive(Clone)]
+pub struct MemoryControl {
+    value: u8,
+}
+
+impl MemoryControl {
+    pub fn new(value: u8) -> MemoryControl {
+        MemoryControl { value }
+    }
+
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+
+    pub fn bios_enabled(&self) -> bool {
+        self.value & BIOS_DISABLE == 0
+    }
+
+    pub fn cartridge_enabled(&self) -> bool {
+        self.value & CARTRIDGE_DISABLE == 0
+    }
+}
+
+impl BusConnectable for MemoryControl {
+    fn accept(&self, addr: u16) -> bool {
+        addr & 0xC1 == 0
+    }
+
+    fn cpu_read(&mut self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    fn cpu_write(&mut self, _addr: u16, val: u8) -> bool {
+        self.value = val;
+        true
+    }
+}
+
+impl SaveState for MemoryControl {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.push(self.value);
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        self.value = take_u8(state);
+    }
+}
+
+/// The images the slot mapper picks from
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Slot {
+    Cartridge,
+    Bios,
+}
+
+/// Maps either the BIOS or the cartridge into the cartridge's address window
+///
+/// Which one is visible is decided by the memory control port, which the mapper owns.
+/// The BIOS image is mirrored across the whole window and can not be written to.  When
+/// neither is enabled the reads see an open bus (0xFF).
+#[derive(Clone)]
+pub struct SlotMapper {
+    bios: Option<Vec<u8>>,
+    cartridge: Cartridge,
+    control: MemoryControl,
+}
+
+impl SlotMapper {
+    pub fn new(cartridge: Cartridge, bios: Option<Vec<u8>>, control: MemoryControl) -> SlotMapper {
+        SlotMapper {
+            bios: bios.filter(|bios| !bios.is_empty()),
+            cartridge,
+            control,
+        }
+    }
+
+    pub fn has_bios(&self) -> bool {
+        self.bios.is_some()
+    }
+
+    pub fn control(&self) -> &MemoryControl {
+        &self.control
+    }
+
+    pub fn control_mut(&mut self) -> &mut MemoryControl {
+        &mut self.control
+    }
+
+    /// Write the memory control port, returns whether the write was taken
+    ///
+    /// The SG-1000 has no such port and games write anything to the ports below 0x40, so
+    /// without a BIOS the writes are ignored and the cartridge stays mapped.
+    pub fn write_control(&mut self, val: u8) -> bool {
+        if self.bios.is_none() {
+            bus::log!(
+                Io,
+                Debug,
+                "memory control 0x{:02x} ignored without a BIOS",
+                val
+            );
+            return false;
+        }
+
+        bus::log!(Io, Debug, "memory control 0x{:02x}", val);
+        self.control.value = val;
+        true
+    }
+
+    /// Write a bank register of the cartridge's mapper, if the cartridge is mapped
+    pub fn write_register(&mut self, addr: u16, val: u8) -> bool {
+        if self.bios().is_some() || !self.control.cartridge_enabled() {
+            return false;
+        }
+
+        self.cartridge.write_register(addr, val)
+    }
+
+    /// Where the byte at `addr` is read from, as an image and the offset into it
+    ///
+    /// `None` when neither the BIOS nor the cartridge is mapped.
+    pub fn offset(&self, addr: u16) -> Option<(Slot, usize)> {
+        if let Some(bios) = self.bios() {
+            Some((Slot::Bios, addr as usize % bios.len()))
+        } else if self.control.cartridge_enabled() {
+            self.cartridge
+                .rom_offset(addr)
+                .map(|offset| (Slot::Cartridge, offset))
+        } else {
+            None
+        }
+    }
+
+    /// The contents of an image, empty for a BIOS that was not loaded
+    pub fn image(&self, slot: Slot) -> &[u8] {
+        match slot {
+            Slot::Cartridge => self.cartridge.rom(),
+            Slot::Bios => self.bios.as_deref().unwrap_or(&[]),
+        }
+    }
+
+    fn bios(&self) -> Option<&Vec<u8>> {
+        self.bios.as_ref().filter(|_| self.control.bios_enabled())
+    }
+}
+
+impl BusConnectable for SlotMapper {
+    fn accept(&self, addr: u16) -> bool {
+        self.cartridge.accept(addr)
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.offset(addr)
+            .map_or(0xFF, |(slot, offset)| self.image(slot)[offset])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) -> bool {
+        if self.bios().is_some() || !self.control.cartridge_enabled() {
+            return false;
+        }
+
+        self.cartridge.cpu_write(addr, data)
+    }
+}
+
+/// The cartridge's bank registers.  The BIOS can not change, and the memory control port
+/// saves its own state.
+impl SaveState for SlotMapper {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        self.cartridge.save_state(out);
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        self.cartridge.load_state(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Mapper;
+
+    /// A game whose bytes are the high byte of their address, and a 1 KB BIOS of 0xB0-0xB3
+    fn slots(with_bios: bool) -> SlotMapper {
+        let rom = (0..0x8000).map(|addr| (addr >> 8) as u8).collect();
+        let bios = (0..0x400).map(|addr| 0xB0 | (addr >> 8) as u8).collect();
+        SlotMapper::new(
+            Cartridge::new(rom, Mapper::Rom),
+            Some(bios).filter(|_| with_bios),
+            MemoryControl::new(if with_bios { BIOS_BOOT } else { CARTRIDGE_BOOT }),
+        )
+    }
+
+    #[test]
+    fn test_bios_overlay() {
+        let mut slots = slots(true);
+        assert!(slots.control().bios_enabled());
+        assert!(!slots.control().cartridge_enabled());
+        assert_eq!(0xB0, slots.cpu_read(0x0000));
+        assert_eq!(0xB3, slots.cpu_read(0x03FF));
+        assert!(!slots.cpu_write(0x0000, 0x55));
+
+        // neither is mapped
+        assert!(slots.write_control(BIOS_BOOT | BIOS_DISABLE));
+        assert_eq!(0xFF, slots.cpu_read(0x0000));
+    }
+
+    #[test]
+    fn test_hand_off() {
+        let mut slots = slots(true);
+        slots.write_control(CARTRIDGE_BOOT);
+        assert!(!slots.control().bios_enabled());
+        assert!(slots.control().cartridge_enabled());
+        assert_eq!(0x00, slots.cpu_read(0x0000));
+        assert_eq!(0x12, slots.cpu_read(0x1234));
+    }
+
+    #[test]
+    fn test_bios_mirror() {
+        let mut slots = slots(true);
+        assert_eq!(0xB2, slots.cpu_read(0x0200));
+        assert_eq!(0xB2, slots.cpu_read(0x0600));
+        assert_eq!(0xB1, slots.cpu_read(0x7D00));
+    }
+
+    #[test]
+    fn test_no_bios() {
+        let mut slots = slots(false);
+        assert!(!slots.write_control(0xFF));
+        assert_eq!(CARTRIDGE_BOOT, slots.control().value());
+        assert_eq!(0x12, slots.cpu_read(0x1234));
+    }
+}
@@ -0,0 +1,283 @@
+use bus::{
+    state::{take, SaveState},
+    BusConnectable,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The file extensions of the games that can be loaded
+pub const ROM_EXTENSIONS: [&str; 5] = ["sg", "sc", "sms", "bin", "rom"];
+
+/// Some dumps start with the 512 byte header of the copier they were made with
+const COPIER_HEADER: usize = 512;
+
+/// The largest game a plain cartridge can hold
+const ROM_SIZE: usize = 0x8000;
+
+/// The size of a bank of the Sega mapper
+const BANK_SIZE: usize = 0x4000;
+
+/// The Master System header, at the end of the first 8, 16 or 32 KB
+const SMS_SIGNATURE: &[u8] = b"TMR SEGA";
+const SMS_HEADER_OFFSETS: [usize; 3] = [0x1FF0, 0x3FF0, 0x7FF0];
+
+/// How the cartridge maps the game into the address space
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mapper {
+    /// The game is mapped as is from 0x0000 to 0x7FFF, like every SG-1000 cartridge
+    Rom,
+    /// The paging chip of larger Master System games: the game is mapped in 16 KB banks,
+    /// selected by writing to 0xFFFD, 0xFFFE and 0xFFFF.  The registers sit on top of the
+    /// work ram, which still sees the writes and answers the reads.
+    Sega,
+}
+
+impl Mapper {
+    /// The mapper a game most likely needs, by its size
+    pub fn detect(rom: &[u8]) -> Mapper {
+        if rom.len() > ROM_SIZE {
+            Mapper::Sega
+        } else {
+            Mapper::Rom
+        }
+    }
+}
+
+impl FromStr for Mapper {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Mapper, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "rom" | "none" => Ok(Mapper::Rom),
+            "sega" => Ok(Mapper::Sega),
+            _ => Err(format!("unknown mapper '{}', expected rom or sega", name)),
+        }
+    }
+}
+
+/// A game cartridge.  The game can not be written to.
+#[derive(Clone)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    mapper: Mapper,
+    /// The banks in the three 16 KB slots of the Sega mapper
+    banks: [u8; 3],
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<u8>, mapper: Mapper) -> Cartridge {
+        Cartridge {
+            rom,
+            mapper,
+            banks: [0, 1, 2],
+        }
+    }
+
+    /// Write a bank register of the Sega mapper, returns whether the address is one
+    pub fn write_register(&mut self, addr: u16, val: u8) -> bool {
+        if self.mapper != Mapper::Sega || addr < 0xFFFD {
+            return false;
+        }
+
+        self.banks[addr as usize - 0xFFFD] = val;
+        true
+    }
+
+    /// The game, mirrored when it is smaller than the address space it is mapped to
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Where the byte at `addr` is in the game, `None` if the game is empty
+    pub fn rom_offset(&self, addr: u16) -> Option<usize> {
+        if self.rom.is_empty() {
+            return None;
+        }
+
+        let addr = addr as usize;
+        let index = match self.mapper {
+            Mapper::Rom => addr,
+            // the first kilobyte holds the interrupt vectors and is never paged out
+            Mapper::Sega if addr < 0x400 => addr,
+            Mapper::Sega => self.banks[addr / BANK_SIZE] as usize * BANK_SIZE + addr % BANK_SIZE,
+        };
+        Some(index % self.rom.len())
+    }
+}
+
+impl BusConnectable for Cartridge {
+    fn accept(&self, addr: u16) -> bool {
+        match self.mapper {
+            Mapper::Rom => (addr as usize) < ROM_SIZE,
+            Mapper::Sega => addr < 0xC000,
+        }
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.rom_offset(addr).map_or(0xFF, |index| self.rom[index])
+    }
+
+    /// The game is read only, the bank registers are written with `write_register`
+    fn cpu_write(&mut self, _addr: u16, _val: u8) -> bool {
+        true
+    }
+}
+
+/// The bank registers.  The game itself can not change.
+impl SaveState for Cartridge {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.banks);
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        self.banks.copy_from_slice(take(state, 3));
+    }
+}
+
+/// Why a game could not be loaded
+#[derive(Debug)]
+pub enum RomError {
+    Io(PathBuf, io::Error),
+    /// The file does not have one of the `ROM_EXTENSIONS`
+    UnsupportedFormat(PathBuf),
+    /// The contents of the file can not be a game
+    BadHeader(PathBuf, String),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(path, err) => write!(f, "Could not read {}: {}", path.display(), err),
+            RomError::UnsupportedFormat(path) => write!(
+                f,
+                "{} is not a supported game, expected one of: .{}",
+                path.display(),
+                ROM_EXTENSIONS.join(", .")
+            ),
+            RomError::BadHeader(path, reason) => {
+                write!(f, "{} is not a valid game: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// Read a game from a file, checking that it looks like one
+///
+/// A copier header in front of the game is removed.
+pub fn load_rom(path: &Path) -> Result<Vec<u8>, RomError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    if !ROM_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(RomError::UnsupportedFormat(path.to_path_buf()));
+    }
+
+    let mut rom = fs::read(path).map_err(|err| RomError::Io(path.to_path_buf(), err))?;
+    let bad_header = |reason: String| Err(RomError::BadHeader(path.to_path_buf(), reason));
+
+    if rom.len() % 1024 == COPIER_HEADER {
+        rom.drain(..COPIER_HEADER);
+    }
+    if rom.is_empty() {
+        return bad_header("the file is empty".to_string());
+    }
+    if rom.len() % 1024 != 0 {
+        return bad_header(format!(
+            "it is {} bytes long, which is not a whole number of kilobytes",
+            rom.len()
+        ));
+    }
+
+    let has_signature = SMS_HEADER_OFFSETS
+        .iter()
+        .any(|&offset| rom.get(offset..offset + SMS_SIGNATURE.len()) == Some(SMS_SIGNATURE));
+    if extension == "sms" && !has_signature {
+        return bad_header(
+            "it has no Master System header (TMR SEGA), rename it to .sg if it is an SG-1000 game"
+                .to_string(),
+        );
+    }
+
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join(name)
+    }
+
+    #[test]
+    fn test_load_rom() {
+        assert_eq!(0x4000, load_rom(&resource("Borderline.sg")).unwrap().len());
+        assert_eq!(0x8000, load_rom(&resource("SNAKE.SG")).unwrap().len());
+        assert!(load_rom(&resource("poleposition.sms")).is_ok());
+
+        match load_rom(&resource("bacachase.asm")) {
+            Err(RomError::UnsupportedFormat(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match load_rom(&resource("missing.sg")) {
+            Err(RomError::Io(_, err)) => assert_eq!(io::ErrorKind::NotFound, err.kind()),
+            other => panic!("{:?}", other),
+        }
+
+        let dir = std::env::temp_dir();
+        let header = dir.join(format!("sg-1000-header-{}.sg", std::process::id()));
+        fs::write(&header, vec![0xAA; COPIER_HEADER + 1024]).unwrap();
+        let rom = load_rom(&header);
+        let odd = dir.join(format!("sg-1000-odd-{}.sg", std::process::id()));
+        fs::write(&odd, vec![0; 1000]).unwrap();
+        let odd_rom = load_rom(&odd);
+        let sms = dir.join(format!("sg-1000-{}.sms", std::process::id()));
+        fs::write(&sms, vec![0; 0x8000]).unwrap();
+        let sms_rom = load_rom(&sms);
+        for path in [header, odd, sms].iter() {
+            fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(1024, rom.unwrap().len());
+        assert!(matches!(odd_rom, Err(RomError::BadHeader(..))));
+        assert!(matches!(sms_rom, Err(RomError::BadHeader(..))));
+    }
+
+    #[test]
+    fn test_sega_mapper() {
+        let rom = (0..8 * BANK_SIZE)
+            .map(|index| (index / BANK_SIZE) as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(Mapper::Sega, Mapper::detect(&rom));
+        let mut cartridge = Cartridge::new(rom, Mapper::Sega);
+
+        assert_eq!(1, cartridge.cpu_read(0x4000));
+        assert!(cartridge.write_register(0xFFFE, 5));
+        assert!(cartridge.write_register(0xFFFF, 7));
+        assert_eq!(5, cartridge.cpu_read(0x4000));
+        assert_eq!(7, cartridge.cpu_read(0xBFFF));
+        // the registers are left to the work ram
+        assert!(!cartridge.accept(0xFFFE));
+        assert!(!cartridge.write_register(0xFFFC, 1));
+
+        // the first kilobyte stays in place
+        cartridge.write_register(0xFFFD, 3);
+        assert_eq!(0, cartridge.cpu_read(0x03FF));
+        assert_eq!(3, cartridge.cpu_read(0x0400));
+
+        // the game is read only
+        cartridge.cpu_write(0x0400, 0x55);
+        assert_eq!(3, cartridge.cpu_read(0x0400));
+    }
+}
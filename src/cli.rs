@@ -0,0 +1,256 @@
+use crate::cartridge::Mapper;
+use crate::config::MAX_SCALE;
+use crate::emulator::Region;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: sg-1000-emu [OPTIONS] <ROM>
+
+Options:
+    --region <ntsc|pal>        The video standard of the machine
+    --scale <1-8>              The size of a pixel in the window
+    --fullscreen               Start in fullscreen
+    --mapper <rom|sega>        Override the cartridge mapper detected from the game's size
+    --bios <FILE>              Run a BIOS image before the game
+    --config <FILE>            Read the settings from this file instead of the one in the
+                               user's config directory
+    --headless                 Run without a window, needs --frames
+    --frames <N>               Quit after N frames
+    --trace <FILE>             Write the cpu state before every instruction to FILE
+    --log <FILTER>             Log the parts of the emulator at these levels, e.g.
+                               vdp=debug,io=trace (also read from SG_LOG)
+    --screenshot-after <N>     Run N frames without a window and save a screenshot
+    --dump-audio <FILE>        Write the audio to a .wav or raw file
+    --record <FILE>            Record a movie of the input
+    --play <FILE>              Play back a movie
+    --record-video <PATH>      Record video to a .y4m file or a directory of PNGs
+    --video-audio              Record audio next to the video
+    -h, --help                 Show this message";
+
+/// The settings given on the command line
+///
+/// The settings that are not given come from the config file.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub region: Option<Region>,
+    pub scale: Option<u32>,
+    pub fullscreen: bool,
+    pub mapper: Option<Mapper>,
+    pub bios: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub headless: bool,
+    /// Quit after this many frames
+    pub frames: Option<u64>,
+    pub trace: Option<PathBuf>,
+    /// The levels of the log targets, see `bus::log::set_filter`
+    pub log: Option<String>,
+    /// Save a screenshot after this many frames, without a window
+    pub screenshot_after: Option<u64>,
+    pub dump_audio: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub record_video: Option<PathBuf>,
+    pub video_audio: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    /// The usage was asked for
+    Help,
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        reason: String,
+    },
+    MissingRom,
+    /// More than one game was given
+    ExtraArgument(String),
+    /// Two options that can not be used together
+    Conflict(&'static str),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::UnknownOption(option) => write!(f, "Unknown option {}", option),
+            CliError::MissingValue(option) => write!(f, "{} needs a value", option),
+            CliError::InvalidValue {
+                option,
+                value,
+                reason,
+            } => write!(f, "Invalid value '{}' for {}: {}", value, option, reason),
+            CliError::MissingRom => write!(f, "The path of the game was not specified"),
+            CliError::ExtraArgument(arg) => {
+                write!(f, "Only one game can be run, unexpected argument {}", arg)
+            }
+            CliError::Conflict(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Region, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            _ => Err("expected ntsc or pal".to_string()),
+        }
+    }
+}
+
+/// Parse the command line arguments, without the program name
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, CliError> {
+    let mut options = Options::default();
+    let mut rom = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| CliError::MissingValue(arg.clone()))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Err(CliError::Help),
+            "--region" => options.region = Some(parse_value(&arg, &value()?)?),
+            "--scale" => {
+                let scale: u32 = parse_value(&arg, &value()?)?;
+                if scale == 0 || scale > MAX_SCALE {
+                    return Err(CliError::InvalidValue {
+                        option: arg,
+                        value: scale.to_string(),
+                        reason: format!("expected 1 to {}", MAX_SCALE),
+                    });
+                }
+                options.scale = Some(scale);
+            }
+            "--fullscreen" => options.fullscreen = true,
+            "--mapper" => options.mapper = Some(parse_value(&arg, &value()?)?),
+            "--bios" => options.bios = Some(PathBuf::from(value()?)),
+            "--config" => options.config = Some(PathBuf::from(value()?)),
+            "--headless" => options.headless = true,
+            "--frames" => options.frames = Some(parse_value(&arg, &value()?)?),
+            "--trace" => options.trace = Some(PathBuf::from(value()?)),
+            "--log" => options.log = Some(value()?),
+            "--screenshot-after" => options.screenshot_after = Some(parse_value(&arg, &value()?)?),
+            "--dump-audio" => options.dump_audio = Some(PathBuf::from(value()?)),
+            "--record" => options.record = Some(PathBuf::from(value()?)),
+            "--play" => options.play = Some(PathBuf::from(value()?)),
+            "--record-video" => options.record_video = Some(PathBuf::from(value()?)),
+            "--video-audio" => options.video_audio = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(CliError::UnknownOption(arg));
+            }
+            _ if rom.is_some() => return Err(CliError::ExtraArgument(arg)),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+
+    options.rom = rom.ok_or(CliError::MissingRom)?;
+
+    if options.screenshot_after.is_some() {
+        options.headless = true;
+    }
+    if options.headless && options.frames.is_none() && options.screenshot_after.is_none() {
+        return Err(CliError::Conflict(
+            "--headless needs --frames or --screenshot-after to know when to stop",
+        ));
+    }
+    if options.record.is_some() && options.play.is_some() {
+        return Err(CliError::Conflict(
+            "--record and --play can not be used together",
+        ));
+    }
+    if options.video_audio && options.record_video.is_none() {
+        return Err(CliError::Conflict("--video-audio needs --record-video"));
+    }
+
+    Ok(options)
+}
+
+fn parse_value<T>(option: &str, value: &str) -> Result<T, CliError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value.parse().map_err(|err: T::Err| CliError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+        reason: err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Options, CliError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let options = parse_args(&[
+            "--region", "PAL", "--scale", "3", "--mapper", "sega", "--bios", "bios.sms",
+            "--frames", "120", "game.sg",
+        ])
+        .unwrap();
+
+        assert_eq!(PathBuf::from("game.sg"), options.rom);
+        assert_eq!(Some(Region::Pal), options.region);
+        assert_eq!(Some(Mapper::Sega), options.mapper);
+        assert_eq!(Some(PathBuf::from("bios.sms")), options.bios);
+        assert_eq!(Some(3), options.scale);
+        assert_eq!(Some(120), options.frames);
+        assert!(!options.headless);
+
+        let options = parse_args(&["game.sg", "--screenshot-after", "10"]).unwrap();
+        assert!(options.headless);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Err(CliError::Help), parse_args(&["--help"]).map(|_| ()));
+        assert_eq!(Err(CliError::MissingRom), parse_args(&[]).map(|_| ()));
+        assert_eq!(
+            Err(CliError::MissingValue("--bios".to_string())),
+            parse_args(&["game.sg", "--bios"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(CliError::UnknownOption("--turbo".to_string())),
+            parse_args(&["--turbo", "game.sg"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(CliError::ExtraArgument("other.sg".to_string())),
+            parse_args(&["game.sg", "other.sg"]).map(|_| ())
+        );
+
+        for args in [
+            &["--scale", "0", "game.sg"][..],
+            &["--scale", "big", "game.sg"],
+            &["--region", "secam", "game.sg"],
+            &["--frames", "-1", "game.sg"],
+        ]
+        .iter()
+        {
+            match parse_args(args) {
+                Err(CliError::InvalidValue { option, .. }) => assert_eq!(args[0], option),
+                other => panic!("{:?}: {:?}", args, other),
+            }
+        }
+
+        assert!(matches!(
+            parse_args(&["--headless", "game.sg"]),
+            Err(CliError::Conflict(_))
+        ));
+    }
+}
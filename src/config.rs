@@ -0,0 +1,310 @@
+use crate::cartridge::Mapper;
+use crate::emulator::Region;
+use crate::hotkeys::Hotkeys;
+use crate::input::InputMapping;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the directory in the user's config directory
+const APP_DIR: &str = "sg-1000-emu";
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// The file in the working directory the controller bindings were read from before they
+/// moved into the config file
+pub const LEGACY_INPUT_FILE: &str = "input.toml";
+
+/// The largest window scale, 8 times 256x192 already fills a 4K screen
+pub const MAX_SCALE: u32 = 8;
+
+/// The largest zoom of the VDP's pictures, more only takes memory without looking smoother
+pub const MAX_VDP_ZOOM: u8 = 8;
+
+/// The settings of the emulator, with overrides for single games
+///
+/// The settings are stored as TOML, e.g.
+/// ```toml
+/// scale = 3
+/// region = "ntsc"
+///
+/// [input.player1]
+/// button1 = [{ key = "Z" }]
+///
+/// [hotkeys]
+/// pause = [{ key = "F1" }]
+///
+/// [games.1a2b3c4d]
+/// name = "Borderline"
+/// region = "pal"
+/// ```
+/// where the games are keyed by the CRC-32 of their ROM in hex.  Settings that are left
+/// out of the file keep their defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The size of a pixel in the window
+    pub scale: u32,
+    pub fullscreen: bool,
+    /// The OpenGL version of the window, "3.2" or "2.1" for older drivers
+    pub opengl: String,
+    /// The size of a pixel in the pictures of the VDP.  The window scales them again, so
+    /// this only changes how smooth the scaling looks.
+    pub vdp_zoom: u8,
+    pub region: Region,
+    /// The mapper of the cartridge, detected from the size of the game if not given.  This
+    /// is usually only set for the games that need it.
+    pub mapper: Option<Mapper>,
+    pub bios: Option<PathBuf>,
+    pub input: InputMapping,
+    pub hotkeys: Hotkeys,
+    pub games: BTreeMap<String, GameConfig>,
+}
+
+/// The settings of a single game, replacing the global settings that are given
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// Only there to make the file readable
+    pub name: Option<String>,
+    pub scale: Option<u32>,
+    pub region: Option<Region>,
+    pub mapper: Option<Mapper>,
+    pub bios: Option<PathBuf>,
+    pub input: Option<InputMapping>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            scale: 2,
+            fullscreen: false,
+            opengl: "3.2".to_string(),
+            vdp_zoom: 2,
+            region: Region::Ntsc,
+            mapper: None,
+            bios: None,
+            input: InputMapping::default(),
+            hotkeys: Hotkeys::default(),
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// The config file in the user's config directory, if there is one
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(APP_DIR).join(CONFIG_FILE))
+    }
+
+    /// Read the settings from a TOML file, or use the defaults if the file does not exist
+    pub fn load(path: &Path) -> io::Result<Config> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(err),
+        };
+
+        let config: Config =
+            toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        config
+            .validate()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        Ok(config)
+    }
+
+    /// Check the settings that TOML accepts but the emulator can not run with
+    pub fn validate(&self) -> Result<(), String> {
+        let check_scale = |scale: u32, what: &str| {
+            if scale == 0 || scale > MAX_SCALE {
+                Err(format!(
+                    "{} is {}, expected 1 to {}",
+                    what, scale, MAX_SCALE
+                ))
+            } else {
+                Ok(())
+            }
+        };
+
+        check_scale(self.scale, "scale")?;
+        if self.vdp_zoom == 0 || self.vdp_zoom > MAX_VDP_ZOOM {
+            return Err(format!(
+                "vdp_zoom is {}, expected 1 to {}",
+                self.vdp_zoom, MAX_VDP_ZOOM
+            ));
+        }
+        for (key, game) in &self.games {
+            if let Some(scale) = game.scale {
+                check_scale(scale, &format!("games.{}.scale", key))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Take the controller bindings from a legacy `input.toml`
+    ///
+    /// The bindings are only taken if the config has none of its own, that is if it has
+    /// the default ones.  Returns a message for the user either way, as the file should
+    /// be moved into the `[input]` section of the config.
+    pub fn read_legacy_input(&mut self, path: &Path) -> io::Result<String> {
+        if self.input != InputMapping::default() {
+            return Ok(format!(
+                "Ignoring {}, the config file has its own [input] section",
+                path.display()
+            ));
+        }
+
+        self.input = InputMapping::load(path)?;
+        Ok(format!(
+            "Read the controller bindings from {}, which is deprecated: move them into the \
+             [input] section of the config file",
+            path.display()
+        ))
+    }
+
+    /// Write the settings to a TOML file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = toml::to_string_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+
+    pub fn game(&self, rom_crc: u32) -> Option<&GameConfig> {
+        self.games.get(&game_key(rom_crc))
+    }
+
+    /// The overrides of a game, added to the file if it had none
+    pub fn game_mut(&mut self, rom_crc: u32) -> &mut GameConfig {
+        self.games.entry(game_key(rom_crc)).or_default()
+    }
+
+    /// The settings with the overrides of the given game applied
+    pub fn for_game(&self, rom_crc: u32) -> Config {
+        let mut config = self.clone();
+        if let Some(game) = self.game(rom_crc) {
+            config.scale = game.scale.unwrap_or(config.scale);
+            config.region = game.region.unwrap_or(config.region);
+            config.mapper = game.mapper.or(config.mapper);
+            config.bios = game.bios.clone().or(config.bios);
+            if let Some(input) = &game.input {
+                config.input = input.clone();
+            }
+        }
+
+        config
+    }
+}
+
+fn game_key(rom_crc: u32) -> String {
+    format!("{:08x}", rom_crc)
+}
+
+/// The directory the user keeps settings in, following each platform's convention
+fn config_dir() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+
+    if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston::Key;
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = Config {
+            scale: 3,
+            region: Region::Pal,
+            ..Config::default()
+        };
+        config.input.player1.button1 = vec![crate::input::Binding::Key { key: Key::Z }];
+        let game = config.game_mut(0x1A2B_3C4D);
+        game.name = Some("Borderline".to_string());
+        game.mapper = Some(Mapper::Sega);
+
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert!(text.contains("[games.1a2b3c4d]"), "{}", text);
+        assert_eq!(config, toml::from_str(&text).unwrap());
+    }
+
+    #[test]
+    fn test_for_game() {
+        let config: Config = toml::from_str(
+            r#"
+            scale = 3
+
+            [games.0000abcd]
+            region = "pal"
+            mapper = "sega"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(Config::default().input, config.input);
+
+        let game = config.for_game(0xABCD);
+        assert_eq!(3, game.scale);
+        assert_eq!(Region::Pal, game.region);
+        assert_eq!(Some(Mapper::Sega), game.mapper);
+        assert_eq!(Region::Ntsc, config.for_game(0x1234).region);
+        assert_eq!(None, config.for_game(0x1234).mapper);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Ok(()), Config::default().validate());
+
+        for text in &[
+            "scale = 0",
+            "scale = 9",
+            "vdp_zoom = 0",
+            "vdp_zoom = 255",
+            "[games.0000abcd]\nscale = 0",
+        ] {
+            let config: Config = toml::from_str(text).unwrap();
+            assert!(config.validate().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_legacy_input() {
+        let path = env::temp_dir().join(format!("sg-1000-input-{}.toml", std::process::id()));
+        let mut mapping = InputMapping::default();
+        mapping.player1.button1 = vec![crate::input::Binding::Key { key: Key::Z }];
+        mapping.save(&path).unwrap();
+
+        let mut config = Config::default();
+        config.read_legacy_input(&path).unwrap();
+        assert_eq!(mapping, config.input);
+
+        // bindings in the config win
+        let mut config = Config::default();
+        config.input.filter_opposing = false;
+        config.read_legacy_input(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!config.input.filter_opposing);
+        assert_eq!(InputMapping::default().player1, config.input.player1);
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let path = env::temp_dir().join("sg-1000-missing").join(CONFIG_FILE);
+        assert_eq!(Config::default(), Config::load(&path).unwrap());
+    }
+}
@@ -1,253 +1,386 @@
-use bus::{bus::*, ram::*, BusConnectable, MemoryMap, MutRef};
-use piston::{Button, ButtonArgs, ButtonState, Key};
-use std::fs::File;
-use std::io::stdout;
-use std::io::Read;
-use std::path::PathBuf;
-use std::{cell::RefCell, rc::Rc};
+use crate::bios::{MemoryControl, SlotMapper, BIOS_BOOT, CARTRIDGE_BOOT};
+use crate::cartridge::{load_rom, Cartridge, Mapper, RomError};
+use crate::input::{InputMapping, KeyboardController};
+use crate::joypad::JoypadState;
+use crate::machine::Machine;
+use bus::{
+    ram::*,
+    state::{take_u64, SaveState},
+    MemoryMap,
+};
+use piston::{ButtonArgs, ControllerAxisArgs};
+use serde::{Deserialize, Serialize};
+use sn76489::psg::Psg;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use tms9918::ppu::*;
 use z80::cpu::*;
 
-const DPAD_UP: u8 = 0;
-const DPAD_DOWN: u8 = 1;
-const DPAD_LEFT: u8 = 2;
-const DPAD_RIGHT: u8 = 3;
-const BUTTON1: u8 = 4;
-const BUTTON2: u8 = 5;
+/// The video standard of the machine, which decides how many lines the VDP draws per frame
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    /// The T-states per second of the cpu
+    pub fn clock_rate(self) -> u32 {
+        match self {
+            Region::Ntsc => 3_579_545,
+            Region::Pal => 3_546_893,
+        }
+    }
+
+    /// The T-states of a frame of the VDP: 228 per line, 262 lines for NTSC and 313 for PAL
+    pub fn cycles_per_frame(self) -> u32 {
+        match self {
+            Region::Ntsc => 228 * 262,
+            Region::Pal => 228 * 313,
+        }
+    }
+}
+
+/// The frame rate of the VDP as a fraction: the cpu clock over the cycles of a frame
+pub fn frame_rate(region: Region) -> (u32, u32) {
+    (region.clock_rate(), region.cycles_per_frame())
+}
+
+/// Options for how the machine is switched on
+#[derive(Clone, Debug, Default)]
+pub struct BootOptions {
+    /// A BIOS image to run before the cartridge.  It stays mapped over the cartridge until
+    /// it is disabled through the memory control port (0x3E).
+    pub bios: Option<PathBuf>,
+    /// How the work ram and the VRAM are filled on power on
+    pub ram_init: RamInit,
+    pub region: Region,
+    /// The mapper of the cartridge, detected from the size of the game if not given
+    pub mapper: Option<Mapper>,
+}
 
-#[allow(dead_code)]
 pub struct Emulator {
-    pub cpu: MutRef<Cpu>,
-    pub ppu: MutRef<Ppu>,
-    controller: MutRef<KeyboardController>,
-    paused: bool,
+    /// The cpu, which owns the rest of the machine
+    cpu: Cpu<Machine>,
+    controller: KeyboardController,
+    /// Whether the pause button of the console was held at the last input
+    pause_held: bool,
+    /// Whether the pause button was pressed during the frame that is being emulated
+    pause_pending: bool,
+    /// Whether the pause button was pressed during the last frame
+    pause_pressed: bool,
+    options: BootOptions,
+    rom_crc: u32,
+    /// The number of frames emulated since power on
+    frame: u64,
+    /// The T-states the last frame took
+    frame_cycles: u64,
+    /// The T-states of the frame that is being emulated so far
+    cycles: u64,
+    /// The PSG output of the last frame
+    audio: Vec<f32>,
+    /// Where the cpu state is written before every instruction
+    trace: Option<Box<dyn Write + Send>>,
 }
 
 impl Emulator {
-    //     vec![
-    //     0xFB, 0x1e, 0x31, 0x16, 0xF2, 0x01, 0x00, 0x80, 0x61, 0x69, 0xcb, 0x38, 0xcb, 0x19,
-    //     0x09, 0xeb, 0xed, 0x52, 0x38, 0x04, 0xeb, 0x09, 0x18, 0x05, 0x19, 0xeb, 0xb7, 0xed,
-    //     0x42, 0xcb, 0x3c, 0xcb, 0x1d, 0xcb, 0x38, 0xcb, 0x19, 0x30,
-    //     0xe3, // 0xC3, 0x00, 0x00,
-    // ]
-    pub fn new(file: &PathBuf) -> Emulator {
-        let mut zexall = File::open(file).expect("Could not find file");
-
-        let mut data = Vec::with_capacity(0xFFFF + 1);
-        // zexall.seek(SeekFrom::Start(0x69)).unwrap();
-        zexall.read_to_end(&mut data).unwrap();
-        let data_bus: MutRef<Bus> = Rc::new(RefCell::new(
-            Bus::builder()
-                .add(
-                    Ram::builder()
-                        .data(data)
-                        .map(MemoryMap::from(0..0x8000))
-                        .build(),
-                )
-                .add(
-                    Ram::builder()
-                        .map(MemoryMap::from(0xA000..0xC000))
-                        .mirror(MemoryMap::from(0xC000..=0xFFFF))
-                        .build(),
-                )
-                .build(),
-        ));
-
-        let controller = Rc::new(RefCell::new(KeyboardController::new()));
-        let ppu = Rc::new(RefCell::new(Ppu::new()));
-        let io_ports = Rc::new(RefCell::new(
-            Bus::builder()
-                .add_ref(&(Rc::clone(&ppu) as Rc<RefCell<dyn BusConnectable>>))
-                .add_ref(&(Rc::clone(&controller) as Rc<RefCell<dyn BusConnectable>>))
+    pub fn new(file: &Path) -> Emulator {
+        Emulator::with_options(file, &BootOptions::default())
+    }
+
+    /// Create an emulator and bring it to its power on state
+    ///
+    /// Without a BIOS the cartridge is mapped from the start and the cpu begins executing
+    /// it at address 0, like an SG-1000.  With a BIOS the cartridge is hidden until the BIOS
+    /// writes to the memory control port, like a Master System.
+    ///
+    /// Power on state:
+    ///     cpu:      PC, I, R = 0, interrupts disabled, all other registers 0xFFFF
+    ///     work ram: filled according to `options.ram_init`
+    ///     vram:     filled according to `options.ram_init`
+    ///
+    /// # Panics
+    /// If the game or the BIOS can not be loaded, see `Emulator::open`
+    pub fn with_options(file: &Path, options: &BootOptions) -> Emulator {
+        Emulator::open(file, options).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Load a game and bring the machine to its power on state (see `with_options`)
+    pub fn open(file: &Path, options: &BootOptions) -> Result<Emulator, RomError> {
+        Emulator::with_rom(load_rom(file)?, options)
+    }
+
+    /// Bring the machine to its power on state with a game that is already loaded
+    ///
+    /// Fails only if the BIOS can not be read.
+    pub fn with_rom(data: Vec<u8>, options: &BootOptions) -> Result<Emulator, RomError> {
+        let rom_crc = crc32(&data);
+
+        let bios = match &options.bios {
+            Some(path) => {
+                Some(fs::read(path).map_err(|err| RomError::Io(path.to_path_buf(), err))?)
+            }
+            None => None,
+        };
+
+        let memory_control = MemoryControl::new(if bios.is_some() {
+            BIOS_BOOT
+        } else {
+            CARTRIDGE_BOOT
+        });
+
+        let mapper = options.mapper.unwrap_or_else(|| Mapper::detect(&data));
+        let cartridge = Cartridge::new(data, mapper);
+
+        let mut ppu = Ppu::with_vram_init(options.ram_init);
+        ppu.set_pal(options.region == Region::Pal);
+        let machine = Machine::new(
+            ppu,
+            Psg::new(),
+            SlotMapper::new(cartridge, bios, memory_control),
+            Ram::builder()
+                .size(0x2000)
+                .map(MemoryMap::from(0xC000..0xE000))
+                .mirror(MemoryMap::from(0xE000..=0xFFFF))
+                .init(options.ram_init)
                 .build(),
-        ));
+        );
 
-        let cpu = Rc::new(RefCell::new(Cpu::with_pc(&data_bus, &io_ports, 0)));
+        let mut cpu = Cpu::with_bus(machine);
+        cpu.power_on();
 
-        Emulator {
+        Ok(Emulator {
             cpu,
-            ppu,
-            controller,
-            paused: false,
+            controller: KeyboardController::new(),
+            pause_held: false,
+            pause_pending: false,
+            pause_pressed: false,
+            options: options.clone(),
+            rom_crc,
+            frame: 0,
+            frame_cycles: 0,
+            cycles: 0,
+            audio: Vec::new(),
+            trace: None,
+        })
+    }
+
+    /// Emulate until the next vblank
+    pub fn run_frame(&mut self) {
+        // loop until we hit vblank
+        while !self.step() {}
+    }
+
+    /// Emulate until the VDP starts its next line
+    pub fn run_scanline(&mut self) {
+        let line = self.ppu().line();
+        while self.ppu().line() == line {
+            self.step();
         }
     }
 
-    pub fn refresh(&mut self) {
-        // dont change the frame if we are paused
-        if self.paused {
-            return;
+    /// Emulate a single instruction, returns whether it finished a frame
+    fn step(&mut self) -> bool {
+        if let Some(trace) = &mut self.trace {
+            self.cpu.log(trace).expect("Could not write the cpu trace");
+        }
+        let ticks = self.cpu.do_operation();
+        self.cycles += ticks;
+        let machine = self.cpu.bus_mut();
+        machine.psg.update(ticks);
+        let vblank = machine.ppu.update(ticks);
+        self.cpu.mask_interrupt = self.ppu().interrupt_pending();
+        if !vblank {
+            return false;
         }
 
-        // loop until we hit vblank
-        loop {
-            let ticks = self.cpu.borrow_mut().do_operation();
-            if self.ppu.borrow_mut().update(ticks) {
-                self.cpu.borrow_mut().mask_interrupt = true && self.ppu.borrow().intrpt_enabled();
+        self.frame += 1;
+        self.frame_cycles = self.cycles;
+        self.cycles = 0;
+        self.pause_pressed = std::mem::take(&mut self.pause_pending);
 
-                // we reached the vblank, so get out of the loop
-                break;
-            }
-        }
+        self.audio.clear();
+        self.cpu.bus_mut().psg.take_samples(&mut self.audio);
 
-        println!(
-            "
-            
-            ================                  ================
-            ================ REFRESH COMPLETE ================
-            ================                  ================
-            
-            "
-        );
+        true
     }
 
+    /// Stop the cpu for the debugger, the devices keep running
     pub fn halt_cpu(&mut self) {
-        self.cpu.borrow_mut().halt();
+        self.cpu.pause();
     }
 
     pub fn reset_halt_cpu(&mut self) {
-        self.cpu.borrow_mut().reset_halt();
+        self.cpu.resume();
     }
 
     pub fn flip_halt_cpu(&mut self) {
-        if self.cpu.borrow().is_halted() {
+        if self.cpu.is_paused() {
             self.reset_halt_cpu();
         } else {
             self.halt_cpu();
         }
     }
 
-    pub fn cpu(&self) -> &RefCell<Cpu> {
-        self.cpu.as_ref()
+    pub fn cpu(&self) -> &Cpu<Machine> {
+        &self.cpu
     }
 
-    pub fn input(&mut self, args: &ButtonArgs) {
-        match args.button {
-            Button::Keyboard(Key::Space) => {
-                if args.state == ButtonState::Press {
-                    self.cpu.borrow_mut().nomask_interrupt = true;
-                }
-            }
-            Button::Keyboard(Key::P) if args.state == ButtonState::Press => {
-                self.paused = !self.paused
-            }
-            Button::Keyboard(Key::Backslash)
-                if self.paused && args.state == ButtonState::Release =>
-            {
-                self.cpu.borrow().log(stdout()).unwrap();
-                self.ppu.borrow().log(stdout()).unwrap();
-                // self.ppu.log(stdout());
-            }
-            _ => {
-                if !self.paused {
-                    self.controller.borrow_mut().input(args);
-                }
-            }
-        }
+    pub fn ppu(&self) -> &Ppu {
+        &self.cpu.bus().ppu
     }
-}
 
-struct KeyboardController {
-    /// Note that the bit is low when the key is pressed
-    joypad1: u8,
-    joypad2: u8,
-    dpad_up: Key,
-    dpad_down: Key,
-    dpad_left: Key,
-    dpad_right: Key,
-    button1: Key,
-    button2: Key,
-}
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.cpu.bus_mut().ppu
+    }
 
-impl KeyboardController {
-    pub fn new() -> KeyboardController {
-        KeyboardController {
-            joypad1: 0xFF,
-            joypad2: 0xFF,
-            dpad_up: Key::W,
-            dpad_down: Key::S,
-            dpad_left: Key::A,
-            dpad_right: Key::D,
-            button1: Key::J,
-            button2: Key::K,
-        }
+    /// The options the machine was switched on with
+    pub fn options(&self) -> &BootOptions {
+        &self.options
     }
 
-    pub fn input(&mut self, args: &ButtonArgs) {
-        let is_pressed = args.state == ButtonState::Press;
-        match args.button {
-            Button::Keyboard(key) => self.change_button_state(key, is_pressed),
-            _ => {}
-        }
+    /// The CRC-32 of the cartridge image
+    pub fn rom_crc(&self) -> u32 {
+        self.rom_crc
     }
 
-    fn change_button_state(&mut self, key: Key, is_pressed: bool) {
-        if key == self.dpad_up {
-            self.set_dpad(DPAD_UP, is_pressed);
-        } else if key == self.dpad_down {
-            self.set_dpad(DPAD_DOWN, is_pressed);
-        } else if key == self.dpad_left {
-            self.set_dpad(DPAD_LEFT, is_pressed);
-        } else if key == self.dpad_right {
-            self.set_dpad(DPAD_RIGHT, is_pressed);
-        } else if key == self.button1 {
-            self.set_button(BUTTON1, is_pressed);
-        } else if key == self.button2 {
-            self.set_button(BUTTON2, is_pressed);
-        }
+    /// The number of frames emulated since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
     }
 
-    fn set_dpad(&mut self, button: u8, is_pressed: bool) {
-        // bit is set high if the button is not pressed
-        if !is_pressed {
-            // clear the other dpad buttons (only one pressed at a time)
-            self.joypad1 = !(1 << button) | (self.joypad1 & 0b11_0000);
-        } else {
-            // clear the dbad buttons
-            self.joypad1 &= 0b11_0000;
-        }
+    /// The T-states the last frame took, which is a few more than a frame of the VDP when
+    /// the last instruction ran past the vblank
+    pub fn frame_cycles(&self) -> u64 {
+        self.frame_cycles
     }
 
-    fn set_button(&mut self, bit: u8, is_pressed: bool) {
-        // bit is set high if the button is not pressed
-        if !is_pressed {
-            self.joypad1 |= 1 << bit;
-        } else {
-            self.joypad1 &= !(1 << bit);
-        }
+    /// Save the state of the whole machine
+    ///
+    /// The state does not include the picture, so after loading it the last frame is
+    /// only up to date once another frame has been emulated.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        state.extend_from_slice(&self.frame.to_le_bytes());
+        self.cpu.save_state(&mut state);
+        let machine = self.cpu.bus();
+        machine.ppu.save_state(&mut state);
+        machine.psg.save_state(&mut state);
+        machine.slots.control().save_state(&mut state);
+        machine.slots.save_state(&mut state);
+        machine.work_ram.save_state(&mut state);
+        machine.joypads.save_state(&mut state);
+
+        state
     }
-}
 
-impl BusConnectable for KeyboardController {
-    fn accept(&self, addr: u16) -> bool {
-        let val = addr & 0xFF;
-        val == 0xDC || val == 0xC0 || val == 0xDD || val == 0xC1
+    /// Restore a state saved with `save_state`
+    ///
+    /// # Panics
+    /// If the state was saved by an emulator running a different cartridge
+    pub fn load_state(&mut self, mut state: &[u8]) {
+        let state = &mut state;
+        self.frame = take_u64(state);
+        self.cpu.load_state(state);
+        let machine = self.cpu.bus_mut();
+        machine.ppu.load_state(state);
+        machine.psg.load_state(state);
+        machine.slots.control_mut().load_state(state);
+        machine.slots.load_state(state);
+        machine.work_ram.load_state(state);
+        machine.joypads.load_state(state);
+        machine.remap();
     }
 
-    fn cpu_read(&mut self, addr: u16) -> u8 {
-        if addr & 0xFF == 0xDC || addr & 0xFF == 0xC0 {
-            self.joypad1
-        } else {
-            self.joypad2
-        }
+    /// The PSG samples of the last frame, at `sn76489::psg::SAMPLE_RATE`
+    pub fn audio(&self) -> &[f32] {
+        &self.audio
     }
 
-    fn cpu_write(&mut self, _addr: u16, _val: u8) -> bool {
-        false
+    /// Write the state of the cpu before every instruction to `trace`, or stop tracing
+    pub fn set_trace(&mut self, trace: Option<Box<dyn Write + Send>>) {
+        self.trace = trace;
     }
-}
 
-struct MiscIo {}
+    /// The CRC-32 of the last complete frame's pixels
+    pub fn frame_hash(&self) -> u32 {
+        crc32(self.ppu().frame())
+    }
 
-impl BusConnectable for MiscIo {
-    fn accept(&self, addr: u16) -> bool {
-        (addr & 0xDE) == 0xDE
+    /// Write the registers of the cpu and the VDP
+    pub fn log_state(&self, mut out: impl Write) -> io::Result<()> {
+        self.cpu.log(&mut out)?;
+        self.ppu().log(&mut out)
     }
 
-    fn cpu_read(&mut self, addr: u16) -> u8 {
-        0
+    /// Press or release a button of the joypads or the console, by the host input bound to it
+    pub fn input(&mut self, args: &ButtonArgs) {
+        self.controller.input(args);
+        self.update_joypads();
     }
 
-    fn cpu_write(&mut self, addr: u16, val: u8) -> bool {
-        true
+    pub fn axis_input(&mut self, args: &ControllerAxisArgs) {
+        self.controller.axis_input(args);
+        self.update_joypads();
+    }
+
+    pub fn set_input_mapping(&mut self, mapping: InputMapping) {
+        self.controller.set_mapping(mapping);
+        self.update_joypads();
+    }
+
+    /// The buttons held on the joypad in `port` (0 or 1), as the game sees them
+    pub fn joypad(&self, port: usize) -> JoypadState {
+        self.cpu.bus().joypads.state(port)
+    }
+
+    /// Hold exactly the given buttons on the joypad in `port` (0 or 1)
+    ///
+    /// The state stays until the next host input for that port or the next call.
+    pub fn set_joypad(&mut self, port: usize, state: JoypadState) {
+        self.cpu.bus_mut().joypads.set_state(port, state);
+    }
+
+    /// Press the pause button of the console
+    ///
+    /// The button is wired to the NMI line, which triggers when it is pressed, so there is
+    /// nothing to release.
+    pub fn press_pause(&mut self) {
+        bus::log!(Input, Debug, "pause");
+        self.cpu.nomask_interrupt = true;
+        self.pause_pending = true;
     }
+
+    /// Whether the pause button was pressed during the last frame
+    pub fn pause_pressed(&self) -> bool {
+        self.pause_pressed
+    }
+
+    /// Copy the held host inputs to the joypads and the pause button
+    fn update_joypads(&mut self) {
+        let joypads = &mut self.cpu.bus_mut().joypads;
+        for port in 0..2 {
+            let state = self.controller.state(port);
+            if state != joypads.state(port) {
+                bus::log!(Input, Debug, "joypad {}: {:?}", port + 1, state);
+            }
+            joypads.set_state(port, state);
+        }
+
+        let pause_held = self.controller.is_pause_held();
+        if pause_held && !self.pause_held {
+            self.press_pause();
+        }
+        self.pause_held = pause_held;
+    }
+}
+
+/// The CRC-32 of some data, e.g. to identify a game by its ROM
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
 }
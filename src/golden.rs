@@ -0,0 +1,253 @@
+use crate::emulator::Emulator;
+use crate::joypad::JoypadState;
+use crate::screenshot::native_frame;
+use image::{Rgb, RgbImage};
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Set this environment variable to write the current output of the emulator as the new
+/// golden images instead of comparing against them
+pub const UPDATE_VAR: &str = "SG_UPDATE_GOLDEN";
+
+/// The directory with the golden images, next to the games they belong to
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/golden")
+}
+
+/// A game that is booted without a window and run for a fixed number of frames, after
+/// which its picture has to match a stored PNG
+pub struct GoldenTest<'a> {
+    /// The name of the golden image, without `.png`
+    pub name: &'static str,
+    /// The game, relative to `resources/`
+    pub rom: &'static str,
+    pub frames: u64,
+    /// The buttons of the first joypad, from the given frame on
+    pub input: &'a [(u64, JoypadState)],
+}
+
+impl GoldenTest<'_> {
+    /// Boot the game and run it with the scripted input, giving the last picture
+    pub fn run(&self) -> RgbImage {
+        let rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join(self.rom);
+        let mut emulator = Emulator::new(&rom);
+
+        for frame in 0..self.frames {
+            if let Some(&(_, state)) = self.input.iter().find(|(start, _)| *start == frame) {
+                emulator.set_joypad(0, state);
+            }
+            emulator.run_frame();
+        }
+
+        native_frame(&emulator)
+    }
+
+    /// Run the game and compare its picture to the golden image
+    ///
+    /// On a mismatch the picture and an image of the differing pixels are written to the
+    /// temporary directory, so they can be looked at (or copied over the golden image if
+    /// the change was intended).
+    pub fn check(&self) -> Result<(), String> {
+        let actual = self.run();
+        let golden = golden_dir().join(format!("{}.png", self.name));
+
+        if env::var_os(UPDATE_VAR).is_some() {
+            return actual
+                .save(&golden)
+                .map_err(|err| format!("Could not write {}: {}", golden.display(), err));
+        }
+
+        let expected = image::open(&golden)
+            .map_err(|err| {
+                format!(
+                    "Could not read {} ({}), run with {}=1 to create it",
+                    golden.display(),
+                    err,
+                    UPDATE_VAR
+                )
+            })?
+            .to_rgb();
+
+        match PixelDiff::new(&expected, &actual) {
+            None => Ok(()),
+            Some(diff) => {
+                let dir = env::temp_dir();
+                let actual_path = dir.join(format!("{}-actual.png", self.name));
+                let diff_path = dir.join(format!("{}-diff.png", self.name));
+                save(&actual, &actual_path);
+                save(&diff.image, &diff_path);
+
+                Err(format!(
+                    "{} does not match {}: {}\n  actual: {}\n  diff:   {}",
+                    self.name,
+                    golden.display(),
+                    diff,
+                    actual_path.display(),
+                    diff_path.display()
+                ))
+            }
+        }
+    }
+}
+
+fn save(image: &RgbImage, path: &Path) {
+    if let Err(err) = image.save(path) {
+        eprintln!("Could not write {}: {}", path.display(), err);
+    }
+}
+
+/// The differences between two pictures
+pub struct PixelDiff {
+    /// The number of pixels that differ
+    pub count: usize,
+    /// The smallest rectangle around the differing pixels, as (left, top, right, bottom)
+    pub bounds: (u32, u32, u32, u32),
+    /// The expected picture dimmed, with the differing pixels in red
+    pub image: RgbImage,
+    /// The pictures are not the same size, nothing else was compared
+    pub size_mismatch: Option<((u32, u32), (u32, u32))>,
+}
+
+impl PixelDiff {
+    /// Compare two pictures, giving None if they are the same
+    pub fn new(expected: &RgbImage, actual: &RgbImage) -> Option<PixelDiff> {
+        if expected.dimensions() != actual.dimensions() {
+            return Some(PixelDiff {
+                count: 0,
+                bounds: (0, 0, 0, 0),
+                image: actual.clone(),
+                size_mismatch: Some((expected.dimensions(), actual.dimensions())),
+            });
+        }
+
+        let mut count = 0;
+        let mut bounds = (u32::MAX, u32::MAX, 0, 0);
+        let image = RgbImage::from_fn(expected.width(), expected.height(), |x, y| {
+            let Rgb([r, g, b]) = *expected.get_pixel(x, y);
+            if expected.get_pixel(x, y) == actual.get_pixel(x, y) {
+                return Rgb([r / 4, g / 4, b / 4]);
+            }
+
+            count += 1;
+            bounds = (
+                bounds.0.min(x),
+                bounds.1.min(y),
+                bounds.2.max(x),
+                bounds.3.max(y),
+            );
+            Rgb([0xFF, 0, 0])
+        });
+
+        if count == 0 {
+            None
+        } else {
+            Some(PixelDiff {
+                count,
+                bounds,
+                image,
+                size_mismatch: None,
+            })
+        }
+    }
+}
+
+impl fmt::Display for PixelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((expected, actual)) = self.size_mismatch {
+            return write!(
+                f,
+                "expected a {}x{} picture, got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            );
+        }
+
+        let (left, top, right, bottom) = self.bounds;
+        write!(
+            f,
+            "{} pixels differ between ({}, {}) and ({}, {})",
+            self.count, left, top, right, bottom
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: &[(u64, JoypadState)] = &[];
+
+    /// Run every test, so one failure does not hide the others
+    fn check_all(tests: &[GoldenTest]) {
+        let failures = tests
+            .iter()
+            .filter_map(|test| test.check().err())
+            .collect::<Vec<_>>();
+
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn test_golden_images() {
+        check_all(&[
+            GoldenTest {
+                name: "hello_world2",
+                rom: "hello_world2.sg",
+                frames: 30,
+                input: NONE,
+            },
+            // the title only comes up after two long delay loops
+            GoldenTest {
+                name: "borderline",
+                rom: "Borderline.sg",
+                frames: 120,
+                input: NONE,
+            },
+            GoldenTest {
+                name: "snake_title",
+                rom: "SNAKE.SG",
+                frames: 90,
+                input: NONE,
+            },
+            GoldenTest {
+                name: "snake_start",
+                rom: "SNAKE.SG",
+                frames: 180,
+                input: &[(100, JoypadState::BUTTON1), (110, JoypadState::empty())],
+            },
+            GoldenTest {
+                name: "bacachase",
+                rom: "bacachase.sg",
+                frames: 150,
+                input: &[(60, JoypadState::RIGHT), (100, JoypadState::UP)],
+            },
+            GoldenTest {
+                name: "poleposition",
+                rom: "poleposition.sg",
+                frames: 120,
+                input: NONE,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_pixel_diff() {
+        let expected = RgbImage::from_pixel(8, 8, Rgb([0x40, 0x40, 0x40]));
+        assert!(PixelDiff::new(&expected, &expected).is_none());
+
+        let mut actual = expected.clone();
+        actual.put_pixel(2, 3, Rgb([0, 0, 0]));
+        actual.put_pixel(5, 1, Rgb([0, 0, 0]));
+        let diff = PixelDiff::new(&expected, &actual).unwrap();
+        assert_eq!(2, diff.count);
+        assert_eq!((2, 1, 5, 3), diff.bounds);
+        assert_eq!(Rgb([0xFF, 0, 0]), *diff.image.get_pixel(2, 3));
+        assert_eq!(Rgb([0x10, 0x10, 0x10]), *diff.image.get_pixel(0, 0));
+
+        let smaller = RgbImage::new(4, 4);
+        let diff = PixelDiff::new(&expected, &smaller).unwrap();
+        assert_eq!(Some(((8, 8), (4, 4))), diff.size_mismatch);
+    }
+}
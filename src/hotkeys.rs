@@ -0,0 +1,150 @@
+use crate::input::Binding;
+use piston::{Button, ButtonArgs, ControllerButton, Key};
+use serde::{Deserialize, Serialize};
+
+/// Something the frontend does, as opposed to a button of the emulated machine
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Command {
+    /// Stop or continue the emulation
+    Pause,
+    /// Run exactly one frame, pausing first if needed
+    FrameAdvance,
+    /// Run until the VDP starts its next line, pausing first if needed
+    ScanlineAdvance,
+    /// Run faster while held
+    FastForward,
+    /// Switch slow motion on and off
+    SlowMotion,
+    /// Run backwards while held
+    Rewind,
+    /// Save a picture of the last frame to the working directory
+    Screenshot,
+    /// Start or stop recording video to the working directory
+    RecordVideo,
+    /// Save the settings of the running game to the config file
+    SaveConfig,
+    /// Print the registers of the cpu and the VDP
+    DumpState,
+}
+
+/// The host inputs bound to each command
+///
+/// Only keys and gamepad buttons can be bound to commands.  The bindings are stored in
+/// the config file, e.g.
+/// ```toml
+/// [hotkeys]
+/// pause = [{ key = "P" }, { gamepad = 0, button = 9 }]
+/// frame_advance = [{ key = "N" }]
+/// ```
+/// Commands that are left out of the file keep their default bindings.  The inputs that
+/// are bound to a command do not reach the joypads.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hotkeys {
+    pub pause: Vec<Binding>,
+    pub frame_advance: Vec<Binding>,
+    pub scanline_advance: Vec<Binding>,
+    pub fast_forward: Vec<Binding>,
+    pub slow_motion: Vec<Binding>,
+    pub rewind: Vec<Binding>,
+    pub screenshot: Vec<Binding>,
+    pub record_video: Vec<Binding>,
+    pub save_config: Vec<Binding>,
+    pub dump_state: Vec<Binding>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Hotkeys {
+        let key = |key| vec![Binding::Key { key }];
+        Hotkeys {
+            pause: key(Key::P),
+            frame_advance: key(Key::F3),
+            scanline_advance: key(Key::F4),
+            fast_forward: key(Key::Tab),
+            slow_motion: key(Key::F2),
+            rewind: key(Key::Backspace),
+            screenshot: key(Key::F12),
+            record_video: key(Key::F10),
+            save_config: key(Key::F5),
+            dump_state: key(Key::Backslash),
+        }
+    }
+}
+
+impl Hotkeys {
+    fn bindings(&self) -> [(Command, &Vec<Binding>); 10] {
+        use Command::*;
+        [
+            (Pause, &self.pause),
+            (FrameAdvance, &self.frame_advance),
+            (ScanlineAdvance, &self.scanline_advance),
+            (FastForward, &self.fast_forward),
+            (SlowMotion, &self.slow_motion),
+            (Rewind, &self.rewind),
+            (Screenshot, &self.screenshot),
+            (RecordVideo, &self.record_video),
+            (SaveConfig, &self.save_config),
+            (DumpState, &self.dump_state),
+        ]
+    }
+
+    /// The command a host input is bound to, if any
+    pub fn command(&self, args: &ButtonArgs) -> Option<Command> {
+        let binding = match args.button {
+            Button::Keyboard(key) => Binding::Key { key },
+            Button::Controller(ControllerButton { id, button }) => Binding::Button {
+                gamepad: id,
+                button,
+            },
+            _ => return None,
+        };
+
+        self.bindings()
+            .iter()
+            .find(|(_, bindings)| bindings.contains(&binding))
+            .map(|(command, _)| *command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston::ButtonState;
+
+    fn press(button: Button) -> ButtonArgs {
+        ButtonArgs {
+            state: ButtonState::Press,
+            button,
+            scancode: None,
+        }
+    }
+
+    #[test]
+    fn test_command() {
+        let hotkeys: Hotkeys = toml::from_str(
+            r#"
+            pause = [{ key = "Space" }, { gamepad = 0, button = 9 }]
+            "#,
+        )
+        .unwrap();
+
+        let pad = |button| Button::Controller(ControllerButton { id: 0, button });
+        assert_eq!(
+            Some(Command::Pause),
+            hotkeys.command(&press(Button::Keyboard(Key::Space)))
+        );
+        assert_eq!(Some(Command::Pause), hotkeys.command(&press(pad(9))));
+        assert_eq!(None, hotkeys.command(&press(pad(8))));
+        assert_eq!(None, hotkeys.command(&press(Button::Keyboard(Key::P))));
+
+        // the binding is for the first gamepad only
+        let second = Button::Controller(ControllerButton { id: 1, button: 9 });
+        assert_eq!(None, hotkeys.command(&press(second)));
+
+        // the commands that are not in the file keep their keys
+        assert_eq!(
+            Some(Command::FrameAdvance),
+            hotkeys.command(&press(Button::Keyboard(Key::F3)))
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use crate::joypad::JoypadState;
+use piston::{
+    Button, ButtonArgs, ButtonState, ControllerAxisArgs, ControllerButton, ControllerHat, HatState,
+    Key,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How far an axis has to be pushed before it counts as a pressed direction
+const AXIS_THRESHOLD: f64 = 0.5;
+
+/// A direction on a gamepad hat
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HatDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl HatDirection {
+    fn is_active(self, state: HatState) -> bool {
+        use HatDirection::*;
+        match state {
+            HatState::Centered => false,
+            HatState::Up => self == Up,
+            HatState::Down => self == Down,
+            HatState::Left => self == Left,
+            HatState::Right => self == Right,
+            HatState::LeftUp => self == Left || self == Up,
+            HatState::LeftDown => self == Left || self == Down,
+            HatState::RightUp => self == Right || self == Up,
+            HatState::RightDown => self == Right || self == Down,
+        }
+    }
+}
+
+/// A single host input that can be bound to a joypad button
+///
+/// The kind of binding is told apart by its fields, so every variant must have a
+/// different set of them.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Binding {
+    /// A keyboard key, by its name (e.g. "W" or "Up")
+    Key { key: Key },
+    /// A gamepad button
+    Button { gamepad: i32, button: u8 },
+    /// A direction of a gamepad hat
+    Hat { gamepad: i32, hat: HatDirection },
+    /// One direction of a gamepad axis
+    Axis {
+        gamepad: i32,
+        axis: u8,
+        positive: bool,
+    },
+}
+
+/// The host inputs bound to each button of one joypad
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PadMapping {
+    pub up: Vec<Binding>,
+    pub down: Vec<Binding>,
+    pub left: Vec<Binding>,
+    pub right: Vec<Binding>,
+    pub button1: Vec<Binding>,
+    pub button2: Vec<Binding>,
+}
+
+impl PadMapping {
+    /// The default mapping for a joypad: the given keys plus the gamepad's hat, left stick
+    /// and first two buttons
+    fn with_keys(keys: [Key; 6], gamepad: i32) -> PadMapping {
+        let key = |key| Binding::Key { key };
+        let hat = |hat| Binding::Hat { gamepad, hat };
+        let axis = |axis, positive| Binding::Axis {
+            gamepad,
+            axis,
+            positive,
+        };
+        let button = |button| Binding::Button { gamepad, button };
+
+        PadMapping {
+            up: vec![key(keys[0]), hat(HatDirection::Up), axis(1, false)],
+            down: vec![key(keys[1]), hat(HatDirection::Down), axis(1, true)],
+            left: vec![key(keys[2]), hat(HatDirection::Left), axis(0, false)],
+            right: vec![key(keys[3]), hat(HatDirection::Right), axis(0, true)],
+            button1: vec![key(keys[4]), button(0)],
+            button2: vec![key(keys[5]), button(1)],
+        }
+    }
+
+    fn bindings(&self) -> [(JoypadState, &Vec<Binding>); 6] {
+        [
+            (JoypadState::UP, &self.up),
+            (JoypadState::DOWN, &self.down),
+            (JoypadState::LEFT, &self.left),
+            (JoypadState::RIGHT, &self.right),
+            (JoypadState::BUTTON1, &self.button1),
+            (JoypadState::BUTTON2, &self.button2),
+        ]
+    }
+
+    /// The buttons that are pressed when the given host inputs are held
+    fn state(&self, held: &HashSet<Binding>) -> JoypadState {
+        self.bindings()
+            .iter()
+            .filter(|(_, bindings)| bindings.iter().any(|binding| held.contains(binding)))
+            .fold(JoypadState::empty(), |state, (button, _)| state | *button)
+    }
+}
+
+/// The bindings of both controller ports
+///
+/// The mapping is stored as TOML, e.g.
+/// ```toml
+/// filter_opposing = true
+/// pause = [{ key = "Space" }]
+///
+/// [player1]
+/// up = [{ key = "W" }, { gamepad = 0, hat = "up" }]
+/// button1 = [{ key = "J" }, { gamepad = 0, button = 0 }]
+///
+/// [player2]
+/// left = [{ gamepad = 1, axis = 0, positive = false }]
+/// ```
+/// Buttons that are left out of the file have no bindings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputMapping {
+    /// Release both directions when opposing directions (up and down or left and right)
+    /// are held at the same time, as they can not be on a real d-pad
+    pub filter_opposing: bool,
+    /// The pause button on the console, which raises an NMI
+    pub pause: Vec<Binding>,
+    pub player1: PadMapping,
+    pub player2: PadMapping,
+}
+
+impl Default for InputMapping {
+    fn default() -> InputMapping {
+        InputMapping {
+            filter_opposing: true,
+            pause: vec![Binding::Key { key: Key::Space }],
+            player1: PadMapping::with_keys([Key::W, Key::S, Key::A, Key::D, Key::J, Key::K], 0),
+            player2: PadMapping::with_keys(
+                [
+                    Key::Up,
+                    Key::Down,
+                    Key::Left,
+                    Key::Right,
+                    Key::Comma,
+                    Key::Period,
+                ],
+                1,
+            ),
+        }
+    }
+}
+
+impl InputMapping {
+    /// Read a mapping from a TOML file
+    pub fn load(path: &Path) -> io::Result<InputMapping> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Write the mapping to a TOML file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = toml::to_string_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+
+    fn pads(&self) -> [&PadMapping; 2] {
+        [&self.player1, &self.player2]
+    }
+}
+
+/// Both joypads, driven by the host's keyboard and gamepads
+///
+/// Every host input is tracked on its own, so any number of buttons can be held at once
+/// and releasing one input never releases a button that is still held through another.
+pub struct KeyboardController {
+    mapping: InputMapping,
+    /// The host inputs that are held down
+    held: HashSet<Binding>,
+    /// The last direction reported for each (gamepad, axis)
+    axes: HashMap<(i32, u8), i8>,
+}
+
+impl KeyboardController {
+    pub fn new() -> KeyboardController {
+        KeyboardController::with_mapping(InputMapping::default())
+    }
+
+    pub fn with_mapping(mapping: InputMapping) -> KeyboardController {
+        KeyboardController {
+            mapping,
+            held: HashSet::new(),
+            axes: HashMap::new(),
+        }
+    }
+
+    pub fn set_mapping(&mut self, mapping: InputMapping) {
+        self.mapping = mapping;
+    }
+
+    /// The buttons of the joypad in `port` (0 or 1) that are held
+    pub fn state(&self, port: usize) -> JoypadState {
+        let state = self.mapping.pads()[port].state(&self.held);
+        if self.mapping.filter_opposing {
+            state.without_opposing()
+        } else {
+            state
+        }
+    }
+
+    /// Whether the pause button of the console is held
+    pub fn is_pause_held(&self) -> bool {
+        self.mapping
+            .pause
+            .iter()
+            .any(|binding| self.held.contains(binding))
+    }
+
+    pub fn input(&mut self, args: &ButtonArgs) {
+        let is_pressed = args.state == ButtonState::Press;
+        match args.button {
+            Button::Keyboard(key) => self.change_binding_state(Binding::Key { key }, is_pressed),
+            Button::Controller(ControllerButton { id, button }) => self.change_binding_state(
+                Binding::Button {
+                    gamepad: id,
+                    button,
+                },
+                is_pressed,
+            ),
+            Button::Hat(ControllerHat { id, state, .. }) => {
+                use HatDirection::*;
+                for &direction in [Up, Down, Left, Right].iter() {
+                    let binding = Binding::Hat {
+                        gamepad: id,
+                        hat: direction,
+                    };
+                    self.change_binding_state(binding, is_pressed && direction.is_active(state));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn axis_input(&mut self, args: &ControllerAxisArgs) {
+        let direction = if args.position > AXIS_THRESHOLD {
+            1
+        } else if args.position < -AXIS_THRESHOLD {
+            -1
+        } else {
+            0
+        };
+
+        let previous = self
+            .axes
+            .insert((args.id, args.axis), direction)
+            .unwrap_or(0);
+        if previous == direction {
+            return;
+        }
+
+        let binding = |positive| Binding::Axis {
+            gamepad: args.id,
+            axis: args.axis,
+            positive,
+        };
+        if previous != 0 {
+            self.change_binding_state(binding(previous > 0), false);
+        }
+        if direction != 0 {
+            self.change_binding_state(binding(direction > 0), true);
+        }
+    }
+
+    fn change_binding_state(&mut self, binding: Binding, is_pressed: bool) {
+        if is_pressed {
+            self.held.insert(binding);
+        } else {
+            self.held.remove(&binding);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: Key, state: ButtonState) -> ButtonArgs {
+        ButtonArgs {
+            state,
+            button: Button::Keyboard(key),
+            scancode: None,
+        }
+    }
+
+    #[test]
+    fn test_independent_buttons() {
+        let mut controller = KeyboardController::new();
+        controller.input(&key(Key::W, ButtonState::Press));
+        controller.input(&key(Key::D, ButtonState::Press));
+        controller.input(&key(Key::J, ButtonState::Press));
+        assert_eq!(
+            JoypadState::UP | JoypadState::RIGHT | JoypadState::BUTTON1,
+            controller.state(0)
+        );
+        assert_eq!(JoypadState::empty(), controller.state(1));
+
+        // releasing one direction keeps the other held
+        controller.input(&key(Key::W, ButtonState::Release));
+        assert_eq!(
+            JoypadState::RIGHT | JoypadState::BUTTON1,
+            controller.state(0)
+        );
+    }
+
+    #[test]
+    fn test_filter_opposing() {
+        let mut controller = KeyboardController::new();
+        controller.input(&key(Key::A, ButtonState::Press));
+        controller.input(&key(Key::D, ButtonState::Press));
+        assert_eq!(JoypadState::empty(), controller.state(0));
+
+        controller.set_mapping(InputMapping {
+            filter_opposing: false,
+            ..InputMapping::default()
+        });
+        assert_eq!(JoypadState::LEFT | JoypadState::RIGHT, controller.state(0));
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut controller = KeyboardController::new();
+        controller.input(&key(Key::Space, ButtonState::Press));
+        assert!(controller.is_pause_held());
+        assert_eq!(JoypadState::empty(), controller.state(0));
+
+        controller.input(&key(Key::Space, ButtonState::Release));
+        assert!(!controller.is_pause_held());
+    }
+
+    #[test]
+    fn test_mapping_round_trip() {
+        let mapping = InputMapping::default();
+        let text = toml::to_string_pretty(&mapping).unwrap();
+
+        assert_eq!(mapping, toml::from_str(&text).unwrap());
+    }
+}
@@ -0,0 +1,135 @@
+use bitflags::bitflags;
+use bus::{
+    state::{take_u8, SaveState},
+    BusConnectable,
+};
+
+bitflags! {
+    /// The buttons of one joypad that are held down
+    ///
+    /// Unlike the value read from the ports, a set bit means the button is pressed.
+    #[derive(Default)]
+    pub struct JoypadState: u8 {
+        const UP      = 1 << 0;
+        const DOWN    = 1 << 1;
+        const LEFT    = 1 << 2;
+        const RIGHT   = 1 << 3;
+        const BUTTON1 = 1 << 4;
+        const BUTTON2 = 1 << 5;
+    }
+}
+
+impl JoypadState {
+    /// Release both directions of an axis when opposing directions are held together,
+    /// which a real d-pad can not do
+    pub fn without_opposing(self) -> JoypadState {
+        let mut state = self;
+        if state.contains(JoypadState::UP | JoypadState::DOWN) {
+            state.remove(JoypadState::UP | JoypadState::DOWN);
+        }
+        if state.contains(JoypadState::LEFT | JoypadState::RIGHT) {
+            state.remove(JoypadState::LEFT | JoypadState::RIGHT);
+        }
+
+        state
+    }
+}
+
+/// The two controller ports as the cpu sees them
+///
+/// Port 0xDC holds all of player 1 plus player 2's up and down, port 0xDD holds the rest
+/// of player 2.  The bits are low while a button is pressed.
+///
+/// Only A7, A6 and A0 are decoded, so every even port from 0xC0 up mirrors 0xDC and every
+/// odd one mirrors 0xDD.
+#[derive(Clone, Default)]
+pub struct Joypads {
+    states: [JoypadState; 2],
+}
+
+impl Joypads {
+    pub fn new() -> Joypads {
+        Joypads::default()
+    }
+
+    pub fn state(&self, port: usize) -> JoypadState {
+        self.states[port]
+    }
+
+    pub fn set_state(&mut self, port: usize, state: JoypadState) {
+        self.states[port] = state;
+    }
+
+    /// Port 0xDC: all of player 1 plus player 2's up (bit 6) and down (bit 7)
+    fn port_a(&self) -> u8 {
+        let player2 = self.states[1].bits() & 0b11;
+        !(self.states[0].bits() | (player2 << 6))
+    }
+
+    /// Port 0xDD: player 2's left, right and buttons in the low nibble.  The upper bits
+    /// are not connected to the joypads and read high.
+    fn port_b(&self) -> u8 {
+        !((self.states[1].bits() >> 2) & 0b1111)
+    }
+}
+
+impl BusConnectable for Joypads {
+    fn accept(&self, addr: u16) -> bool {
+        addr & 0xC0 == 0xC0
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        if addr & 1 == 0 {
+            self.port_a()
+        } else {
+            self.port_b()
+        }
+    }
+
+    fn cpu_write(&mut self, _addr: u16, _val: u8) -> bool {
+        false
+    }
+}
+
+impl SaveState for Joypads {
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend(self.states.iter().map(|state| state.bits()));
+    }
+
+    fn load_state(&mut self, state: &mut &[u8]) {
+        for joypad in self.states.iter_mut() {
+            *joypad = JoypadState::from_bits_truncate(take_u8(state));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ports() {
+        let mut joypads = Joypads::new();
+        assert_eq!(0xFF, joypads.cpu_read(0xDC));
+        assert_eq!(0xFF, joypads.cpu_read(0xDD));
+
+        joypads.set_state(0, JoypadState::UP | JoypadState::RIGHT);
+        joypads.set_state(1, JoypadState::DOWN | JoypadState::BUTTON2);
+        assert_eq!(0b0111_0110, joypads.cpu_read(0xDC));
+        assert_eq!(0b1111_0111, joypads.cpu_read(0xDD));
+
+        // the mirrors
+        assert_eq!(0b0111_0110, joypads.cpu_read(0xC0));
+        assert_eq!(0b1111_0111, joypads.cpu_read(0xFF));
+        assert!(!joypads.accept(0xBF));
+    }
+
+    #[test]
+    fn test_without_opposing() {
+        let state = JoypadState::UP | JoypadState::DOWN | JoypadState::LEFT;
+        assert_eq!(JoypadState::LEFT, state.without_opposing());
+
+        let diagonal = JoypadState::UP | JoypadState::RIGHT;
+        assert_eq!(diagonal, diagonal.without_opposing());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::bios::{Slot, SlotMapper};
+use crate::joypad::Joypads;
+use bus::{
+    bus::{Bus, PAGE_SIZE},
+    io::IoBus,
+    ram::Ram,
+    BusConnectable,
+};
+use sn76489::psg::Psg;
+use tms9918::ppu::Ppu;
+use z80::cpu::Z80Bus;
+
+/// Every chip and memory of the console, as the cpu sees them
+///
+/// The machine owns all of its devices and the cpu owns the machine, so nothing is shared
+/// and the whole console can be cloned or moved to another thread.
+///
+/// The memory goes to the cartridge slot wherever its mapper claims the address and to the
+/// work ram otherwise.  The bank registers of the Sega mapper (0xFFFD-0xFFFF) only listen
+/// to writes, which reach the work ram underneath as well.  Where every page of it is read
+/// from is kept in a `Bus`, which is rebuilt when the game switches banks or the memory
+/// control port switches between the BIOS and the cartridge.
+///
+/// The ports are decoded by an `IoBus`, see `Machine::new`.
+#[derive(Clone)]
+pub struct Machine {
+    pub ppu: Ppu,
+    pub psg: Psg,
+    pub joypads: Joypads,
+    /// The cartridge and the BIOS, along with the memory control port that picks one
+    pub slots: SlotMapper,
+    pub work_ram: Ram,
+    memory: Bus<Memory>,
+    ports: IoBus<Port>,
+}
+
+/// What a page of the memory is read from
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Memory {
+    /// The BIOS or the game, read directly
+    Slot(Slot),
+    /// The slot mapper is asked on every access, for an open bus or an image whose size
+    /// is not a whole number of pages
+    Slots,
+    WorkRam,
+}
+
+/// The devices that answer on the I/O ports
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Port {
+    MemoryControl,
+    Psg,
+    Vdp,
+    Joypads,
+}
+
+impl Machine {
+    /// Connect the devices, with no buttons held on the joypads
+    ///
+    /// Only A7, A6 and A0 of a port are decoded:
+    ///     0x00-0x3F: the memory control port on even ports, nothing on odd ones.  The
+    ///                SG-1000 has no such port, so without a BIOS nothing answers at all.
+    ///     0x40-0x7F: the PSG
+    ///     0x80-0xBF: the VDP, data on even ports and control on odd ones
+    ///     0xC0-0xFF: the joypads, 0xDC on even ports and 0xDD on odd ones
+    pub fn new(ppu: Ppu, psg: Psg, slots: SlotMapper, work_ram: Ram) -> Machine {
+        let mut ports = IoBus::builder()
+            .add(0x40..=0x7F, Port::Psg)
+            .add(0x80..=0xBF, Port::Vdp)
+            .add(0xC0..=0xFF, Port::Joypads);
+        if slots.has_bios() {
+            ports = ports.add((0x00..=0x3F).step_by(2), Port::MemoryControl);
+        }
+
+        let mut machine = Machine {
+            ppu,
+            psg,
+            joypads: Joypads::new(),
+            slots,
+            work_ram,
+            memory: Bus::new(),
+            ports: ports.build(),
+        };
+        machine.remap();
+        machine
+    }
+
+    /// Rebuild the page table of the memory
+    ///
+    /// The machine does this itself for the writes of the cpu, but it has to be called
+    /// after changing the slots from outside, e.g. when loading a saved state.
+    pub fn remap(&mut self) {
+        for addr in Bus::<Memory>::pages() {
+            let page = if self.slots.accept(addr) {
+                let direct = self
+                    .slots
+                    .offset(addr)
+                    .filter(|&(slot, _)| self.slots.image(slot).len().is_multiple_of(PAGE_SIZE));
+                Some(direct.map_or((Memory::Slots, 0), |(slot, offset)| {
+                    (Memory::Slot(slot), offset)
+                }))
+            } else {
+                self.work_ram
+                    .offset(addr)
+                    .map(|offset| (Memory::WorkRam, offset))
+            };
+            self.memory.map(addr, page);
+        }
+    }
+}
+
+impl Z80Bus for Machine {
+    fn read(&mut self, addr: u16) -> u8 {
+        match self.memory.device(addr) {
+            Some((Memory::Slot(slot), offset)) => self.slots.image(slot)[offset],
+            Some((Memory::Slots, _)) => self.slots.cpu_read(addr),
+            Some((Memory::WorkRam, offset)) => self.work_ram.data()[offset],
+            None => {
+                bus::log!(Bus, Debug, "read from unmapped address 0x{:04x}", addr);
+                0xFF
+            }
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if self.slots.write_register(addr, val) {
+            self.remap();
+        }
+        match self.memory.device(addr) {
+            Some((Memory::WorkRam, offset)) => self.work_ram.data_mut()[offset] = val,
+            // the BIOS and the game are read only
+            Some(_) => {}
+            None => bus::log!(Bus, Debug, "write to unmapped address 0x{:04x}", addr),
+        }
+    }
+
+    fn input(&mut self, port: u16) -> u8 {
+        match self.ports.device(port) {
+            Some(Port::Psg) => self.psg.cpu_read(port),
+            Some(Port::Vdp) => self.ppu.cpu_read(port),
+            Some(Port::Joypads) => self.joypads.cpu_read(port),
+            // the memory control port is write only, nothing drives the data bus
+            Some(Port::MemoryControl) | None => {
+                bus::log!(Io, Debug, "read from unmapped port 0x{:02x}", port & 0xFF);
+                0xFF
+            }
+        }
+    }
+
+    fn output(&mut self, port: u16, val: u8) {
+        match self.ports.device(port) {
+            Some(Port::MemoryControl) => {
+                if self.slots.write_control(val) {
+                    self.remap();
+                }
+            }
+            Some(Port::Psg) => {
+                self.psg.cpu_write(port, val);
+            }
+            Some(Port::Vdp) => {
+                self.ppu.cpu_write(port, val);
+            }
+            // the joypads ignore writes
+            Some(Port::Joypads) => {
+                self.joypads.cpu_write(port, val);
+            }
+            None => bus::log!(Io, Debug, "write to unmapped port 0x{:02x}", port & 0xFF),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bios::{MemoryControl, BIOS_BOOT, CARTRIDGE_BOOT};
+    use crate::cartridge::{Cartridge, Mapper};
+    use crate::joypad::JoypadState;
+    use bus::MemoryMap;
+
+    fn machine() -> Machine {
+        let rom = (0..0x8000).map(|addr| (addr >> 8) as u8).collect();
+        with_slots(SlotMapper::new(
+            Cartridge::new(rom, Mapper::Rom),
+            None,
+            MemoryControl::new(CARTRIDGE_BOOT),
+        ))
+    }
+
+    fn with_slots(slots: SlotMapper) -> Machine {
+        Machine::new(
+            Ppu::new(),
+            Psg::new(),
+            slots,
+            Ram::builder()
+                .size(0x2000)
+                .map(MemoryMap::from(0xC000..0xE000))
+                .mirror(MemoryMap::from(0xE000..=0xFFFF))
+                .build(),
+        )
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut machine = machine();
+        assert_eq!(0x12, machine.read(0x1234));
+        assert_eq!(0xFF, machine.read(0x8000));
+
+        machine.write(0xC010, 0x55);
+        assert_eq!(0x55, machine.read(0xE010));
+
+        // a BIOS that is not a whole number of pages is still mirrored
+        let mut machine = with_slots(SlotMapper::new(
+            Cartridge::new(vec![0; 0x8000], Mapper::Rom),
+            Some(vec![1, 2, 3]),
+            MemoryControl::new(BIOS_BOOT),
+        ));
+        assert_eq!(2, machine.read(0x0100));
+        assert_eq!(3, machine.read(0x0101));
+    }
+
+    #[test]
+    fn test_mapper_registers() {
+        let rom = (0..0x20000).map(|addr| (addr >> 14) as u8).collect();
+        let mut machine = with_slots(SlotMapper::new(
+            Cartridge::new(rom, Mapper::Sega),
+            None,
+            MemoryControl::new(CARTRIDGE_BOOT),
+        ));
+
+        machine.write(0xFFFE, 6);
+        assert_eq!(6, machine.read(0x4000));
+        // the work ram under the registers keeps the value, and so does its mirror
+        assert_eq!(6, machine.read(0xFFFE));
+        assert_eq!(6, machine.read(0xDFFE));
+        // writing the mirror does not switch banks
+        machine.write(0xDFFF, 7);
+        assert_eq!(2, machine.read(0x8000));
+        assert_eq!(7, machine.read(0xFFFF));
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut machine = machine();
+        machine.joypads.set_state(0, JoypadState::BUTTON1);
+        assert_eq!(0xEF, machine.input(0xDC));
+        assert_eq!(0xEF, machine.input(0xC0));
+        assert_eq!(0xFF, machine.input(0xDD));
+
+        // without a BIOS there is no memory control port, so the cartridge stays mapped
+        machine.output(0x3E, 0xFF);
+        assert!(machine.slots.control().cartridge_enabled());
+        assert_eq!(0x12, machine.read(0x1234));
+
+        let mut machine = with_slots(SlotMapper::new(
+            Cartridge::new(vec![0; 0x8000], Mapper::Rom),
+            Some(vec![0xB0; 0x400]),
+            MemoryControl::new(BIOS_BOOT),
+        ));
+        // odd ports below 0x40 are not connected
+        machine.output(0x3F, CARTRIDGE_BOOT);
+        assert!(!machine.slots.control().cartridge_enabled());
+        assert_eq!(0xB0, machine.read(0x0000));
+        // and every even one mirrors 0x3E
+        machine.output(0x10, CARTRIDGE_BOOT);
+        assert!(machine.slots.control().cartridge_enabled());
+        assert_eq!(0x00, machine.read(0x0000));
+    }
+
+    #[test]
+    fn test_send() {
+        fn runs_anywhere<T: Clone + Send + 'static>() {}
+        runs_anywhere::<z80::cpu::Cpu<Machine>>();
+    }
+}
@@ -4,44 +4,506 @@ extern crate graphics;
 extern crate image as im;
 extern crate opengl_graphics;
 extern crate piston;
+extern crate sn76489;
 extern crate z80;
 
-use emulator::Emulator;
+#[cfg(feature = "audio-device")]
+use audio::DeviceSink;
+use audio::{AudioDump, AudioPipeline, AudioSink, NullSink, DEFAULT_SAMPLE_RATE};
+use cartridge::load_rom;
+use cli::CliError;
+use config::{Config, LEGACY_INPUT_FILE};
+use emulator::{crc32, BootOptions, Emulator};
+use hotkeys::Command;
+use movie::{Movie, MoviePlayer, MovieRecorder, DEFAULT_HASH_INTERVAL};
 use opengl_graphics::{GlyphCache, OpenGL, TextureSettings};
+use pacing::{FramePacer, FAST_FORWARD_SPEED, SLOW_MOTION_SPEED};
 use piston::event_loop::{EventSettings, Events};
 use piston::input::ButtonEvent;
 use piston::window::WindowSettings;
-use piston::EventLoop;
-use piston::{ButtonArgs, RenderEvent};
+use piston::{ButtonArgs, ButtonState, ControllerAxisArgs, ControllerAxisEvent, RenderEvent};
 use piston_window::*;
-use std::path::PathBuf;
+use rewind::Rewind;
+use screenshot::{save_screenshot, screenshot_path};
+use std::env;
+use std::fs::File;
+use std::io::{stdout, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+use tms9918::ppu::{HEIGHT, WIDTH};
+use tms9918::Canvas;
+use video::VideoRecorder;
 
+mod audio;
+mod bios;
+mod cartridge;
+mod cli;
+mod config;
 mod emulator;
+#[cfg(test)]
+mod golden;
+mod hotkeys;
+mod input;
+mod joypad;
+mod machine;
+mod movie;
+mod pacing;
+mod rewind;
+mod screenshot;
+mod video;
 
 pub struct App {
     pub emulator: Emulator,
+    recorder: Option<MovieRecorder>,
+    player: Option<MoviePlayer>,
+    rewind: Rewind,
+    /// Whether the rewind key is held
+    rewinding: bool,
+    /// The sound card, or nowhere without a window or a sound card
+    audio: AudioPipeline<Box<dyn AudioSink>>,
+    audio_dump: Option<AudioDump>,
+    video: Option<VideoRecorder>,
+    /// The config file as it was read, without the command line options
+    config: Config,
+    config_path: Option<PathBuf>,
+    /// The settings the game runs with
+    settings: Config,
+    game_name: String,
+    pacer: FramePacer,
+    /// Whether the slow motion is switched on, the speed while fast-forward is not held
+    slow_motion: bool,
+    /// Whether the picture should show the frame that is being drawn, after advancing by
+    /// scanlines
+    show_next_frame: bool,
 }
 
 impl App {
+    /// Emulate the frames that are due at the given time of the host
+    fn update_paced(&mut self, now: Instant) {
+        self.pacer.tick(now);
+        while self.pacer.frame_due() {
+            self.update();
+            self.pacer.ran(self.emulator.frame_cycles());
+        }
+    }
+
     fn update(&mut self) {
-        self.emulator.refresh();
+        let frame = self.emulator.frame();
+        self.show_next_frame = false;
+
+        // rewinding would break the movie that is being recorded or played
+        if self.rewinding && self.recorder.is_none() && self.player.is_none() {
+            self.rewind.step_back(&mut self.emulator, 1);
+            return;
+        }
+
+        match &mut self.player {
+            Some(player) => match player.play_frame(&mut self.emulator) {
+                Ok(true) => {}
+                Ok(false) => {
+                    println!("Movie finished");
+                    self.player = None;
+                }
+                Err(err) => {
+                    eprintln!("Movie playback stopped: {}", err);
+                    self.player = None;
+                }
+            },
+            None => self.emulator.run_frame(),
+        }
+
+        self.after_emulation(frame);
+    }
+
+    /// Emulate up to the next line of the VDP, showing the frame as far as it is drawn
+    fn advance_scanline(&mut self) {
+        // the movie is played back by whole frames
+        if self.player.is_some() {
+            eprintln!("Can not advance by scanlines while playing a movie");
+            return;
+        }
+
+        let frame = self.emulator.frame();
+        self.emulator.run_scanline();
+        self.show_next_frame = self.emulator.frame() == frame;
+        self.after_emulation(frame);
+    }
+
+    /// Record the emulated frame if `frame` was finished
+    fn after_emulation(&mut self, frame: u64) {
+        if self.emulator.frame() == frame {
+            return;
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&self.emulator);
+        }
+        self.rewind.record(&self.emulator);
+
+        if let Err(err) = self.audio.push(self.emulator.audio()) {
+            eprintln!("Could not play audio, running without sound: {}", err);
+            self.audio = silent_audio();
+        }
+        if let Some(dump) = &mut self.audio_dump {
+            dump.push(self.emulator.audio())
+                .expect("Could not write audio dump");
+        }
+        if let Some(video) = &mut self.video {
+            video.record(&self.emulator).expect("Could not write video");
+        }
     }
 
     fn input(&mut self, args: &ButtonArgs) {
-        self.emulator.input(args);
+        match self.settings.hotkeys.command(args) {
+            Some(command) => self.command(command, args.state == ButtonState::Press),
+            // the movie holds the buttons while it plays
+            None if self.player.is_some() => {}
+            None => self.emulator.input(args),
+        }
+    }
+
+    /// Run a command of the frontend, on `pressed` and on release for the ones that are held
+    fn command(&mut self, command: Command, pressed: bool) {
+        match command {
+            Command::FastForward => {
+                let speed = if pressed {
+                    FAST_FORWARD_SPEED
+                } else if self.slow_motion {
+                    SLOW_MOTION_SPEED
+                } else {
+                    1.0
+                };
+                self.pacer.set_speed(speed);
+            }
+            Command::Rewind => self.rewinding = pressed,
+            _ if !pressed => {}
+            Command::Pause => {
+                let paused = !self.pacer.is_paused();
+                self.pacer.set_paused(paused);
+            }
+            Command::FrameAdvance => {
+                self.pacer.set_paused(true);
+                self.pacer.advance_frame();
+            }
+            Command::ScanlineAdvance => {
+                self.pacer.set_paused(true);
+                self.advance_scanline();
+            }
+            Command::SlowMotion => {
+                self.slow_motion = !self.slow_motion;
+                if self.pacer.speed() != FAST_FORWARD_SPEED {
+                    let speed = if self.slow_motion {
+                        SLOW_MOTION_SPEED
+                    } else {
+                        1.0
+                    };
+                    self.pacer.set_speed(speed);
+                }
+            }
+            Command::Screenshot => {
+                let path = screenshot_path(self.emulator.frame());
+                match save_screenshot(&self.emulator, &path) {
+                    Ok(()) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => eprintln!("Could not save screenshot: {}", err),
+                }
+            }
+            Command::RecordVideo => self.toggle_video(),
+            Command::SaveConfig => self.save_game_config(),
+            Command::DumpState => self
+                .emulator
+                .log_state(stdout())
+                .expect("Could not write the registers"),
+        }
+    }
+
+    /// The picture to show if it changed: the last frame, or the frame that is being drawn
+    /// after advancing by scanlines
+    fn take_canvas(&mut self) -> Option<&Canvas> {
+        let ppu = self.emulator.ppu_mut();
+        if self.show_next_frame {
+            self.show_next_frame = false;
+            Some(ppu.next_frame())
+        } else {
+            ppu.get_canvas()
+        }
+    }
+
+    /// Start recording video (with audio) to the working directory, or stop the recording
+    fn toggle_video(&mut self) {
+        match self.video.take() {
+            Some(video) => {
+                let frames = video.frames();
+                match video.finish() {
+                    Ok(()) => println!("Stopped video recording after {} frames", frames),
+                    Err(err) => eprintln!("Could not finish video: {}", err),
+                }
+            }
+            None => {
+                let path = PathBuf::from(format!("video-{:06}.y4m", self.emulator.frame()));
+                let region = self.emulator.options().region;
+                match VideoRecorder::create(&path, region, true) {
+                    Ok(video) => {
+                        println!("Recording video to {}", path.display());
+                        self.video = Some(video);
+                    }
+                    Err(err) => eprintln!("Could not start video recording: {}", err),
+                }
+            }
+        }
+    }
+
+    /// Keep the settings the game runs with as its overrides in the config file
+    fn save_game_config(&mut self) {
+        let path = match &self.config_path {
+            Some(path) => path,
+            None => {
+                eprintln!("There is no config directory to save the settings in");
+                return;
+            }
+        };
+
+        let game = self.config.game_mut(self.emulator.rom_crc());
+        game.name = Some(self.game_name.clone());
+        game.scale = Some(self.settings.scale);
+        game.region = Some(self.settings.region);
+        game.mapper = self.emulator.options().mapper;
+        game.bios = self.settings.bios.clone();
+        match self.config.save(path) {
+            Ok(()) => println!(
+                "Saved the settings of {} to {}",
+                self.game_name,
+                path.display()
+            ),
+            Err(err) => eprintln!("Could not save the settings: {}", err),
+        }
+    }
+
+    fn axis_input(&mut self, args: &ControllerAxisArgs) {
+        if self.player.is_none() {
+            self.emulator.axis_input(args);
+        }
+    }
+}
+
+/// The number of samples buffered for the audio device, about 100ms
+const AUDIO_BUFFER_SIZE: usize = DEFAULT_SAMPLE_RATE as usize / 10;
+
+/// The environment variable with the levels of the log targets, like `--log`
+const LOG_VAR: &str = "SG_LOG";
+
+/// Print an error and quit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("sg-1000: {}", message);
+    process::exit(1);
+}
+
+/// Play the audio on the sound card, unless there is no window
+///
+/// Without a sound card, or when built without the `audio-device` feature, the game runs
+/// silently.
+#[cfg(feature = "audio-device")]
+fn audio_sink(headless: bool) -> Box<dyn AudioSink> {
+    if headless {
+        return Box::new(NullSink::new(DEFAULT_SAMPLE_RATE));
+    }
+
+    match DeviceSink::open(AUDIO_BUFFER_SIZE) {
+        Ok(sink) => Box::new(sink),
+        Err(err) => {
+            eprintln!("{}, running without sound", err);
+            Box::new(NullSink::new(DEFAULT_SAMPLE_RATE))
+        }
+    }
+}
+
+#[cfg(not(feature = "audio-device"))]
+fn audio_sink(_headless: bool) -> Box<dyn AudioSink> {
+    Box::new(NullSink::new(DEFAULT_SAMPLE_RATE))
+}
+
+/// A pipeline that throws the audio away, for when the sound card fails
+fn silent_audio() -> AudioPipeline<Box<dyn AudioSink>> {
+    AudioPipeline::new(
+        sn76489::psg::SAMPLE_RATE,
+        Box::new(NullSink::new(DEFAULT_SAMPLE_RATE)),
+        AUDIO_BUFFER_SIZE,
+    )
+}
+
+/// Run the game without a window for the given number of frames, e.g. for checking the
+/// output of the emulator in scripts
+fn run_headless(app: &mut App, frames: u64, screenshot: Option<&Path>) {
+    for _ in 0..frames {
+        app.update();
+    }
+
+    if let Some(screenshot) = screenshot {
+        save_screenshot(&app.emulator, screenshot)
+            .unwrap_or_else(|err| fail(format!("Could not save screenshot: {}", err)));
+        println!("Saved screenshot to {}", screenshot.display());
     }
 }
 
 fn main() {
-    // Change this to OpenGL::V2_1 if not working.
-    let opengl = OpenGL::V3_2;
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(CliError::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("sg-1000: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    let log_filter = options.log.clone().or_else(|| env::var(LOG_VAR).ok());
+    if let Some(filter) = log_filter {
+        bus::log::set_filter(&filter)
+            .unwrap_or_else(|err| fail(format!("Invalid log filter: {}", err)));
+    }
+
+    let config_path = options.config.clone().or_else(Config::default_path);
+    let mut config = match &config_path {
+        Some(path) if options.config.is_some() && !path.exists() => {
+            fail(format!("Could not find {}", path.display()))
+        }
+        Some(path) => Config::load(path)
+            .unwrap_or_else(|err| fail(format!("Could not read {}: {}", path.display(), err))),
+        None => Config::default(),
+    };
+    let legacy_input = Path::new(LEGACY_INPUT_FILE);
+    if legacy_input.exists() {
+        let message = config
+            .read_legacy_input(legacy_input)
+            .unwrap_or_else(|err| {
+                fail(format!(
+                    "Could not read {}: {}",
+                    legacy_input.display(),
+                    err
+                ))
+            });
+        eprintln!("{}", message);
+    }
+
+    let rom = load_rom(&options.rom).unwrap_or_else(|err| fail(err));
+    let rom_crc = crc32(&rom);
+
+    // the command line has the last word, then the settings of the game
+    let mut settings = config.for_game(rom_crc);
+    settings.scale = options.scale.unwrap_or(settings.scale);
+    settings.region = options.region.unwrap_or(settings.region);
+    settings.mapper = options.mapper.or(settings.mapper);
+    settings.bios = options.bios.clone().or(settings.bios);
+    settings.fullscreen |= options.fullscreen;
+    let boot = BootOptions {
+        bios: settings.bios.clone(),
+        region: settings.region,
+        mapper: settings.mapper,
+        ..BootOptions::default()
+    };
+
+    let mut emulator = Emulator::with_rom(rom, &boot).unwrap_or_else(|err| fail(err));
+    emulator.ppu_mut().set_zoom(settings.vdp_zoom.max(1));
+    emulator.set_input_mapping(settings.input.clone());
+    if let Some(trace) = &options.trace {
+        let file = File::create(trace)
+            .unwrap_or_else(|err| fail(format!("Could not create {}: {}", trace.display(), err)));
+        emulator.set_trace(Some(Box::new(BufWriter::new(file))));
+    }
+
+    let player = options.play.as_ref().map(|play| {
+        let movie = Movie::load(play).unwrap_or_else(|err| fail(err));
+        MoviePlayer::new(movie, &mut emulator).unwrap_or_else(|err| fail(err))
+    });
+    let recorder = options
+        .record
+        .as_ref()
+        .map(|_| MovieRecorder::new(&emulator, DEFAULT_HASH_INTERVAL));
+    let audio_dump = options.dump_audio.as_ref().map(|path| {
+        AudioDump::create(path)
+            .unwrap_or_else(|err| fail(format!("Could not create {}: {}", path.display(), err)))
+    });
+    let video = options.record_video.as_ref().map(|path| {
+        VideoRecorder::create(path, settings.region, options.video_audio)
+            .unwrap_or_else(|err| fail(format!("Could not create {}: {}", path.display(), err)))
+    });
+
+    // Create a new game and run it.
+    let mut app = App {
+        emulator,
+        recorder,
+        player,
+        rewind: Rewind::default(),
+        rewinding: false,
+        audio: AudioPipeline::new(
+            sn76489::psg::SAMPLE_RATE,
+            audio_sink(options.headless),
+            AUDIO_BUFFER_SIZE,
+        ),
+        audio_dump,
+        video,
+        config,
+        config_path,
+        pacer: FramePacer::new(settings.region),
+        slow_motion: false,
+        show_next_frame: false,
+        settings,
+        game_name: options
+            .rom
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    app.rewind.record(&app.emulator);
+
+    if options.headless {
+        let frames = options.screenshot_after.or(options.frames).unwrap();
+        let screenshot = options.screenshot_after.map(screenshot_path);
+        run_headless(&mut app, frames, screenshot.as_deref());
+    } else {
+        run_window(&mut app, options.frames);
+    }
+
+    if let (Some(recorder), Some(record_path)) = (app.recorder, &options.record) {
+        recorder
+            .finish()
+            .save(record_path)
+            .unwrap_or_else(|err| fail(format!("Could not save movie: {}", err)));
+    }
 
+    if let Some(dump) = app.audio_dump {
+        dump.finish()
+            .unwrap_or_else(|err| fail(format!("Could not write audio dump: {}", err)));
+    }
+
+    if let Some(video) = app.video {
+        video
+            .finish()
+            .unwrap_or_else(|err| fail(format!("Could not write video: {}", err)));
+    }
+}
+
+fn run_window(app: &mut App, frames: Option<u64>) {
+    let settings = app.settings.clone();
+    let opengl = match settings.opengl.as_str() {
+        "2.1" => OpenGL::V2_1,
+        "3.2" => OpenGL::V3_2,
+        other => fail(format!(
+            "Unsupported OpenGL version {} in the config, expected 3.2 or 2.1",
+            other
+        )),
+    };
+
+    let size = [settings.scale * WIDTH, settings.scale * HEIGHT];
     // Create a Glutin window.
-    let mut window: PistonWindow = WindowSettings::new("sg-1000", [2 * 256, 2 * 192])
+    let mut window: PistonWindow = WindowSettings::new("sg-1000", size)
         .graphics_api(opengl)
+        .fullscreen(settings.fullscreen)
         .exit_on_esc(true)
         .build()
-        .unwrap();
+        .unwrap_or_else(|err| fail(format!("Could not create a window: {}", err)));
 
     let mut texture_context = TextureContext {
         factory: window.factory.clone(),
@@ -51,41 +513,41 @@ fn main() {
     // let ref mut glyphs = GlyphCache::new("assets/FiraMono-Regular.ttf", (), texture_settings)
     //     .expect("Could not load font");
 
-    let path = std::env::args()
-        .nth(1)
-        .expect("The path of the game was not specified!");
-
-    // Create a new game and run it.
-    let mut app = App {
-        emulator: emulator::Emulator::new(&PathBuf::from(path)),
-    };
+    let canvas = app.emulator.ppu_mut().get_canvas().unwrap();
+    // the VDP draws its pictures zoomed in already
+    let zoom = settings.scale as f64 * WIDTH as f64 / canvas.width() as f64;
+    let mut texture: G2dTexture =
+        Texture::from_image(&mut texture_context, canvas, &TextureSettings::new())
+            .unwrap_or_else(|err| fail(format!("Could not create a texture: {}", err)));
 
-    let mut texture: G2dTexture = Texture::from_image(
-        &mut texture_context,
-        &app.emulator.ppu.borrow_mut().get_canvas().unwrap(),
-        &TextureSettings::new(),
-    )
-    .unwrap();
-
-    let mut events = Events::new(EventSettings::new().max_fps(60));
+    // the pacer decides how many frames to emulate, whatever the refresh rate of the host
+    let mut events = Events::new(EventSettings::new());
     while let Some(e) = events.next(&mut window) {
         if let Some(_) = e.render_args() {
-            app.update();
+            app.update_paced(Instant::now());
 
-            if let Some(canvas) = app.emulator.ppu.borrow_mut().get_canvas() {
-                texture.update(&mut texture_context, &canvas).unwrap();
+            if let Some(canvas) = app.take_canvas() {
+                texture.update(&mut texture_context, canvas).unwrap();
             }
             window.draw_2d(&e, |c, g, device| {
                 // Update texture before rendering.
                 texture_context.encoder.flush(device);
 
                 clear([0.0, 0.0, 0.0, 1.0], g);
-                image(&texture, c.transform, g);
+                image(&texture, c.transform.zoom(zoom), g);
             });
+
+            if frames.is_some_and(|frames| app.emulator.frame() >= frames) {
+                break;
+            }
         }
 
         if let Some(args) = e.button_args() {
             app.input(&args);
         }
+
+        if let Some(args) = e.controller_axis_args() {
+            app.axis_input(&args);
+        }
     }
 }
@@ -0,0 +1,639 @@
+use crate::emulator::{Emulator, Region};
+use crate::joypad::JoypadState;
+use bus::ram::RamInit;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The first line of every movie file
+const MAGIC: &str = "sg-movie 1";
+
+/// How often the picture is hashed while recording, in frames
+pub const DEFAULT_HASH_INTERVAL: u64 = 60;
+
+/// The machine state a movie starts from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MovieStart {
+    /// The machine is switched on right before the first frame
+    PowerOn,
+    /// The machine is restored from a state saved with `Emulator::save_state` right
+    /// before the first frame
+    Savestate(Vec<u8>),
+}
+
+/// The input of one emulated frame
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MovieFrame {
+    pub joypads: [JoypadState; 2],
+    /// Whether the pause button of the console was pressed
+    pub pause: bool,
+    /// The hash of the picture at the end of the frame, kept every `hash_interval` frames
+    pub hash: Option<u32>,
+}
+
+/// The joypad and pause input of every frame of a run, enough to reproduce it exactly
+///
+/// Movies are stored as text:
+/// ```text
+/// sg-movie 1
+/// rom-crc 1a2b3c4d
+/// region ntsc
+/// ram-init zero
+/// start power-on
+/// hash-interval 60
+/// frames
+/// 00 00
+/// 00 00 pause
+/// 11 00 89abcdef
+/// ```
+/// Every line after `frames` holds the state of both joypads for one frame (a set bit
+/// is a pressed button, see `JoypadState`), followed by `pause` on frames the pause button
+/// was pressed in and by the frame hash on frames that have one.  A movie that starts from a save state has `start savestate` followed by
+/// the state in hex instead of `start power-on`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    pub rom_crc: u32,
+    pub region: Region,
+    pub ram_init: RamInit,
+    pub start: MovieStart,
+    pub hash_interval: u64,
+    pub frames: Vec<MovieFrame>,
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    /// The movie file could not be understood
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// The emulator does not run the game the movie was recorded on, or not in the same way
+    Mismatch {
+        what: &'static str,
+        expected: String,
+        actual: String,
+    },
+    /// The picture differs from the one that was recorded
+    Desync {
+        frame: u64,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(err) => write!(f, "{}", err),
+            MovieError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MovieError::Mismatch {
+                what,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "the movie was recorded with {} {}, but the emulator has {}",
+                what, expected, actual
+            ),
+            MovieError::Desync {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "desync at frame {}: expected hash {:08x}, got {:08x}",
+                frame, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> MovieError {
+        MovieError::Io(err)
+    }
+}
+
+impl Movie {
+    /// An empty movie that starts at the power on of the given emulator
+    pub fn new(emulator: &Emulator, hash_interval: u64) -> Movie {
+        Movie {
+            rom_crc: emulator.rom_crc(),
+            region: emulator.options().region,
+            ram_init: emulator.options().ram_init,
+            start: MovieStart::PowerOn,
+            hash_interval,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, MovieError> {
+        Movie::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+
+    pub fn write(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{}", MAGIC)?;
+        writeln!(out, "rom-crc {:08x}", self.rom_crc)?;
+        writeln!(out, "region {}", region_name(self.region))?;
+        writeln!(out, "ram-init {}", ram_init_name(self.ram_init))?;
+        match &self.start {
+            MovieStart::PowerOn => writeln!(out, "start power-on")?,
+            MovieStart::Savestate(state) => writeln!(out, "start savestate {}", hex(state))?,
+        }
+        writeln!(out, "hash-interval {}", self.hash_interval)?;
+        writeln!(out, "frames")?;
+        for frame in &self.frames {
+            let [p1, p2] = frame.joypads;
+            write!(out, "{:02x} {:02x}", p1.bits(), p2.bits())?;
+            if frame.pause {
+                write!(out, " pause")?;
+            }
+            if let Some(hash) = frame.hash {
+                write!(out, " {:08x}", hash)?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read(input: impl BufRead) -> Result<Movie, MovieError> {
+        let mut lines = input.lines().enumerate();
+        let mut next_line = || -> Result<(usize, String), MovieError> {
+            match lines.next() {
+                Some((index, line)) => Ok((index + 1, line?)),
+                None => Err(MovieError::Parse {
+                    line: 0,
+                    message: "unexpected end of file".to_string(),
+                }),
+            }
+        };
+
+        let (line, magic) = next_line()?;
+        if magic.trim() != MAGIC {
+            return Err(parse_error(line, "not a movie file"));
+        }
+
+        let mut header = |key: &str| -> Result<(usize, String), MovieError> {
+            let (line, text) = next_line()?;
+            match text.trim().splitn(2, ' ').collect::<Vec<_>>().as_slice() {
+                [name, value] if *name == key => Ok((line, value.trim().to_string())),
+                _ => Err(parse_error(line, &format!("expected {}", key))),
+            }
+        };
+
+        let (line, value) = header("rom-crc")?;
+        let rom_crc = u32::from_str_radix(&value, 16).map_err(|_| parse_error(line, "bad CRC"))?;
+        let (line, value) = header("region")?;
+        let region = parse_region(&value).ok_or_else(|| parse_error(line, "unknown region"))?;
+        let (line, value) = header("ram-init")?;
+        let ram_init =
+            parse_ram_init(&value).ok_or_else(|| parse_error(line, "unknown ram init"))?;
+        let (line, value) = header("start")?;
+        let start = parse_start(&value).ok_or_else(|| parse_error(line, "unknown start"))?;
+        let (line, value) = header("hash-interval")?;
+        let hash_interval = value
+            .parse()
+            .map_err(|_| parse_error(line, "bad hash interval"))?;
+
+        let (line, text) = next_line()?;
+        if text.trim() != "frames" {
+            return Err(parse_error(line, "expected frames"));
+        }
+
+        let mut frames = Vec::new();
+        for (index, text) in lines {
+            let line = index + 1;
+            let text = text?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            frames.push(parse_frame(&text).ok_or_else(|| parse_error(line, "bad frame"))?);
+        }
+
+        Ok(Movie {
+            rom_crc,
+            region,
+            ram_init,
+            start,
+            hash_interval,
+            frames,
+        })
+    }
+
+    /// Make sure the emulator runs the same game in the same way as the recording
+    fn check(&self, emulator: &Emulator) -> Result<(), MovieError> {
+        let mismatch = |what, expected: String, actual: String| {
+            if expected == actual {
+                Ok(())
+            } else {
+                Err(MovieError::Mismatch {
+                    what,
+                    expected,
+                    actual,
+                })
+            }
+        };
+
+        mismatch(
+            "ROM CRC",
+            format!("{:08x}", self.rom_crc),
+            format!("{:08x}", emulator.rom_crc()),
+        )?;
+        mismatch(
+            "region",
+            region_name(self.region).to_string(),
+            region_name(emulator.options().region).to_string(),
+        )?;
+        mismatch(
+            "ram init",
+            ram_init_name(self.ram_init),
+            ram_init_name(emulator.options().ram_init),
+        )?;
+        match self.start {
+            MovieStart::PowerOn => {
+                mismatch("start frame", "0".to_string(), emulator.frame().to_string())
+            }
+            // the emulator is restored from the state, wherever it is now
+            MovieStart::Savestate(_) => Ok(()),
+        }
+    }
+}
+
+/// Records the joypads and the pause button of an emulator into a movie
+pub struct MovieRecorder {
+    movie: Movie,
+    /// The frame of the emulator the movie starts after
+    first_frame: u64,
+}
+
+impl MovieRecorder {
+    /// Start recording an emulator that has just been switched on
+    pub fn new(emulator: &Emulator, hash_interval: u64) -> MovieRecorder {
+        assert_eq!(0, emulator.frame(), "Movies have to start at power on");
+
+        MovieRecorder {
+            movie: Movie::new(emulator, hash_interval),
+            first_frame: 0,
+        }
+    }
+
+    /// Start recording an emulator from where it is now, saving its state in the movie
+    pub fn from_state(emulator: &Emulator, hash_interval: u64) -> MovieRecorder {
+        let mut movie = Movie::new(emulator, hash_interval);
+        movie.start = MovieStart::Savestate(emulator.save_state());
+
+        MovieRecorder {
+            movie,
+            first_frame: emulator.frame(),
+        }
+    }
+
+    /// Record the frame the emulator has just finished
+    ///
+    /// This has to be called after every emulated frame.  Nothing is recorded when the
+    /// emulator did not advance.
+    pub fn record(&mut self, emulator: &Emulator) {
+        let frame = emulator.frame().saturating_sub(self.first_frame);
+        if frame <= self.movie.frames.len() as u64 {
+            return;
+        }
+
+        // an interval of 0 never hashes, as frame is never 0 here
+        self.movie.frames.push(MovieFrame {
+            joypads: [emulator.joypad(0), emulator.joypad(1)],
+            pause: emulator.pause_pressed(),
+            hash: if frame.is_multiple_of(self.movie.hash_interval) {
+                Some(emulator.frame_hash())
+            } else {
+                None
+            },
+        });
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Plays a movie back on an emulator, frame by frame
+pub struct MoviePlayer {
+    movie: Movie,
+    next: usize,
+}
+
+impl MoviePlayer {
+    /// Start playing a movie on an emulator that was switched on like the recorded one
+    ///
+    /// A movie that starts from a save state restores it on the emulator.
+    pub fn new(movie: Movie, emulator: &mut Emulator) -> Result<MoviePlayer, MovieError> {
+        movie.check(emulator)?;
+        if let MovieStart::Savestate(state) = &movie.start {
+            emulator.load_state(state);
+        }
+
+        Ok(MoviePlayer { movie, next: 0 })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.movie.frames.len()
+    }
+
+    /// Emulate the next frame of the movie with the recorded input
+    ///
+    /// Returns false once the movie is over, and an error when the picture no longer
+    /// matches the recording.
+    pub fn play_frame(&mut self, emulator: &mut Emulator) -> Result<bool, MovieError> {
+        let frame = match self.movie.frames.get(self.next) {
+            Some(frame) => *frame,
+            None => return Ok(false),
+        };
+
+        emulator.set_joypad(0, frame.joypads[0]);
+        emulator.set_joypad(1, frame.joypads[1]);
+        if frame.pause {
+            emulator.press_pause();
+        }
+        emulator.run_frame();
+        self.next += 1;
+
+        if let Some(expected) = frame.hash {
+            let actual = emulator.frame_hash();
+            if actual != expected {
+                return Err(MovieError::Desync {
+                    frame: emulator.frame(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Play all remaining frames
+    pub fn play_to_end(&mut self, emulator: &mut Emulator) -> Result<(), MovieError> {
+        while self.play_frame(emulator)? {}
+
+        Ok(())
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> MovieError {
+    MovieError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+/// The bytes as two hex digits each
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+fn parse_start(text: &str) -> Option<MovieStart> {
+    match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["power-on"] => Some(MovieStart::PowerOn),
+        ["savestate", state] => parse_hex(state).map(MovieStart::Savestate),
+        _ => None,
+    }
+}
+
+fn region_name(region: Region) -> &'static str {
+    match region {
+        Region::Ntsc => "ntsc",
+        Region::Pal => "pal",
+    }
+}
+
+fn parse_region(name: &str) -> Option<Region> {
+    match name {
+        "ntsc" => Some(Region::Ntsc),
+        "pal" => Some(Region::Pal),
+        _ => None,
+    }
+}
+
+fn ram_init_name(init: RamInit) -> String {
+    match init {
+        RamInit::Zero => "zero".to_string(),
+        RamInit::Fill(val) => format!("fill {:02x}", val),
+        RamInit::Random(seed) => format!("random {}", seed),
+    }
+}
+
+fn parse_ram_init(name: &str) -> Option<RamInit> {
+    match name.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["zero"] => Some(RamInit::Zero),
+        ["fill", val] => u8::from_str_radix(val, 16).ok().map(RamInit::Fill),
+        ["random", seed] => seed.parse().ok().map(RamInit::Random),
+        _ => None,
+    }
+}
+
+fn parse_frame(text: &str) -> Option<MovieFrame> {
+    let fields = text.split_whitespace().collect::<Vec<_>>();
+    let joypad = |text: &str| {
+        u8::from_str_radix(text, 16)
+            .ok()
+            .and_then(JoypadState::from_bits)
+    };
+
+    let (p1, p2, mut rest) = match fields.as_slice() {
+        [p1, p2, rest @ ..] => (p1, p2, rest),
+        _ => return None,
+    };
+    let pause = rest.first() == Some(&"pause");
+    if pause {
+        rest = &rest[1..];
+    }
+    let hash = match rest {
+        [] => None,
+        [hash] => Some(u32::from_str_radix(hash, 16).ok()?),
+        _ => return None,
+    };
+
+    Some(MovieFrame {
+        joypads: [joypad(p1)?, joypad(p2)?],
+        pause,
+        hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn rom() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/hello_world2.sg")
+    }
+
+    #[test]
+    fn test_movie_round_trip() {
+        let movie = Movie {
+            rom_crc: 0x1234_abcd,
+            region: Region::Pal,
+            ram_init: RamInit::Random(42),
+            start: MovieStart::PowerOn,
+            hash_interval: 2,
+            frames: vec![
+                MovieFrame {
+                    joypads: [JoypadState::UP, JoypadState::empty()],
+                    pause: false,
+                    hash: None,
+                },
+                MovieFrame {
+                    joypads: [JoypadState::empty(), JoypadState::empty()],
+                    pause: true,
+                    hash: None,
+                },
+                MovieFrame {
+                    joypads: [JoypadState::BUTTON1, JoypadState::LEFT],
+                    pause: true,
+                    hash: Some(0xdead_beef),
+                },
+            ],
+        };
+
+        let mut text = Vec::new();
+        movie.write(&mut text).unwrap();
+        assert_eq!(movie, Movie::read(text.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let mut emulator = Emulator::new(&rom());
+        let mut recorder = MovieRecorder::new(&emulator, 10);
+        for frame in 0..30 {
+            if frame == 12 {
+                emulator.set_joypad(0, JoypadState::RIGHT | JoypadState::BUTTON1);
+            }
+            emulator.run_frame();
+            recorder.record(&emulator);
+        }
+        let movie = recorder.finish();
+        assert_eq!(30, movie.frames.len());
+        assert_eq!(3, movie.frames.iter().filter(|f| f.hash.is_some()).count());
+
+        let mut emulator = Emulator::new(&rom());
+        let mut player = MoviePlayer::new(movie.clone(), &mut emulator).unwrap();
+        player.play_to_end(&mut emulator).unwrap();
+        assert!(player.is_finished());
+        assert_eq!(30, emulator.frame());
+
+        let mut broken = movie;
+        broken.frames[19].hash = broken.frames[19].hash.map(|hash| !hash);
+        let mut emulator = Emulator::new(&rom());
+        let mut player = MoviePlayer::new(broken, &mut emulator).unwrap();
+        match player.play_to_end(&mut emulator) {
+            Err(MovieError::Desync { frame, .. }) => assert_eq!(20, frame),
+            other => panic!("expected a desync, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_savestate_start() {
+        let mut emulator = Emulator::new(&rom());
+        for _ in 0..20 {
+            emulator.run_frame();
+        }
+        let mut recorder = MovieRecorder::from_state(&emulator, 5);
+        for frame in 0..10 {
+            emulator.set_joypad(0, JoypadState::from_bits_truncate(frame));
+            emulator.run_frame();
+            recorder.record(&emulator);
+        }
+        let movie = recorder.finish();
+        assert_eq!(10, movie.frames.len());
+        assert_eq!(2, movie.frames.iter().filter(|f| f.hash.is_some()).count());
+
+        let mut text = Vec::new();
+        movie.write(&mut text).unwrap();
+        let movie = Movie::read(text.as_slice()).unwrap();
+        assert!(matches!(movie.start, MovieStart::Savestate(_)));
+
+        // the movie plays back from the state, not from where the emulator is
+        let mut emulator = Emulator::new(&rom());
+        let mut player = MoviePlayer::new(movie, &mut emulator).unwrap();
+        assert_eq!(20, emulator.frame());
+        player.play_to_end(&mut emulator).unwrap();
+        assert_eq!(30, emulator.frame());
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut emulator = Emulator::new(&rom());
+        let mut recorder = MovieRecorder::new(&emulator, 0);
+        for frame in 0..10 {
+            if frame == 4 {
+                emulator.press_pause();
+            }
+            emulator.run_frame();
+            recorder.record(&emulator);
+        }
+        let movie = recorder.finish();
+        let paused = movie
+            .frames
+            .iter()
+            .map(|frame| frame.pause)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![false, false, false, false, true], paused[..5].to_vec());
+        assert!(!paused[5..].contains(&true));
+
+        let mut text = Vec::new();
+        movie.write(&mut text).unwrap();
+        let movie = Movie::read(text.as_slice()).unwrap();
+
+        // the game takes the NMI at the same point of the playback
+        let mut played = Emulator::new(&rom());
+        MoviePlayer::new(movie.clone(), &mut played)
+            .unwrap()
+            .play_to_end(&mut played)
+            .unwrap();
+        assert_eq!(emulator.save_state(), played.save_state());
+
+        let mut unpaused = movie;
+        unpaused.frames[4].pause = false;
+        let mut played = Emulator::new(&rom());
+        MoviePlayer::new(unpaused, &mut played)
+            .unwrap()
+            .play_to_end(&mut played)
+            .unwrap();
+        assert_ne!(emulator.save_state(), played.save_state());
+    }
+
+    #[test]
+    fn test_wrong_rom() {
+        let mut movie = Movie::new(&Emulator::new(&rom()), DEFAULT_HASH_INTERVAL);
+        movie.rom_crc = !movie.rom_crc;
+
+        let result = MoviePlayer::new(movie, &mut Emulator::new(&rom()));
+        assert!(matches!(result, Err(MovieError::Mismatch { .. })));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::emulator::Region;
+use std::time::{Duration, Instant};
+
+/// The speed of fast-forward, relative to the real machine
+pub const FAST_FORWARD_SPEED: f64 = 4.0;
+
+/// The speed of slow motion, relative to the real machine
+pub const SLOW_MOTION_SPEED: f64 = 0.25;
+
+/// How far the emulation may fall behind the host before the time is given up on, so a
+/// stall (e.g. moving the window) does not make the game race to catch up afterwards
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Keeps the emulation at the speed of the real machine, independent of how often the host
+/// draws
+///
+/// The pacer compares the T-states that were emulated with the wall clock time that passed,
+/// scaled by the speed.  On every host frame the frontend emulates frames as long as
+/// `frame_due` says the emulation is behind, and reports their T-states with `ran`.  On a
+/// 144 Hz monitor most host frames then emulate nothing, and a slow host emulates two.
+pub struct FramePacer {
+    /// The T-states per second of the real machine
+    clock_rate: f64,
+    /// The T-states of a frame of the VDP
+    cycles_per_frame: u64,
+    speed: f64,
+    /// The T-states the emulation is behind the wall clock, negative when it is ahead
+    owed: f64,
+    last_tick: Option<Instant>,
+    paused: bool,
+    /// The frames to run while paused
+    steps: u32,
+}
+
+impl FramePacer {
+    pub fn new(region: Region) -> FramePacer {
+        FramePacer {
+            clock_rate: region.clock_rate() as f64,
+            cycles_per_frame: region.cycles_per_frame() as u64,
+            speed: 1.0,
+            owed: 0.0,
+            last_tick: None,
+            paused: false,
+            steps: 0,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Run faster (> 1.0) or slower (< 1.0) than the real machine
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(speed > 0.0, "The speed must be positive");
+        self.speed = speed;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop or continue the emulation.  The time spent paused is not made up for.
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused != self.paused {
+            self.paused = paused;
+            self.steps = 0;
+            self.owed = 0.0;
+        }
+    }
+
+    /// Run one more frame while paused
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.steps += 1;
+        }
+    }
+
+    /// Add the wall clock time that passed since the last tick
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(last_tick) = self.last_tick {
+            let elapsed = now.saturating_duration_since(last_tick);
+            if !self.paused {
+                self.owed += elapsed.as_secs_f64() * self.clock_rate * self.speed;
+            }
+        }
+        self.last_tick = Some(now);
+
+        let max_owed = MAX_LAG.as_secs_f64() * self.clock_rate * self.speed;
+        self.owed = self.owed.min(max_owed.max(self.cycles_per_frame as f64));
+    }
+
+    /// Whether the emulation is behind and another frame should be emulated
+    pub fn frame_due(&self) -> bool {
+        if self.paused {
+            self.steps > 0
+        } else {
+            self.owed > 0.0
+        }
+    }
+
+    /// Account for a frame that was emulated
+    ///
+    /// A frame of 0 T-states, e.g. one that was rewound rather than emulated, counts as a
+    /// frame of the VDP.
+    pub fn ran(&mut self, cycles: u64) {
+        if self.paused {
+            self.steps = self.steps.saturating_sub(1);
+        } else if cycles == 0 {
+            self.owed -= self.cycles_per_frame as f64;
+        } else {
+            self.owed -= cycles as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tick the pacer `hz` times for a second, emulating whole VDP frames
+    fn frames_in_a_second(pacer: &mut FramePacer, hz: u32) -> u32 {
+        let start = Instant::now();
+        pacer.tick(start);
+        let mut frames = 0;
+        for tick in 1..=hz {
+            pacer.tick(start + Duration::from_secs(1) * tick / hz);
+            while pacer.frame_due() {
+                pacer.ran(pacer.cycles_per_frame);
+                frames += 1;
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn test_host_rate() {
+        // the game runs at the speed of the machine whatever the refresh rate of the monitor
+        for &hz in [30, 60, 144, 240].iter() {
+            let mut pacer = FramePacer::new(Region::Ntsc);
+            let frames = frames_in_a_second(&mut pacer, hz);
+            assert!((59..=61).contains(&frames), "{} Hz: {} frames", hz, frames);
+        }
+
+        let mut pacer = FramePacer::new(Region::Pal);
+        let frames = frames_in_a_second(&mut pacer, 60);
+        assert!((49..=51).contains(&frames), "{}", frames);
+    }
+
+    #[test]
+    fn test_speed() {
+        let mut pacer = FramePacer::new(Region::Ntsc);
+        pacer.set_speed(FAST_FORWARD_SPEED);
+        let frames = frames_in_a_second(&mut pacer, 60);
+        assert!((238..=241).contains(&frames), "{}", frames);
+
+        pacer.set_speed(SLOW_MOTION_SPEED);
+        let frames = frames_in_a_second(&mut pacer, 60);
+        assert!((14..=16).contains(&frames), "{}", frames);
+    }
+
+    #[test]
+    fn test_stall() {
+        let mut pacer = FramePacer::new(Region::Ntsc);
+        let start = Instant::now();
+        pacer.tick(start);
+        pacer.tick(start + Duration::from_secs(2));
+
+        let mut frames = 0;
+        while pacer.frame_due() {
+            pacer.ran(pacer.cycles_per_frame);
+            frames += 1;
+        }
+        assert!(frames <= 7, "{}", frames);
+    }
+
+    #[test]
+    fn test_frame_advance() {
+        let mut pacer = FramePacer::new(Region::Ntsc);
+        pacer.set_paused(true);
+        let start = Instant::now();
+        pacer.tick(start);
+        pacer.tick(start + Duration::from_secs(1));
+        assert!(!pacer.frame_due());
+
+        pacer.advance_frame();
+        pacer.advance_frame();
+        assert!(pacer.frame_due());
+        pacer.ran(pacer.cycles_per_frame);
+        assert!(pacer.frame_due());
+        pacer.ran(pacer.cycles_per_frame);
+        assert!(!pacer.frame_due());
+
+        // the time spent paused is not made up for
+        pacer.set_paused(false);
+        pacer.tick(start + Duration::from_secs(1) + Duration::from_millis(10));
+        pacer.ran(pacer.cycles_per_frame);
+        assert!(!pacer.frame_due());
+    }
+}
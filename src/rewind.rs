@@ -0,0 +1,346 @@
+use crate::emulator::Emulator;
+use crate::joypad::JoypadState;
+use std::collections::VecDeque;
+
+/// How often a snapshot is taken, in frames
+pub const DEFAULT_INTERVAL: u64 = 10;
+
+/// How much memory the rewind buffer may use, in bytes
+pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;
+
+/// A snapshot that is stored as the difference to the next newer snapshot
+struct Delta {
+    frame: u64,
+    delta: Vec<u8>,
+}
+
+/// A ring buffer of machine snapshots for rewinding the game frame by frame
+///
+/// A snapshot is taken every `interval` frames.  Only the newest snapshot is kept in
+/// full, every older one is stored as the (run length encoded) difference to the one
+/// after it, so little memory is used while the game does not change much.  When the
+/// buffer grows over its memory budget the oldest snapshots are dropped.
+///
+/// The joypads of every frame since the oldest snapshot are kept as well, so any of
+/// those frames can be reached exactly by restoring the snapshot before it and emulating
+/// forward with the same input.
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+    /// The frame and state of the newest snapshot
+    newest: Option<(u64, Vec<u8>)>,
+    /// The older snapshots, oldest first
+    deltas: VecDeque<Delta>,
+    /// The joypads of the frames after the oldest snapshot, oldest first
+    inputs: VecDeque<[JoypadState; 2]>,
+    /// The frame of `inputs[0]`
+    first_input: u64,
+    size: usize,
+}
+
+impl Rewind {
+    pub fn new(interval: u64, budget: usize) -> Rewind {
+        assert!(interval > 0, "The snapshot interval can not be 0");
+
+        Rewind {
+            interval,
+            budget,
+            newest: None,
+            deltas: VecDeque::new(),
+            inputs: VecDeque::new(),
+            first_input: 0,
+            size: 0,
+        }
+    }
+
+    /// The oldest frame that can be rewound to
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.deltas
+            .front()
+            .map(|delta| delta.frame)
+            .or_else(|| self.newest.as_ref().map(|(frame, _)| *frame))
+    }
+
+    /// The number of bytes used by the snapshots and inputs
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Record the frame the emulator has just finished
+    ///
+    /// This has to be called after every emulated frame.  Frames that were already
+    /// recorded (e.g. because the emulator was paused) are skipped.
+    pub fn record(&mut self, emulator: &Emulator) {
+        let frame = emulator.frame();
+        if self.newest.is_some() && frame < self.first_input + self.inputs.len() as u64 {
+            return;
+        }
+
+        if self.newest.is_some() {
+            self.inputs
+                .push_back([emulator.joypad(0), emulator.joypad(1)]);
+            self.size += 2;
+        }
+
+        if self.newest.is_none() || frame.is_multiple_of(self.interval) {
+            self.snapshot(frame, emulator.save_state());
+        }
+
+        while self.size > self.budget && !self.deltas.is_empty() {
+            self.drop_oldest();
+        }
+    }
+
+    /// Go back `frames` frames, as far as the buffer allows
+    ///
+    /// Returns false if nothing could be rewound.
+    pub fn step_back(&mut self, emulator: &mut Emulator, frames: u64) -> bool {
+        let oldest = match self.oldest_frame() {
+            Some(oldest) => oldest,
+            None => return false,
+        };
+
+        let target = emulator.frame().saturating_sub(frames).max(oldest);
+        target < emulator.frame() && self.rewind_to(emulator, target)
+    }
+
+    /// Bring the emulator back to the end of `frame`
+    ///
+    /// The emulator restores the newest snapshot before the frame and emulates forward,
+    /// so the picture of `frame` is redrawn (except for the oldest frame, which has nothing
+    /// before it).  Everything recorded after `frame` is forgotten.  Returns false if the
+    /// frame is not in the buffer.
+    pub fn rewind_to(&mut self, emulator: &mut Emulator, frame: u64) -> bool {
+        let oldest = match self.oldest_frame() {
+            Some(oldest) => oldest,
+            None => return false,
+        };
+        if frame < oldest || frame >= self.first_input + self.inputs.len() as u64 {
+            return false;
+        }
+
+        // the snapshot at the frame itself only has the picture of the frame before it
+        let snapshot_frame = if frame > oldest { frame - 1 } else { frame };
+        let (start, state) = self.restore(snapshot_frame);
+
+        // the inputs of the frames after the snapshot, up to and including the frame
+        let first = (start + 1 - self.first_input) as usize;
+        let end = (frame + 1 - self.first_input) as usize;
+        let inputs = self.inputs.range(first..end).copied().collect::<Vec<_>>();
+        self.truncate(start, state);
+
+        emulator.load_state(&self.newest.as_ref().unwrap().1);
+        for [p1, p2] in inputs {
+            emulator.set_joypad(0, p1);
+            emulator.set_joypad(1, p2);
+            emulator.run_frame();
+            self.record(emulator);
+        }
+
+        true
+    }
+
+    /// Take a snapshot of the given state, turning the current newest snapshot into a delta
+    fn snapshot(&mut self, frame: u64, state: Vec<u8>) {
+        if let Some((newest_frame, newest)) = self.newest.take() {
+            let delta = encode_delta(&newest, &state);
+            self.size += delta.len();
+            self.size -= newest.len();
+            self.deltas.push_back(Delta {
+                frame: newest_frame,
+                delta,
+            });
+        } else {
+            self.first_input = frame + 1;
+        }
+
+        self.size += state.len();
+        self.newest = Some((frame, state));
+    }
+
+    fn drop_oldest(&mut self) {
+        if let Some(oldest) = self.deltas.pop_front() {
+            self.size -= oldest.delta.len();
+
+            let next = self.oldest_frame().unwrap();
+            while self.first_input <= next {
+                self.inputs.pop_front();
+                self.first_input += 1;
+                self.size -= 2;
+            }
+        }
+    }
+
+    /// Rebuild the state of the newest snapshot at or before `frame`
+    fn restore(&self, frame: u64) -> (u64, Vec<u8>) {
+        let (mut start, state) = self.newest.as_ref().unwrap();
+        let mut state = state.clone();
+        for delta in self.deltas.iter().rev() {
+            if start <= frame {
+                break;
+            }
+            apply_delta(&delta.delta, &mut state);
+            start = delta.frame;
+        }
+
+        (start, state)
+    }
+
+    /// Forget everything after the snapshot at `frame` and make it the newest one
+    fn truncate(&mut self, frame: u64, state: Vec<u8>) {
+        while self.deltas.back().is_some_and(|delta| delta.frame >= frame) {
+            let delta = self.deltas.pop_back().unwrap();
+            self.size -= delta.delta.len();
+        }
+
+        let inputs = (frame + 1 - self.first_input) as usize;
+        self.size -= 2 * (self.inputs.len() - inputs);
+        self.inputs.truncate(inputs);
+
+        let (_, newest) = self.newest.take().unwrap();
+        self.size -= newest.len();
+        self.size += state.len();
+        self.newest = Some((frame, state));
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Rewind {
+        Rewind::new(DEFAULT_INTERVAL, DEFAULT_BUDGET)
+    }
+}
+
+/// The longest run that is stored in one piece of a delta
+const MAX_RUN: usize = u16::MAX as usize;
+
+/// Encode the difference of two states of the same size
+///
+/// The delta is a list of (unchanged bytes, changed bytes) runs.  Each run starts with the
+/// two lengths as little endian u16s, followed by the changed bytes xor-ed together.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    assert_eq!(old.len(), new.len(), "Snapshots have to be the same size");
+
+    let mut delta = Vec::new();
+    let mut pos = 0;
+    while pos < old.len() {
+        let same = old[pos..]
+            .iter()
+            .zip(&new[pos..])
+            .take(MAX_RUN)
+            .take_while(|(old, new)| old == new)
+            .count();
+        pos += same;
+
+        let changed = old[pos..]
+            .iter()
+            .zip(&new[pos..])
+            .take(MAX_RUN)
+            .take_while(|(old, new)| old != new)
+            .count();
+
+        delta.extend_from_slice(&(same as u16).to_le_bytes());
+        delta.extend_from_slice(&(changed as u16).to_le_bytes());
+        delta.extend(
+            old[pos..pos + changed]
+                .iter()
+                .zip(&new[pos..pos + changed])
+                .map(|(old, new)| old ^ new),
+        );
+        pos += changed;
+    }
+
+    delta
+}
+
+/// Apply a delta to one of its states, giving the other one
+fn apply_delta(delta: &[u8], state: &mut [u8]) {
+    let mut pos = 0;
+    let mut delta = delta;
+    while !delta.is_empty() {
+        let same = u16::from_le_bytes([delta[0], delta[1]]) as usize;
+        let changed = u16::from_le_bytes([delta[2], delta[3]]) as usize;
+        pos += same;
+        for (byte, diff) in state[pos..pos + changed].iter_mut().zip(&delta[4..]) {
+            *byte ^= diff;
+        }
+        pos += changed;
+        delta = &delta[4 + changed..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn emulator() -> Emulator {
+        Emulator::new(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/hello_world2.sg"))
+    }
+
+    #[test]
+    fn test_delta() {
+        let old = (0..200_000).map(|i| (i / 7) as u8).collect::<Vec<_>>();
+        let mut new = old.clone();
+        new[3] ^= 0xFF;
+        new[100_000..100_010].iter_mut().for_each(|byte| *byte = 0);
+
+        let delta = encode_delta(&old, &new);
+        assert!(delta.len() < 100);
+
+        let mut state = new.clone();
+        apply_delta(&delta, &mut state);
+        assert_eq!(old, state);
+        apply_delta(&delta, &mut state);
+        assert_eq!(new, state);
+    }
+
+    #[test]
+    fn test_rewind_is_frame_accurate() {
+        let mut emulator = emulator();
+        let mut rewind = Rewind::new(4, DEFAULT_BUDGET);
+        rewind.record(&emulator);
+
+        let mut hashes = Vec::new();
+        for frame in 1..=25 {
+            if frame == 6 {
+                emulator.set_joypad(0, JoypadState::BUTTON1);
+            }
+            emulator.run_frame();
+            rewind.record(&emulator);
+            hashes.push((emulator.frame_hash(), emulator.save_state()));
+        }
+
+        assert!(rewind.rewind_to(&mut emulator, 13));
+        assert_eq!(13, emulator.frame());
+        assert_eq!(hashes[12].0, emulator.frame_hash());
+        assert_eq!(hashes[12].1, emulator.save_state());
+
+        assert!(rewind.step_back(&mut emulator, 5));
+        assert_eq!(8, emulator.frame());
+        assert_eq!(hashes[7].1, emulator.save_state());
+
+        // the rewound frames are recorded again
+        emulator.run_frame();
+        rewind.record(&emulator);
+        assert!(rewind.rewind_to(&mut emulator, 2));
+        assert_eq!(hashes[1].1, emulator.save_state());
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut emulator = emulator();
+        let state_size = emulator.save_state().len();
+        let mut rewind = Rewind::new(1, state_size + 1024);
+        rewind.record(&emulator);
+        for _ in 0..50 {
+            emulator.run_frame();
+            rewind.record(&emulator);
+        }
+
+        assert!(rewind.size() <= state_size + 1024);
+        assert!(rewind.oldest_frame().unwrap() > 0);
+        assert!(!rewind.rewind_to(&mut emulator, 0));
+        assert!(rewind.step_back(&mut emulator, 100));
+        assert_eq!(rewind.oldest_frame(), Some(emulator.frame()));
+    }
+}
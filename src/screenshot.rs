@@ -0,0 +1,57 @@
+use crate::emulator::Emulator;
+use image::{Rgb, RgbImage};
+use std::io;
+use std::path::{Path, PathBuf};
+use tms9918::ppu::{HEIGHT, WIDTH};
+
+/// The last complete frame at the VDP's native resolution (256x192)
+///
+/// The canvas of the VDP is zoomed for the window, so only every zoom-th pixel is kept.
+/// Transparent pixels show the black behind the picture, like they do on screen.
+pub fn native_frame(emulator: &Emulator) -> RgbImage {
+    let ppu = emulator.ppu();
+    let frame = ppu.frame();
+    let zoom = (frame.width() / WIDTH).max(1);
+
+    RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let [r, g, b, a] = frame.get_pixel(x * zoom, y * zoom).0;
+        if a == 0 {
+            Rgb([0, 0, 0])
+        } else {
+            Rgb([r, g, b])
+        }
+    })
+}
+
+/// Save the last complete frame as a native resolution PNG
+pub fn save_screenshot(emulator: &Emulator, path: &Path) -> io::Result<()> {
+    native_frame(emulator).save(path)
+}
+
+/// The file a screenshot of the given frame is saved to, in the working directory
+pub fn screenshot_path(frame: u64) -> PathBuf {
+    PathBuf::from(format!("screenshot-{:06}.png", frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_screenshot() {
+        let rom = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/hello_world2.sg");
+        let mut emulator = Emulator::new(&rom);
+        for _ in 0..10 {
+            emulator.run_frame();
+        }
+
+        let frame = native_frame(&emulator);
+        assert_eq!((WIDTH, HEIGHT), frame.dimensions());
+
+        let path = std::env::temp_dir().join(format!("sg-1000-{}.png", std::process::id()));
+        save_screenshot(&emulator, &path).unwrap();
+        let saved = image::open(&path).unwrap().to_rgb();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(frame.into_raw(), saved.into_raw());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::audio::AudioDump;
+use crate::emulator::{frame_rate, Emulator, Region};
+use crate::screenshot::native_frame;
+use image::RgbImage;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tms9918::ppu::{HEIGHT, WIDTH};
+
+/// Writes pictures as an uncompressed YUV4MPEG2 stream, which most video tools can read
+///
+/// The pictures are stored as full resolution 4:4:4 YCbCr, so no colour is lost to
+/// subsampling.
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    plane: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut out: W, frame_rate: (u32, u32)) -> io::Result<Y4mWriter<W>> {
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            WIDTH, HEIGHT, frame_rate.0, frame_rate.1
+        )?;
+
+        Ok(Y4mWriter {
+            out,
+            plane: Vec::with_capacity((WIDTH * HEIGHT) as usize),
+        })
+    }
+
+    /// Write a picture of the native resolution
+    pub fn write_frame(&mut self, frame: &RgbImage) -> io::Result<()> {
+        assert_eq!((WIDTH, HEIGHT), frame.dimensions(), "Wrong picture size");
+
+        self.out.write_all(b"FRAME\n")?;
+        // BT.601 studio range
+        let planes: [fn(f32, f32, f32) -> f32; 3] = [
+            |r, g, b| 16.0 + 0.257 * r + 0.504 * g + 0.098 * b,
+            |r, g, b| 128.0 - 0.148 * r - 0.291 * g + 0.439 * b,
+            |r, g, b| 128.0 + 0.439 * r - 0.368 * g - 0.071 * b,
+        ];
+        for convert in planes.iter() {
+            self.plane.clear();
+            self.plane.extend(frame.pixels().map(|pixel| {
+                let [r, g, b] = pixel.0;
+                convert(r as f32, g as f32, b as f32).round() as u8
+            }));
+            self.out.write_all(&self.plane)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+enum VideoOutput {
+    Y4m(Y4mWriter<BufWriter<File>>),
+    /// Numbered PNGs in a directory
+    Png(PathBuf),
+}
+
+/// Records every frame of the emulator, and optionally its audio, for bug reports and
+/// reviewing movies
+///
+/// A path ending in `.y4m` is written as one YUV4MPEG2 stream, any other path is a
+/// directory that gets a `frame-000000.png` for each frame.  The audio goes to a WAV file
+/// next to it.
+pub struct VideoRecorder {
+    output: VideoOutput,
+    audio: Option<AudioDump>,
+    frames: u64,
+}
+
+impl VideoRecorder {
+    pub fn create(path: &Path, region: Region, with_audio: bool) -> io::Result<VideoRecorder> {
+        let is_y4m = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("y4m"));
+
+        let output = if is_y4m {
+            let out = BufWriter::new(File::create(path)?);
+            VideoOutput::Y4m(Y4mWriter::new(out, frame_rate(region))?)
+        } else {
+            fs::create_dir_all(path)?;
+            VideoOutput::Png(path.to_path_buf())
+        };
+
+        let audio = if with_audio {
+            Some(AudioDump::create(&audio_path(path))?)
+        } else {
+            None
+        };
+
+        Ok(VideoRecorder {
+            output,
+            audio,
+            frames: 0,
+        })
+    }
+
+    /// The number of frames recorded so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Record the frame the emulator has just finished
+    pub fn record(&mut self, emulator: &Emulator) -> io::Result<()> {
+        let frame = native_frame(emulator);
+        match &mut self.output {
+            VideoOutput::Y4m(writer) => writer.write_frame(&frame)?,
+            VideoOutput::Png(dir) => {
+                frame.save(dir.join(format!("frame-{:06}.png", self.frames)))?
+            }
+        }
+
+        if let Some(audio) = &mut self.audio {
+            audio.push(emulator.audio())?;
+        }
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if let VideoOutput::Y4m(writer) = self.output {
+            writer.finish()?;
+        }
+        if let Some(audio) = self.audio {
+            audio.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The WAV file recorded next to a video
+fn audio_path(video: &Path) -> PathBuf {
+    if video.extension().is_some() {
+        video.with_extension("wav")
+    } else {
+        video.join("audio.wav")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_y4m() {
+        let mut writer = Y4mWriter::new(Vec::new(), frame_rate(Region::Ntsc)).unwrap();
+        writer
+            .write_frame(&RgbImage::from_pixel(WIDTH, HEIGHT, Rgb([0, 0, 0])))
+            .unwrap();
+        writer
+            .write_frame(&RgbImage::from_pixel(
+                WIDTH,
+                HEIGHT,
+                Rgb([0xFF, 0xFF, 0xFF]),
+            ))
+            .unwrap();
+        let out = writer.finish().unwrap();
+
+        let header = b"YUV4MPEG2 W256 H192 F3579545:59736 Ip A1:1 C444\n";
+        assert!(out.starts_with(header));
+        let plane = (WIDTH * HEIGHT) as usize;
+        let frame_size = 6 + 3 * plane;
+        assert_eq!(header.len() + 2 * frame_size, out.len());
+
+        let first = &out[header.len()..];
+        assert_eq!(b"FRAME\n", &first[..6]);
+        // black and white in studio range, no colour
+        assert_eq!(16, first[6]);
+        assert_eq!(128, first[6 + plane]);
+        assert_eq!(235, out[header.len() + frame_size + 6]);
+    }
+
+    #[test]
+    fn test_png_sequence() {
+        let rom = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/hello_world2.sg");
+        let mut emulator = Emulator::new(&rom);
+        let dir = std::env::temp_dir().join(format!("sg-1000-video-{}", std::process::id()));
+
+        let mut recorder = VideoRecorder::create(&dir, Region::Ntsc, true).unwrap();
+        for _ in 0..3 {
+            emulator.run_frame();
+            recorder.record(&emulator).unwrap();
+        }
+        assert_eq!(3, recorder.frames());
+        recorder.finish().unwrap();
+
+        let last = image::open(dir.join("frame-000002.png")).unwrap().to_rgb();
+        assert_eq!(native_frame(&emulator).into_raw(), last.into_raw());
+        assert!(dir.join("audio.wav").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}